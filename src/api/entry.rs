@@ -1,8 +1,9 @@
 use crate::err::RulesError;
 use crate::orchestrator::Orchestrator;
+use crate::types::EvaluationResult;
 
-pub fn evaluate() -> Result<(), RulesError> {
-    Orchestrator::run()
+pub fn evaluate() -> Result<EvaluationResult, RulesError> {
+    Ok(Orchestrator::load()?.evaluate())
 }
 
 // TODO: Create base class which takes dir parameter, then all API-exposed methods belong to this class
@@ -1,3 +1,4 @@
+mod test_object;
 mod test_rule;
 mod test_rules_api;
 mod test_tag;
@@ -0,0 +1,329 @@
+use crate::api::write::object::{remove_with_base_dir, rename_lines, write_with_base_dir};
+use crate::err::RulesError;
+use crate::parser::objects;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const TEST_CONFIG_DIR: &str = "src/api/tests/test_config";
+
+// Attribute names and values -- e.g. [("colour", &["red", "blue"])]
+type Attributes<'a> = Vec<(&'a str, &'a [&'a str])>;
+
+fn create_test_tags() -> HashMap<String, Vec<String>> {
+    let mut tags = HashMap::new();
+    tags.insert(
+        "colour".to_string(),
+        vec!["red".to_string(), "blue".to_string(), "green".to_string()],
+    );
+    tags.insert(
+        "size".to_string(),
+        vec!["small".to_string(), "large".to_string()],
+    );
+    tags.insert("doors".to_string(), vec!["integer(2..5)".to_string()]);
+    tags
+}
+
+fn make_object(attributes: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+    attributes
+        .iter()
+        .map(|(name, values)| {
+            (
+                name.to_string(),
+                values.iter().map(|v| v.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+fn setup_test_file(file_name: &str, content: Option<&str>) -> String {
+    let _ = fs::create_dir_all(TEST_CONFIG_DIR);
+
+    let path = format!("{}/{}", TEST_CONFIG_DIR, file_name);
+    match content {
+        Some(content) => fs::write(&path, content).unwrap(),
+        None if Path::new(&path).exists() => fs::remove_file(&path).unwrap(),
+        None => {}
+    }
+    path
+}
+
+fn cleanup_test_file(path: &str) {
+    if Path::new(path).exists() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn write(
+    file_name: &str,
+    obj_type: &str,
+    attributes: &[(&str, &[&str])],
+) -> Result<(), RulesError> {
+    write_with_base_dir(
+        file_name,
+        obj_type.to_string(),
+        make_object(attributes),
+        create_test_tags(),
+        TEST_CONFIG_DIR,
+    )
+}
+
+#[test]
+fn test_write_object_creates_file() {
+    let path = setup_test_file("test_object_create.yaml", None);
+
+    write(
+        "test_object_create",
+        "Cars",
+        &[("Colour", &["Red", "blue"]), ("doors", &["3"])],
+    )
+    .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "objects:\n  cars:\n    - colour: [red, blue]\n      doors: 3\n"
+    );
+
+    let parsed = objects::parse_objects_str(
+        &path,
+        &fs::read_to_string(&path).unwrap(),
+        &create_test_tags(),
+    )
+    .unwrap();
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].obj_type, "cars");
+
+    cleanup_test_file(&path);
+}
+
+#[test]
+fn test_write_object_appends_to_group() {
+    let path = setup_test_file(
+        "test_object_append.yaml",
+        Some(
+            "# Objects\nobjects:\n  shapes:\n    - colour: red # first\n      size:\n        - small\n\n  cars:\n    - doors: 4\n",
+        ),
+    );
+
+    write(
+        "test_object_append.yaml",
+        "shapes",
+        &[("colour", &["green"])],
+    )
+    .unwrap();
+    write("test_object_append.yaml", "trucks", &[("size", &["large"])]).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Objects\nobjects:\n  shapes:\n    - colour: red # first\n      size:\n        - small\n\n    - colour: green\n\n  cars:\n    - doors: 4\n\n  trucks:\n    - size: large\n"
+    );
+
+    cleanup_test_file(&path);
+}
+
+#[test]
+fn test_write_object_merges_existing_object() {
+    let path = setup_test_file(
+        "test_object_merge.yaml",
+        Some(
+            "objects:\n  cars:\n    - colour: [red, blue]\n      size:\n        - small\n    - colour: green\n",
+        ),
+    );
+
+    // Adds the attributes the existing object doesn't have
+    write(
+        "test_object_merge",
+        "cars",
+        &[
+            ("colour", &["blue", "red"]),
+            ("size", &["small"]),
+            ("doors", &["5"]),
+        ],
+    )
+    .unwrap();
+    let expected = "objects:\n  cars:\n    - colour: [red, blue]\n      size:\n        - small\n      doors: 5\n    - colour: green\n";
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+    // Already has it
+    write("test_object_merge", "cars", &[("colour", &["green"])]).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+    // Different values are a different object
+    write("test_object_merge", "cars", &[("colour", &["red"])]).unwrap();
+    let parsed = objects::parse_objects_str(
+        &path,
+        &fs::read_to_string(&path).unwrap(),
+        &create_test_tags(),
+    )
+    .unwrap();
+    assert_eq!(parsed.len(), 3);
+    assert_eq!(parsed[2].attributes["colour"], vec!["red"]);
+
+    cleanup_test_file(&path);
+}
+
+#[test]
+fn test_write_object_rejects_invalid_object() {
+    let path = setup_test_file(
+        "test_object_invalid.yaml",
+        Some("objects:\n  cars:\n    - colour: red\n"),
+    );
+
+    let cases: Vec<(&str, Attributes, &str)> = vec![
+        ("cars", vec![("colour", &["purple"])], "invalid value"),
+        ("cars", vec![("wheels", &["4"])], "invalid attribute"),
+        ("cars", vec![("doors", &["9"])], "above the maximum"),
+        ("cars", vec![], "at least one attribute"),
+        ("my cars", vec![("colour", &["red"])], "Invalid object type"),
+    ];
+
+    for (obj_type, attributes, expected) in cases {
+        match write("test_object_invalid", obj_type, &attributes) {
+            Err(RulesError::ObjectParseError(msg)) => {
+                assert!(
+                    msg.contains(expected),
+                    "'{}' should contain '{}'",
+                    msg,
+                    expected
+                )
+            }
+            other => panic!("Expected ObjectParseError, got {:?}", other),
+        }
+    }
+
+    // Nothing was written
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "objects:\n  cars:\n    - colour: red\n"
+    );
+
+    cleanup_test_file(&path);
+}
+
+#[test]
+fn test_write_object_rejects_invalid_file() {
+    let path = setup_test_file(
+        "test_object_invalid_file.yaml",
+        Some("shapes:\n  - colour: red\n"),
+    );
+
+    let result = write(
+        "test_object_invalid_file",
+        "shapes",
+        &[("colour", &["blue"])],
+    );
+    assert!(matches!(result, Err(RulesError::ObjectParseError(_))));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "shapes:\n  - colour: red\n"
+    );
+
+    cleanup_test_file(&path);
+}
+
+fn remove(
+    file_name: &str,
+    obj_type: &str,
+    attributes: &[(&str, &[&str])],
+) -> Result<(), RulesError> {
+    remove_with_base_dir(
+        file_name,
+        obj_type,
+        &make_object(attributes),
+        create_test_tags(),
+        TEST_CONFIG_DIR,
+    )
+}
+
+#[test]
+fn test_remove_object() {
+    let path = setup_test_file(
+        "test_object_remove.yaml",
+        Some(
+            "# Objects\nobjects:\n  shapes:\n    - colour: red # first\n      size:\n        - small\n\n    # Second\n    - colour: green\n\n    - colour: blue\n\n  cars:\n    - doors: 4\n",
+        ),
+    );
+
+    // Attributes and values must all be the same
+    let partial = remove("test_object_remove", "shapes", &[("colour", &["red"])]);
+    assert!(matches!(partial, Err(RulesError::ObjectParseError(msg)) if msg.contains("not found")));
+
+    remove(
+        "test_object_remove",
+        "Shapes",
+        &[("size", &["small"]), ("colour", &["RED"])],
+    )
+    .unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Objects\nobjects:\n  shapes:\n    # Second\n    - colour: green\n\n    - colour: blue\n\n  cars:\n    - doors: 4\n"
+    );
+
+    remove("test_object_remove", "shapes", &[("colour", &["blue"])]).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Objects\nobjects:\n  shapes:\n    # Second\n    - colour: green\n\n  cars:\n    - doors: 4\n"
+    );
+
+    // The group goes with its last object
+    remove("test_object_remove", "cars", &[("doors", &["4"])]).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Objects\nobjects:\n  shapes:\n    # Second\n    - colour: green\n"
+    );
+
+    let missing = remove("test_object_remove", "trucks", &[("doors", &["4"])]);
+    assert!(matches!(missing, Err(RulesError::ObjectParseError(_))));
+
+    cleanup_test_file(&path);
+}
+
+#[test]
+fn test_rename_object_lines() {
+    let tags = create_test_tags();
+    let content = "objects:\n  colour:\n    - colour: [Red, 'green'] # first\n      size:\n      - small\n        - large\n    - size: small\n      doors: 4\n";
+    let lines: Vec<&str> = content.lines().collect();
+    let rename = |tag_name: &str, tag_value: Option<&str>, new_name: &str| {
+        rename_lines("test.yaml", content, &tags, tag_name, tag_value, new_name)
+            .unwrap()
+            .into_iter()
+            .map(|(i, line)| (lines[i], line))
+            .collect::<Vec<_>>()
+    };
+
+    // Only attributes are renamed, not the object type with the same name
+    assert_eq!(
+        rename("colour", None, "hue"),
+        vec![(lines[2], "    - hue: [Red, 'green'] # first".to_string())]
+    );
+    assert_eq!(
+        rename("colour", Some("green"), "lime"),
+        vec![(lines[2], "    - colour: [Red, 'lime'] # first".to_string())]
+    );
+    assert_eq!(
+        rename("colour", Some("RED"), "crimson"),
+        vec![(
+            lines[2],
+            "    - colour: [crimson, 'green'] # first".to_string()
+        )]
+    );
+
+    // Values of block lists, at any indent, and scalars
+    assert_eq!(
+        rename("size", Some("small"), "tiny"),
+        vec![
+            (lines[4], "      - tiny".to_string()),
+            (lines[6], "    - size: tiny".to_string()),
+        ]
+    );
+    assert_eq!(
+        rename("size", None, "scale"),
+        vec![
+            (lines[3], "      scale:".to_string()),
+            (lines[6], "    - scale: small".to_string()),
+        ]
+    );
+
+    // The file must be valid
+    assert!(rename_lines("test.yaml", "shapes:\n", &tags, "colour", None, "hue").is_err());
+}
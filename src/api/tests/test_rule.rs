@@ -1,4 +1,4 @@
-use crate::api::write::rule::write_with_base_dir;
+use crate::api::write::rule::{remove_with_base_dir, replace_with_base_dir, write_with_base_dir};
 use crate::err::RulesError;
 use std::collections::HashMap;
 use std::fs;
@@ -108,7 +108,7 @@ fn test_write_rule_appends_to_existing_file() {
 
     write_with_base_dir(file_name, "-size = large", tags.clone(), TEST_CONFIG_DIR).unwrap();
 
-    let content = fs::read_to_string(&format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
+    let content = fs::read_to_string(format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
     assert!(content.contains("-colour = red"));
     assert!(content.contains("-size = large"));
 
@@ -126,8 +126,8 @@ fn test_write_rule_prevents_duplicates() {
 
     let result = write_with_base_dir(file_name, "-colour = red", tags.clone(), TEST_CONFIG_DIR);
     assert!(result.is_err());
-    if let Err(RulesError::RuleParseError(msg)) = result {
-        assert!(msg.contains("already exists"));
+    if let Err(RulesError::RuleParseError(e)) = result {
+        assert!(e.message.contains("already exists"));
     } else {
         panic!("Expected RuleParseError about duplicate");
     }
@@ -244,3 +244,96 @@ fn test_write_rule_comma_with_different_operators() {
 
     cleanup_test_file(file_name);
 }
+
+#[test]
+fn test_remove_rule_keeps_comments_and_order() {
+    let file_name = "test_remove.rules";
+    setup_and_cleanup_test_file(file_name);
+    let path = format!("{}/{}", TEST_CONFIG_DIR, file_name);
+    fs::write(
+        &path,
+        "# Colours
+- colour = red
+-Colour=BLUE & size = large
+
+# Sizes
+- size = small
+",
+    )
+    .unwrap();
+
+    // Compared in canonical form
+    remove_with_base_dir(file_name, "- colour = blue & size = large", TEST_CONFIG_DIR).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Colours\n- colour = red\n\n# Sizes\n- size = small\n"
+    );
+
+    let result = remove_with_base_dir(file_name, "- colour = green", TEST_CONFIG_DIR);
+    assert!(
+        matches!(result, Err(RulesError::RuleParseError(e)) if e.message.contains("not found"))
+    );
+
+    cleanup_test_file(file_name);
+}
+
+#[test]
+fn test_replace_rule_in_place() {
+    let file_name = "test_replace.rules";
+    setup_and_cleanup_test_file(file_name);
+    let path = format!("{}/{}", TEST_CONFIG_DIR, file_name);
+    fs::write(&path, "# Rules\n- colour = red\n- size = small\n").unwrap();
+
+    let tags = create_test_tags();
+    replace_with_base_dir(
+        file_name,
+        "-colour=red",
+        "- colour = red, blue",
+        tags.clone(),
+        TEST_CONFIG_DIR,
+    )
+    .unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Rules\n- colour = red, blue\n- size = small\n"
+    );
+
+    // The new rule must be valid, and not already in the file
+    let invalid = replace_with_base_dir(
+        file_name,
+        "- size = small",
+        "- colour = purple",
+        tags.clone(),
+        TEST_CONFIG_DIR,
+    );
+    assert!(invalid.is_err());
+
+    let duplicate = replace_with_base_dir(
+        file_name,
+        "- size = small",
+        "- colour = red, blue",
+        tags.clone(),
+        TEST_CONFIG_DIR,
+    );
+    assert!(
+        matches!(duplicate, Err(RulesError::RuleParseError(e)) if e.message.contains("already exists"))
+    );
+
+    let missing = replace_with_base_dir(
+        file_name,
+        "- size = large",
+        "- size = medium",
+        tags,
+        TEST_CONFIG_DIR,
+    );
+    assert!(
+        matches!(missing, Err(RulesError::RuleParseError(e)) if e.message.contains("not found"))
+    );
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Rules\n- colour = red, blue\n- size = small\n"
+    );
+
+    cleanup_test_file(file_name);
+}
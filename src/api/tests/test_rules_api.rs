@@ -1,4 +1,7 @@
 use crate::Rules;
+use crate::err::RulesError;
+use crate::types::{LineChange, Strategy};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
@@ -91,3 +94,857 @@ fn test_rules_api_write_methods() {
 
     cleanup_test_env(&test_dir);
 }
+
+#[test]
+fn test_rules_api_validate_object() {
+    let test_dir = setup_test_env("test_validate_object");
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert(
+        "colour".to_string(),
+        vec!["red".to_string(), "blue".to_string()],
+    );
+    obj.insert("Shape".to_string(), vec!["Circle".to_string()]);
+    assert!(rules.validate_object(&obj).is_ok());
+
+    obj.insert("doors".to_string(), vec!["3".to_string()]);
+    assert!(rules.validate_object(&obj).is_err());
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_evaluate() {
+    let test_dir = setup_test_env("test_evaluate");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "# Test rules\n- (colour = blue, red) & shape ! circle\n- colour = green | shape = rectangle",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/test.yaml", test_dir),
+        "objects:\n  shapes:\n    - colour: [red, green]\n      shape: rectangle\n\n    - colour: blue\n      shape: circle\n  boxes:\n    - colour: blue\n      size: large\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load().unwrap();
+    let result = rules.evaluate();
+
+    assert_eq!(result.objects.len(), 3);
+
+    let first = result.get("shapes", 0).unwrap();
+    assert_eq!(first.rule_numbers(), vec![0, 1]);
+    assert_eq!(first.rules[0].subrules, vec![1]);
+
+    assert!(!result.get("shapes", 1).unwrap().is_match());
+
+    let boxed = result.get("boxes", 0).unwrap();
+    assert_eq!(boxed.rule_numbers(), vec![0]);
+    assert_eq!(boxed.rules[0].subrules, vec![0]);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_evaluate_in_memory_objects() {
+    let test_dir = setup_test_env("test_evaluate_in_memory");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "- colour = red & size ! small\n- shape = circle",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load().unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert("Colour".to_string(), vec!["RED".to_string()]);
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+    obj.insert("user_id".to_string(), vec!["42".to_string()]);
+
+    let matches = rules.evaluate_object(&obj);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].rule_number, 0);
+
+    let mut circle = HashMap::new();
+    circle.insert("shape".to_string(), vec!["circle".to_string()]);
+    circle.insert("size".to_string(), vec!["small".to_string()]);
+
+    let all = rules.evaluate_objects(vec![obj, circle, HashMap::new()]);
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0][0].rule_number, 0);
+    assert_eq!(all[1].len(), 1);
+    assert_eq!(all[1][0].rule_number, 1);
+    assert!(all[2].is_empty());
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_negated_numeric_comparisons() {
+    let test_dir = setup_test_env("test_negated_numeric");
+    fs::write(format!("{}/doors.tags", test_dir), "- doors: integer(1..9)").unwrap();
+    let rules_file = format!("{}/test.rules", test_dir);
+    fs::write(
+        &rules_file,
+        "- !(doors > 2)\n- doors ! 2..4\n- doors = 2..4\n- doors ! 3 & size = large\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load().unwrap();
+
+    let matched = |doors: &[&str], size: Option<&str>| {
+        let mut obj = HashMap::new();
+        if !doors.is_empty() {
+            obj.insert(
+                "doors".to_string(),
+                doors.iter().map(|d| d.to_string()).collect(),
+            );
+        }
+        if let Some(size) = size {
+            obj.insert("size".to_string(), vec![size.to_string()]);
+        }
+        rules
+            .evaluate_object(&obj)
+            .iter()
+            .map(|m| m.rule_number)
+            .collect::<Vec<_>>()
+    };
+
+    // Negations match exactly the objects the comparison doesn't, including those without
+    // the attribute
+    assert_eq!(matched(&[], Some("large")), vec![0, 1, 3]);
+    assert_eq!(matched(&["1"], None), vec![0, 1]);
+    assert_eq!(matched(&["3"], None), vec![2]);
+    // Every value has to be outside the comparison for its negation to match
+    assert_eq!(matched(&["1", "5"], None), vec![1]);
+    // A range is checked against one value at a time, so 1 and 9 aren't within 2..4
+    assert_eq!(matched(&["1", "9"], None), vec![1]);
+
+    // Removing the tag treats a negated ordering like any other negation
+    fs::write(
+        &rules_file,
+        "- !(doors > 2) & size = large\n- doors ! 3 & size = large\n- doors = 2..4 | shape = circle\n",
+    )
+    .unwrap();
+    rules.remove_tag_cascade("doors", "doors").unwrap();
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "- size = large\n- size = large\n- shape = circle\n"
+    );
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_load_with_diagnostics() {
+    let test_dir = setup_test_env("test_load_with_diagnostics");
+    fs::write(
+        format!("{}/more.tags", test_dir),
+        "- colour: red\n- weight 10",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "- colour = red\n- colour = purple\n- shape = circle & size = huge\n- size = small",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    let diagnostics = rules.load_with_diagnostics().unwrap();
+
+    let errors: Vec<String> = diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| format!("{}:{}", d.file, d.line))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            format!("{}/more.tags:2", test_dir),
+            format!("{}/test.rules:2", test_dir),
+            format!("{}/test.rules:3", test_dir),
+        ]
+    );
+    assert_eq!(diagnostics.iter().filter(|d| !d.is_error()).count(), 1);
+
+    // The valid rules are still loaded
+    let mut obj = HashMap::new();
+    obj.insert("size".to_string(), vec!["small".to_string()]);
+    assert_eq!(rules.evaluate_object(&obj)[0].rule_number, 1);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_explain() {
+    let test_dir = setup_test_env("test_explain");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "- colour = red & shape ! circle\n- size = large | (colour = blue & shape = square)",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+    rules.load_rules().unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["Blue".to_string()]);
+    obj.insert("shape".to_string(), vec!["circle".to_string()]);
+
+    let explanation = rules.explain(&obj, 1).unwrap();
+    assert!(!explanation.is_match());
+    assert_eq!(explanation.subrules.len(), 2);
+
+    let passed: Vec<Vec<bool>> = explanation
+        .subrules
+        .iter()
+        .map(|s| s.clauses.iter().map(|c| c.passed).collect())
+        .collect();
+    assert_eq!(passed, vec![vec![false], vec![true, false]]);
+
+    let clause = &explanation.subrules[1].clauses[1];
+    assert_eq!(
+        (
+            clause.tag_name.as_str(),
+            clause.comparison_op.as_str(),
+            clause.tag_value.as_str()
+        ),
+        ("shape", "=", "square")
+    );
+    assert_eq!(clause.object_values, vec!["circle"]);
+
+    assert!(rules.explain(&obj, 2).is_none());
+
+    // Explanations agree with evaluation
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+    let matched: Vec<_> = rules
+        .explain_all(&obj)
+        .iter()
+        .filter(|e| e.is_match())
+        .map(|e| e.rule_number)
+        .collect();
+    let evaluated: Vec<_> = rules
+        .evaluate_object(&obj)
+        .iter()
+        .map(|m| m.rule_number)
+        .collect();
+    assert_eq!(matched, evaluated);
+    assert_eq!(matched, vec![1]);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_near_misses() {
+    let test_dir = setup_test_env("test_near_misses");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "- colour = red & size = large\n- colour = blue\n- colour = red & shape = square & size = large\n- shape = circle",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+    rules.load_rules().unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    obj.insert("shape".to_string(), vec!["circle".to_string()]);
+    obj.insert("size".to_string(), vec!["small".to_string()]);
+
+    let near_misses = rules.near_misses(&obj, 5);
+    let ranked: Vec<_> = near_misses.iter().map(|m| m.rule_number).collect();
+    assert_eq!(ranked, vec![0, 2]);
+
+    // "You'd qualify if size were large"
+    let missing = &near_misses[0].missing_clauses;
+    assert_eq!(missing.len(), 1);
+    assert_eq!(
+        (missing[0].tag_name.as_str(), missing[0].tag_value.as_str()),
+        ("size", "large")
+    );
+
+    assert_eq!(rules.near_misses(&obj, 1).len(), 1);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_named_rules() {
+    let test_dir = setup_test_env("test_named_rules");
+    let rules_file = format!("{}/test.rules", test_dir);
+    fs::write(
+        &rules_file,
+        "# Rules\n@owner: sales\n@description: Big red things ship free\n@priority: 5\n- [free_shipping] colour = red & size = large\n- shape = circle\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+    rules.load_rules().unwrap();
+
+    let rule = rules.rule_by_name("free_shipping").unwrap();
+    assert_eq!(rule.rule_number, 0);
+    assert_eq!(rule.owner.as_deref(), Some("sales"));
+    assert_eq!(rule.priority, Some(5));
+    assert_eq!(rule.line, 5);
+    assert!(rules.rule_by_name("circles").is_none());
+    assert_eq!(rules.rules()[1].name, None);
+
+    // Matches carry the rule they came from
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+    let matches = rules.evaluate_object(&obj);
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].rule.name.as_deref(), Some("free_shipping"));
+    assert_eq!(
+        matches[0].rule.description.as_deref(),
+        Some("Big red things ship free")
+    );
+
+    // Removing a rule removes its annotations
+    rules
+        .remove_rule("test", "- [free_shipping] colour = red & size = large")
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "# Rules\n- shape = circle\n"
+    );
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_decide() {
+    let test_dir = setup_test_env("test_decide");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "- colour = red => discount: 5\n@priority: 10\n- colour = red & size = large => discount: 10, tier: gold\n- size = large\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+    rules.load_rules().unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+
+    let first = rules.decide(&obj, Strategy::FirstMatch).unwrap();
+    assert_eq!(first.outcome().unwrap()["discount"], "5");
+
+    let highest = rules.decide(&obj, Strategy::HighestPriority).unwrap();
+    assert_eq!(highest.matches[0].rule_number, 1);
+    assert_eq!(highest.outcome().unwrap()["tier"], "gold");
+
+    // A matching rule without an outcome is still part of the decision
+    let all = rules.decide(&obj, Strategy::AllMatches).unwrap();
+    assert_eq!(all.outcomes().len(), 3);
+    assert!(all.outcomes()[2].is_empty());
+
+    // Rules 0 and 2 share the default priority, but rule 1 still wins outright, so there's
+    // no conflict
+    assert!(!all.has_conflicts());
+    assert!(!highest.has_conflicts());
+    let unique = rules.decide(&obj, Strategy::Unique).unwrap();
+    assert_eq!(unique.matches[0].rule_number, 1);
+
+    obj.insert("size".to_string(), vec!["small".to_string()]);
+    assert!(matches!(
+        rules.decide(&obj, Strategy::Unique),
+        Ok(decision) if decision.matches[0].rule_number == 0
+    ));
+
+    obj.insert("colour".to_string(), vec!["blue".to_string()]);
+    obj.insert("size".to_string(), vec!["small".to_string()]);
+    assert!(!rules.decide(&obj, Strategy::FirstMatch).unwrap().is_match());
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_decision_tables() {
+    let test_dir = setup_test_env("test_decision_tables");
+    fs::write(format!("{}/test.rules", test_dir), "- shape = circle\n").unwrap();
+    let table_file = format!("{}/discounts.csv", test_dir);
+    fs::write(
+        &table_file,
+        "Colour,Size,Outcome\nred,!small,\"discount: 10, tier: gold\"\n-,small,tier: bronze\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+    rules.load_rules().unwrap();
+
+    // Rows are numbered after the .rules files, keeping the table and row they came from
+    assert_eq!(rules.rule_count(), 3);
+    assert_eq!(rules.rules()[1].file, table_file);
+    assert_eq!(rules.rules()[1].line, 2);
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+    let decision = rules.decide(&obj, Strategy::FirstMatch).unwrap();
+    assert_eq!(decision.matches[0].rule_number, 1);
+    assert_eq!(decision.outcome().unwrap()["discount"], "10");
+
+    obj.insert("size".to_string(), vec!["small".to_string()]);
+    let decision = rules.decide(&obj, Strategy::FirstMatch).unwrap();
+    assert_eq!(decision.outcome().unwrap()["tier"], "bronze");
+
+    // Tags used by a table can't be removed or renamed, as tables are edited by hand
+    let dependents = rules.tag_value_dependents("colour", "red").unwrap();
+    assert_eq!(dependents.len(), 1);
+    assert_eq!(
+        (dependents[0].file.as_str(), dependents[0].line),
+        (table_file.as_str(), 2)
+    );
+    assert!(matches!(
+        rules.remove_tag_cascade("test", "size"),
+        Err(RulesError::TagInUse { .. })
+    ));
+    assert!(matches!(
+        rules.rename_tag_value("colour", "red", "crimson"),
+        Err(RulesError::TagInUse { .. })
+    ));
+    assert!(rules.rename_tag("shape", "form").is_ok());
+
+    // Invalid rows are errors with the table and row, and only those rows are left out
+    fs::write(
+        &table_file,
+        "colour,size,outcome\nred,huge,\nblue,-,tier: silver\n",
+    )
+    .unwrap();
+    let err = rules.load_rules().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&format!("{}:2: Column 'size':", table_file)),
+        "{}",
+        err
+    );
+
+    let diagnostics = rules.load_with_diagnostics().unwrap();
+    let errors: Vec<(String, usize)> = diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| (d.file.clone(), d.line))
+        .collect();
+    assert_eq!(errors, vec![(table_file.clone(), 2)]);
+    assert_eq!(rules.rule_count(), 2);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_load_cached() {
+    let test_dir = setup_test_env("test_load_cached");
+    let rules_file = format!("{}/test.rules", test_dir);
+    let cache_file = format!("{}/rules.cache", test_dir);
+    fs::write(
+        &rules_file,
+        "- colour = red & shape ! circle\n- size = large",
+    )
+    .unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+
+    // Parsed, then cached
+    let mut rules = Rules::new(&test_dir);
+    assert!(!rules.load_cache(&cache_file).unwrap());
+    assert!(!rules.load_cached(&cache_file).unwrap());
+    assert!(Path::new(&cache_file).exists());
+
+    let mut cached = Rules::new(&test_dir);
+    assert!(cached.load_cached(&cache_file).unwrap());
+    assert_eq!(cached.tags(), rules.tags());
+    assert_eq!(cached.rule_count(), 2);
+    assert_eq!(cached.evaluate_object(&obj), rules.evaluate_object(&obj));
+    assert!(cached.validate_rule("- shape = square").is_ok());
+
+    // Editing a rules file invalidates the cache
+    fs::write(&rules_file, "- size = small").unwrap();
+    let mut rules = Rules::new(&test_dir);
+    assert!(!rules.load_cache(&cache_file).unwrap());
+    assert!(!rules.load_cached(&cache_file).unwrap());
+    assert!(rules.evaluate_object(&obj).is_empty());
+    assert!(rules.load_cache(&cache_file).unwrap());
+
+    // So does adding a tags file
+    fs::write(format!("{}/more.tags", test_dir), "- weight: decimal(..)").unwrap();
+    assert!(!Rules::new(&test_dir).load_cache(&cache_file).unwrap());
+
+    // Rules written after loading make the saved cache stale instead of being left out of it
+    let mut rules = Rules::new(&test_dir);
+    assert!(!rules.load_cached(&cache_file).unwrap());
+    rules.write_rule("test", "- colour = blue").unwrap();
+    rules.save_cache(&cache_file).unwrap();
+
+    let mut reloaded = Rules::new(&test_dir);
+    assert!(!reloaded.load_cached(&cache_file).unwrap());
+    assert_eq!(reloaded.rule_count(), 2);
+    assert!(Rules::new(&test_dir).load_cache(&cache_file).unwrap());
+
+    // Tags and rules that weren't loaded from the config directory can't be cached
+    let built = Rules::builder()
+        .tags_str("- colour: red")
+        .rules_str("- colour = red")
+        .build()
+        .unwrap();
+    assert!(matches!(
+        built.save_cache(&cache_file),
+        Err(RulesError::CacheError(_))
+    ));
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_write_object() {
+    let test_dir = setup_test_env("test_write_object");
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    rules
+        .write_object("objects", "shapes", obj.clone())
+        .unwrap();
+
+    obj.insert("shape".to_string(), vec!["circle".to_string()]);
+    rules.write_object("objects", "shapes", obj).unwrap();
+
+    let mut invalid = HashMap::new();
+    invalid.insert("colour".to_string(), vec!["purple".to_string()]);
+    assert!(rules.write_object("objects", "shapes", invalid).is_err());
+
+    rules.load_objects().unwrap();
+    assert_eq!(rules.objects().len(), 1);
+    assert_eq!(rules.objects()[0].attributes["shape"], vec!["circle"]);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_remove_methods() {
+    let test_dir = setup_test_env("test_remove_methods");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "# Rules\n- colour = red\n- shape = circle\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+
+    rules
+        .replace_rule("test", "- colour = red", "- colour = blue")
+        .unwrap();
+    rules.remove_rule("test.rules", "- shape = circle").unwrap();
+    assert_eq!(
+        fs::read_to_string(format!("{}/test.rules", test_dir)).unwrap(),
+        "# Rules\n- colour = blue\n"
+    );
+
+    // Removals update the loaded tags
+    rules.remove_tag_value("test", "colour", "green").unwrap();
+    assert!(rules.validate_rule("- colour = green").is_err());
+    assert!(rules.validate_rule("- colour = red").is_ok());
+
+    rules.remove_tag("test", "size").unwrap();
+    assert!(rules.validate_rule("- size = small").is_err());
+
+    // A tag keeps at least one value
+    rules.remove_tag_value("test", "shape", "circle").unwrap();
+    rules.remove_tag_value("test", "shape", "square").unwrap();
+    assert!(
+        rules
+            .remove_tag_value("test", "shape", "rectangle")
+            .is_err()
+    );
+
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["red".to_string()]);
+    rules
+        .write_object("objects", "shapes", obj.clone())
+        .unwrap();
+    rules.remove_object("objects", "shapes", &obj).unwrap();
+    rules.load_objects().unwrap();
+    assert!(rules.objects().is_empty());
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_write_rule_name_in_use() {
+    let test_dir = setup_test_env("test_write_rule_name_in_use");
+    let rules_file = format!("{}/test.rules", test_dir);
+    fs::write(&rules_file, "- [a] colour = red\n- shape = circle\n").unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load().unwrap();
+
+    // A second rule named 'a' would stop the rules loading, so nothing is written
+    let expected = format!(
+        "Rule name 'a' is already used by the rule at {}:1",
+        rules_file
+    );
+    for result in [
+        rules.write_rule("test", "- [a] colour = blue"),
+        rules.replace_rule("test", "- shape = circle", "- [a] shape = square"),
+    ] {
+        match result {
+            Err(RulesError::RuleParseError(e)) => assert_eq!(e.message, expected),
+            other => panic!("Expected RuleParseError, got {:?}", other),
+        }
+    }
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "- [a] colour = red\n- shape = circle\n"
+    );
+
+    // The rule being replaced can keep its name
+    rules
+        .replace_rule("test", "- [a] colour = red", "- [a] colour = blue")
+        .unwrap();
+    rules.write_rule("test", "- [b] colour = red").unwrap();
+    rules.load().unwrap();
+    assert_eq!(rules.rule_count(), 3);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_tag_removal_integrity() {
+    let test_dir = setup_test_env("test_tag_removal_integrity");
+    let rules_file = format!("{}/test.rules", test_dir);
+    fs::write(
+        &rules_file,
+        "# Rules\n- (colour = red, green) & size = large\n- colour = green & shape = circle\n- shape = square\n- colour ! green & size = small\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+
+    let dependents = rules.tag_value_dependents("colour", "GREEN").unwrap();
+    assert_eq!(
+        dependents.iter().map(|r| r.line).collect::<Vec<_>>(),
+        vec![2, 3, 5]
+    );
+    assert_eq!(dependents[1].rule, "- colour = green & shape = circle");
+    assert_eq!(rules.tag_dependents("shape").unwrap().len(), 2);
+
+    // Refused while rules reference it, leaving the files untouched
+    match rules.remove_tag_value("test", "colour", "green") {
+        Err(RulesError::TagInUse { tag, rules }) => {
+            assert_eq!(tag, "colour: green");
+            assert_eq!(rules, dependents);
+        }
+        other => panic!("Expected TagInUse, got {:?}", other),
+    }
+    assert!(matches!(
+        rules.remove_tag("test", "shape"),
+        Err(RulesError::TagInUse { .. })
+    ));
+    assert!(rules.validate_rule("- colour = green").is_ok());
+
+    // Cascade rewrites the rules instead, and evaluation uses the rewritten rules
+    rules.load_rules().unwrap();
+    let mut green = HashMap::new();
+    green.insert("colour".to_string(), vec!["green".to_string()]);
+    green.insert("shape".to_string(), vec!["circle".to_string()]);
+    assert_eq!(rules.evaluate_object(&green).len(), 1);
+    let changes = rules
+        .remove_tag_value_cascade("test", "colour", "green")
+        .unwrap();
+    assert_eq!(
+        changes
+            .iter()
+            .map(|c| (c.line, c.new_line.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            (2, Some("- colour = red & size = large")),
+            (3, None),
+            (5, Some("- size = small")),
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "# Rules\n- colour = red & size = large\n- shape = square\n- size = small\n"
+    );
+    assert!(rules.validate_rule("- colour = green").is_err());
+    assert_eq!(rules.rule_count(), 3);
+    assert!(rules.evaluate_object(&green).is_empty());
+
+    // A rule that would match every object stops the cascade before anything is written
+    fs::write(&rules_file, "- shape ! circle\n").unwrap();
+    assert!(rules.remove_tag_cascade("test", "shape").is_err());
+    assert!(rules.validate_rule("- shape = circle").is_ok());
+
+    fs::write(&rules_file, "- shape = circle | size = large\n").unwrap();
+    rules.remove_tag_cascade("test", "shape").unwrap();
+    assert_eq!(fs::read_to_string(&rules_file).unwrap(), "- size = large\n");
+    assert_eq!(rules.rule_count(), 1);
+    green.insert("size".to_string(), vec!["large".to_string()]);
+    assert_eq!(rules.evaluate_object(&green).len(), 1);
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_rename() {
+    let test_dir = setup_test_env("test_rename");
+    let rules_file = format!("{}/test.rules", test_dir);
+    fs::write(
+        &rules_file,
+        "# Rules\n-colour=green,RED & size ! large\n- shape = circle\n- (colour ! green)\n",
+    )
+    .unwrap();
+    fs::write(format!("{}/more.tags", test_dir), "- colour: green\n").unwrap();
+    let objects_file = format!("{}/objects.yaml", test_dir);
+    fs::write(
+        &objects_file,
+        "objects:\n  shapes:\n    - colour: [red, green]\n      size: large\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load_tags().unwrap();
+    rules.load_objects().unwrap();
+
+    // Nothing is written by a dry run
+    let changes = rules
+        .rename_tag_value_dry_run("colour", "green", "lime")
+        .unwrap();
+    assert_eq!(
+        LineChange::diff(&changes),
+        format!(
+            "{dir}/more.tags:1\n-- colour: green\n+- colour: lime\n\
+             {dir}/test.tags:2\n-- colour: red, blue, green\n+- colour: red, blue, lime\n\
+             {dir}/test.rules:2\n--colour=green,RED & size ! large\n+-colour=lime,RED & size ! large\n\
+             {dir}/test.rules:4\n-- (colour ! green)\n+- (colour ! lime)\n\
+             {dir}/objects.yaml:3\n-    - colour: [red, green]\n+    - colour: [red, lime]\n",
+            dir = test_dir
+        )
+    );
+    assert!(rules.validate_rule("- colour = lime").is_err());
+
+    assert_eq!(
+        rules.rename_tag_value("colour", "green", "lime").unwrap(),
+        changes
+    );
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "# Rules\n-colour=lime,RED & size ! large\n- shape = circle\n- (colour ! lime)\n"
+    );
+    assert!(rules.validate_rule("- colour = lime").is_ok());
+    assert!(rules.validate_rule("- colour = green").is_err());
+
+    let changes = rules.rename_tag("Colour", "hue").unwrap();
+    assert_eq!(changes.len(), 5);
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "# Rules\n-hue=lime,RED & size ! large\n- shape = circle\n- (hue ! lime)\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&objects_file).unwrap(),
+        "objects:\n  shapes:\n    - hue: [red, lime]\n      size: large\n"
+    );
+    assert!(rules.validate_rule("- hue = lime").is_ok());
+
+    // The loaded objects are renamed with the files
+    let renamed = &rules.objects()[0].attributes;
+    assert_eq!(renamed["hue"], vec!["red", "lime"]);
+    assert!(!renamed.contains_key("colour"));
+
+    // The renamed rules and objects load from disk
+    rules.load_tags().unwrap();
+    rules.load_rules().unwrap();
+    rules.load_objects().unwrap();
+
+    // Names must exist, be valid and not be taken
+    assert!(rules.rename_tag("colour", "tint").is_err());
+    assert!(rules.rename_tag("hue", "shape").is_err());
+    assert!(rules.rename_tag("hue", "light blue").is_err());
+    assert!(rules.rename_tag_value("hue", "red", "blue").is_err());
+    assert!(rules.rename_tag_value("hue", "pink", "rose").is_err());
+    assert!(rules.rename_tag_value("hue", "red", "integer(..)").is_err());
+
+    // Nothing is renamed while an objects file can't be parsed and rewritten
+    fs::write(
+        format!("{}/broken.yaml", test_dir),
+        "shapes:\n  - hue: red\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        rules.rename_tag_value("hue", "red", "crimson"),
+        Err(RulesError::ObjectParseError(_))
+    ));
+    assert_eq!(
+        fs::read_to_string(&rules_file).unwrap(),
+        "# Rules\n-hue=lime,RED & size ! large\n- shape = circle\n- (hue ! lime)\n"
+    );
+
+    cleanup_test_env(&test_dir);
+}
+
+#[test]
+fn test_rules_api_rename_evaluates() {
+    let test_dir = setup_test_env("test_rename_evaluates");
+    fs::write(
+        format!("{}/test.rules", test_dir),
+        "- colour = green\n- colour = green, red & size = large\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/test.yaml", test_dir),
+        "objects:\n  shapes:\n    - colour: green\n      size: large\n",
+    )
+    .unwrap();
+
+    let mut rules = Rules::new(&test_dir);
+    rules.load().unwrap();
+
+    // The loaded rules are renamed along with the files, without loading them again
+    rules.rename_tag_value("colour", "green", "lime").unwrap();
+    let mut obj = HashMap::new();
+    obj.insert("colour".to_string(), vec!["lime".to_string()]);
+    obj.insert("size".to_string(), vec!["large".to_string()]);
+    let matches: Vec<_> = rules
+        .evaluate_object(&obj)
+        .iter()
+        .map(|m| m.rule_number)
+        .collect();
+    assert_eq!(matches, vec![0, 1]);
+    assert_eq!(
+        rules.evaluate().get("shapes", 0).unwrap().rule_numbers(),
+        vec![0, 1]
+    );
+
+    rules.rename_tag("colour", "hue").unwrap();
+    assert!(rules.evaluate_object(&obj).is_empty());
+    let obj: HashMap<String, Vec<String>> = obj
+        .into_iter()
+        .map(|(name, values)| (name.replace("colour", "hue"), values))
+        .collect();
+    assert_eq!(rules.evaluate_object(&obj).len(), 2);
+    assert_eq!(
+        rules.evaluate().get("shapes", 0).unwrap().rule_numbers(),
+        vec![0, 1]
+    );
+
+    cleanup_test_env(&test_dir);
+}
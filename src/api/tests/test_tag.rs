@@ -1,4 +1,6 @@
-use crate::api::write::tag::write_with_base_dir;
+use crate::api::write::tag::{
+    remove_value_with_base_dir, remove_with_base_dir, rename_line, write_with_base_dir,
+};
 use crate::err::RulesError;
 use std::fs;
 use std::path::Path;
@@ -168,7 +170,7 @@ fn test_write_tag_appends_values_to_existing_tag() {
     .unwrap();
 
     // Read file and verify
-    let content = fs::read_to_string(&format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
+    let content = fs::read_to_string(format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
     assert!(content.contains("red"));
     assert!(content.contains("blue"));
     assert!(content.contains("green"));
@@ -205,7 +207,7 @@ fn test_write_tag_adds_multiple_tags() {
     .unwrap();
 
     // Read file and verify both tags exist
-    let content = fs::read_to_string(&format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
+    let content = fs::read_to_string(format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
     assert!(content.contains("colour"));
     assert!(content.contains("size"));
     assert!(content.contains("red"));
@@ -251,10 +253,84 @@ fn test_write_tag_formats_correctly() {
     )
     .unwrap();
 
-    let content = fs::read_to_string(&format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
+    let content = fs::read_to_string(format!("{}/{}", TEST_CONFIG_DIR, file_name)).unwrap();
 
     // Should be formatted as "- colour: red, blue, green"
     assert!(content.contains("- colour: red, blue, green"));
 
     cleanup_test_file(file_name);
 }
+
+#[test]
+fn test_remove_tag_value() {
+    let file_name = "test_remove_value.tags";
+    setup_and_cleanup_test_file(file_name);
+    let path = format!("{}/{}", TEST_CONFIG_DIR, file_name);
+    fs::write(
+        &path,
+        "# Colours\n- Colour: Red, blue, green\n- size: small\n- colour: blue\n- doors: integer(2..5)\n",
+    )
+    .unwrap();
+
+    let removed = remove_value_with_base_dir(file_name, "colour", "BLUE", TEST_CONFIG_DIR).unwrap();
+    assert_eq!(removed, vec!["blue", "blue"]);
+
+    // Other values keep how they were written, lines left without values are removed
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Colours\n- Colour: Red, green\n- size: small\n- doors: integer(2..5)\n"
+    );
+
+    let missing = remove_value_with_base_dir(file_name, "colour", "blue", TEST_CONFIG_DIR);
+    assert!(matches!(missing, Err(RulesError::TagParseError(_))));
+
+    let numeric = remove_value_with_base_dir(file_name, "doors", "3", TEST_CONFIG_DIR);
+    assert!(matches!(numeric, Err(RulesError::TagParseError(msg)) if msg.contains("Numeric")));
+
+    cleanup_test_file(file_name);
+}
+
+#[test]
+fn test_remove_tag() {
+    let file_name = "test_remove.tags";
+    setup_and_cleanup_test_file(file_name);
+    let path = format!("{}/{}", TEST_CONFIG_DIR, file_name);
+    fs::write(
+        &path,
+        "# Tags\n- colour: red, blue\n- size: small\n- colour: green",
+    )
+    .unwrap();
+
+    let removed = remove_with_base_dir(file_name, "Colour", TEST_CONFIG_DIR).unwrap();
+    assert_eq!(removed, vec!["red", "blue", "green"]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# Tags\n- size: small");
+
+    let missing = remove_with_base_dir(file_name, "colour", TEST_CONFIG_DIR);
+    assert!(matches!(missing, Err(RulesError::TagParseError(msg)) if msg.contains("not found")));
+
+    cleanup_test_file(file_name);
+}
+
+#[test]
+fn test_rename_tag_line() {
+    assert_eq!(
+        rename_line(
+            "- Colour:Grey, greyish ,grey",
+            "colour",
+            Some("GREY"),
+            "gray"
+        )
+        .as_deref(),
+        Some("- Colour:gray, greyish ,gray")
+    );
+    assert_eq!(
+        rename_line("-  Colour : grey", "colour", None, "hue").as_deref(),
+        Some("-  hue : grey")
+    );
+    assert_eq!(rename_line("- colours: grey", "colour", None, "hue"), None);
+    assert_eq!(
+        rename_line("- colour: red", "colour", Some("grey"), "gray"),
+        None
+    );
+    assert_eq!(rename_line("# - colour: grey", "colour", None, "hue"), None);
+}
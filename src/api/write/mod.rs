@@ -1,3 +1,71 @@
+use crate::err::RulesError;
+use crate::types::LineChange;
+use std::fs;
+use std::io;
+
 pub mod object;
 pub mod rule;
 pub mod tag;
+
+// Lines of an existing file, and whether it ends with a newline
+pub(crate) fn read_lines(full_path: &str) -> Result<(Vec<String>, bool), RulesError> {
+    let content = fs::read_to_string(full_path)?;
+    let lines = content.lines().map(|l: &str| l.to_string()).collect();
+    Ok((lines, content.ends_with('\n')))
+}
+
+pub(crate) fn write_lines(
+    full_path: &str,
+    lines: &[String],
+    trailing_newline: bool,
+) -> Result<(), RulesError> {
+    let mut content = lines.join("\n");
+    if trailing_newline && !content.is_empty() {
+        content.push('\n');
+    }
+    fs::write(full_path, content)?;
+    Ok(())
+}
+
+// Rewrites the lines of each change in place, removing the lines with no new line.
+// Nothing is written unless every changed line is still what it was when it was read.
+pub(crate) fn apply_changes(changes: &[LineChange]) -> Result<(), RulesError> {
+    let mut files: Vec<(&str, Vec<String>, bool)> = Vec::new();
+    for change in changes {
+        if !files.iter().any(|(file, ..)| *file == change.file) {
+            let (lines, trailing_newline) = read_lines(&change.file)?;
+            files.push((&change.file, lines, trailing_newline));
+        }
+    }
+
+    for (file, lines, _) in files.iter_mut() {
+        // Removed lines are kept until every change has been made, so line numbers hold
+        let mut removed: Vec<usize> = Vec::new();
+        for change in changes.iter().filter(|c| c.file == *file) {
+            let i = change.line - 1;
+            if lines.get(i) != Some(&change.old_line) {
+                return Err(RulesError::IoError(io::Error::other(format!(
+                    "{}:{}: Line has changed since it was read, expected '{}'",
+                    file, change.line, change.old_line
+                ))));
+            }
+
+            match &change.new_line {
+                Some(new_line) => lines[i] = new_line.clone(),
+                None => removed.push(i),
+            }
+        }
+
+        let mut i = 0;
+        lines.retain(|_| {
+            i += 1;
+            !removed.contains(&(i - 1))
+        });
+    }
+
+    for (file, lines, trailing_newline) in &files {
+        write_lines(file, lines, *trailing_newline)?;
+    }
+
+    Ok(())
+}
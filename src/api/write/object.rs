@@ -1,32 +1,473 @@
 use crate::err::RulesError;
+use crate::parser::objects;
+use crate::types::{Object, TagName, TagValues};
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const OBJECTS_KEY: &str = "objects";
+const INDENT: usize = 2;
+
+// An object in the file -- its attributes, the lines it starts and ends on and the column
+// its keys start at
+struct ObjectLines {
+    attributes: Object,
+    first_line: usize,
+    last_line: usize,
+    key_indent: usize,
+}
+
+// An object type group in the file, and the column its objects' '-' is at
+struct GroupLines {
+    obj_type: String,
+    indent: usize,
+    first_line: usize,
+    last_line: usize,
+    item_indent: Option<usize>,
+    objects: Vec<ObjectLines>,
+}
+
+fn normalise_filename(file_name: &str) -> String {
+    if file_name.ends_with(".yaml") {
+        file_name.to_string()
+    } else {
+        format!("{}.yaml", file_name)
+    }
+}
+
+fn ensure_config_dir(base_dir: &str) -> Result<(), RulesError> {
+    let config_dir = Path::new(base_dir);
+    if !config_dir.exists() {
+        fs::create_dir_all(config_dir)?;
+    }
+    Ok(())
+}
 
 pub fn write(
     file_name: &str,
     obj_type: String,
     obj: HashMap<String, Vec<String>>,
+    tags: HashMap<TagName, TagValues>,
 ) -> Result<(), RulesError> {
-    write_with_base_dir(file_name, obj_type, obj, "config")
+    write_with_base_dir(file_name, obj_type, obj, tags, "config")
+}
+
+// Lowercased object, as objects are parsed case-insensitively
+fn normalise_object(obj: &Object) -> Object {
+    let mut normalised: Object = HashMap::new();
+    for (name, values) in obj {
+        let normalised_values = normalised.entry(name.trim().to_lowercase()).or_default();
+        for value in values.iter().map(|v| v.trim().to_lowercase()) {
+            if !normalised_values.contains(&value) {
+                normalised_values.push(value);
+            }
+        }
+    }
+    normalised
+}
+
+// "red" for one value, "[red, green]" for several
+fn format_values(values: &[String]) -> String {
+    match values {
+        [value] => value.clone(),
+        values => format!("[{}]", values.join(", ")),
+    }
+}
+
+// Attribute lines at `key_indent`, in name order
+fn attribute_lines(attributes: &Object, names: &[&String], key_indent: usize) -> Vec<String> {
+    names
+        .iter()
+        .map(|name| {
+            format!(
+                "{}{}: {}",
+                " ".repeat(key_indent),
+                name,
+                format_values(&attributes[*name])
+            )
+        })
+        .collect()
+}
+
+// Lines of a new object, with its '-' at `item_indent`
+fn object_lines(obj: &Object, item_indent: usize) -> Vec<String> {
+    let mut names: Vec<&String> = obj.keys().collect();
+    names.sort();
+
+    let mut lines = attribute_lines(obj, &names, item_indent + INDENT);
+    lines[0].replace_range(item_indent..item_indent + 1, "-");
+    lines
+}
+
+fn same_values(a: &[String], b: &[String]) -> bool {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort();
+    b.sort();
+    a == b
+}
+
+// An existing object is the new object if every attribute they share has the same values,
+// and one has every attribute of the other -- e.g. {colour: red} and {colour: red, size: large}
+fn is_same_object(existing: &Object, new: &Object) -> bool {
+    let shared: Vec<&String> = existing.keys().filter(|k| new.contains_key(*k)).collect();
+
+    !shared.is_empty()
+        && shared
+            .iter()
+            .all(|name| same_values(&existing[*name], &new[*name]))
+        && (shared.len() == existing.len() || shared.len() == new.len())
+}
+
+// Finds the groups and objects in a valid objects file, with the objects parsed from it
+fn group_lines(lines: &[String], mut parsed: impl Iterator<Item = Object>) -> Vec<GroupLines> {
+    let mut groups: Vec<GroupLines> = Vec::new();
+    let mut group_indent: Option<usize> = None;
+
+    for (i, raw) in lines.iter().enumerate() {
+        let text = raw.split('#').next().unwrap_or_default();
+        if text.trim().is_empty() {
+            continue;
+        }
+        let indent = text.len() - text.trim_start().len();
+        let text = text.trim();
+
+        // "objects:"
+        if indent == 0 {
+            continue;
+        }
+
+        // "type:" starts a group
+        if group_indent.is_none_or(|group_indent| group_indent == indent) && !text.starts_with('-')
+        {
+            group_indent = Some(indent);
+            groups.push(GroupLines {
+                obj_type: text.trim_end_matches(':').trim().to_lowercase(),
+                indent,
+                first_line: i,
+                last_line: i,
+                item_indent: None,
+                objects: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(group) = groups.last_mut() else {
+            continue;
+        };
+        group.last_line = i;
+
+        // "- key: value" starts an object, deeper "- value" lines are block list values
+        if let Some(rest) = text.strip_prefix('-')
+            && group
+                .item_indent
+                .is_none_or(|item_indent| item_indent == indent)
+        {
+            group.item_indent = Some(indent);
+            group.objects.push(ObjectLines {
+                attributes: parsed.next().unwrap_or_default(),
+                first_line: i,
+                last_line: i,
+                key_indent: indent + text.len() - rest.trim_start().len(),
+            });
+        } else if let Some(obj) = group.objects.last_mut() {
+            obj.last_line = i;
+        }
+    }
+
+    groups
+}
+
+fn is_blank(line: &str) -> bool {
+    line.split('#').next().unwrap_or_default().trim().is_empty()
 }
 
-#[cfg(test)]
 pub(crate) fn write_with_base_dir(
-    _file_name: &str,
-    _obj_type: String,
-    _obj: HashMap<String, Vec<String>>,
-    _base_dir: &str,
+    file_name: &str,
+    obj_type: String,
+    obj: HashMap<String, Vec<String>>,
+    tags: HashMap<TagName, TagValues>,
+    base_dir: &str,
 ) -> Result<(), RulesError> {
-    // TODO: if object already exists, add non-existent attributes
+    let obj_type = obj_type.trim().to_lowercase();
+    if obj_type.is_empty() || obj_type.contains([' ', ':', '#']) || obj_type.starts_with('-') {
+        return Err(RulesError::ObjectParseError(format!(
+            "Invalid object type '{}'",
+            obj_type
+        )));
+    }
+
+    if obj.is_empty() {
+        return Err(RulesError::ObjectParseError(
+            "Object must have at least one attribute".to_string(),
+        ));
+    }
+
+    let obj = normalise_object(&obj);
+    objects::validate_object(&obj, &tags)?;
+
+    let normalised_name = normalise_filename(file_name);
+    let full_path = format!("{}/{}", base_dir, normalised_name);
+
+    ensure_config_dir(base_dir)?;
+
+    let content = if Path::new(&full_path).exists() {
+        fs::read_to_string(&full_path)?
+    } else {
+        String::new()
+    };
+
+    // The file must be valid to find where the object goes
+    let parsed = objects::parse_objects_str(&full_path, &content, &tags)?;
+    let mut lines: Vec<String> = content.lines().map(|l: &str| l.to_string()).collect();
+    let groups = group_lines(&lines, parsed.into_iter().map(|o| o.attributes));
+
+    if lines.iter().all(|line| is_blank(line)) {
+        lines.push(format!("{}:", OBJECTS_KEY));
+    }
+
+    match groups.iter().find(|g| g.obj_type == obj_type) {
+        Some(group) => {
+            match group
+                .objects
+                .iter()
+                .find(|existing| is_same_object(&existing.attributes, &obj))
+            {
+                // Add the attributes the existing object doesn't have
+                Some(existing) => {
+                    let mut missing: Vec<&String> = obj
+                        .keys()
+                        .filter(|name| !existing.attributes.contains_key(*name))
+                        .collect();
+                    if missing.is_empty() {
+                        return Ok(());
+                    }
+                    missing.sort();
+
+                    let new_lines = attribute_lines(&obj, &missing, existing.key_indent);
+                    lines.splice(existing.last_line + 1..existing.last_line + 1, new_lines);
+                }
+                // Append to the end of the group, separated from its last object
+                None => {
+                    let item_indent = group.item_indent.unwrap_or(group.indent + INDENT);
+                    let mut new_lines = object_lines(&obj, item_indent);
+                    if !group.objects.is_empty() {
+                        new_lines.insert(0, String::new());
+                    }
+                    lines.splice(group.last_line + 1..group.last_line + 1, new_lines);
+                }
+            }
+        }
+        // Start a new group at the end of the file
+        None => {
+            let indent = groups.first().map_or(INDENT, |g| g.indent);
+            if !groups.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(format!("{}{}:", " ".repeat(indent), obj_type));
+            lines.extend(object_lines(&obj, indent + INDENT));
+        }
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+
+    // Check the result still parses before overwriting the file
+    objects::parse_objects_str(&full_path, &updated, &tags)?;
+    fs::write(&full_path, updated)?;
+
     Ok(())
 }
 
-#[cfg(not(test))]
-pub(crate) fn write_with_base_dir(
-    _file_name: &str,
-    _obj_type: String,
-    _obj: HashMap<String, Vec<String>>,
-    _base_dir: &str,
+// Removes the lines of an object with exactly the given attributes, and its group if it was
+// the group's only object. Comments before the object and the rest of the file are kept.
+pub(crate) fn remove_with_base_dir(
+    file_name: &str,
+    obj_type: &str,
+    obj: &Object,
+    tags: HashMap<TagName, TagValues>,
+    base_dir: &str,
 ) -> Result<(), RulesError> {
-    // TODO: if object already exists, add non-existent attributes
+    let obj_type = obj_type.trim().to_lowercase();
+    let obj = normalise_object(obj);
+    let full_path = format!("{}/{}", base_dir, normalise_filename(file_name));
+
+    let content = fs::read_to_string(&full_path)?;
+    let parsed = objects::parse_objects_str(&full_path, &content, &tags)?;
+    let mut lines: Vec<String> = content.lines().map(|l: &str| l.to_string()).collect();
+    let groups = group_lines(&lines, parsed.into_iter().map(|o| o.attributes));
+
+    let not_found = || {
+        RulesError::ObjectParseError(format!(
+            "Object not found in group '{}' of {}",
+            obj_type, full_path
+        ))
+    };
+
+    let group = groups
+        .iter()
+        .find(|g| g.obj_type == obj_type)
+        .ok_or_else(not_found)?;
+    let existing = group
+        .objects
+        .iter()
+        .find(|existing| {
+            existing.attributes.len() == obj.len()
+                && obj.iter().all(|(name, values)| {
+                    existing
+                        .attributes
+                        .get(name)
+                        .is_some_and(|existing_values| same_values(existing_values, values))
+                })
+        })
+        .ok_or_else(not_found)?;
+
+    let (first, last) = if group.objects.len() == 1 {
+        (group.first_line, group.last_line)
+    } else {
+        (existing.first_line, existing.last_line)
+    };
+    lines.drain(first..=last);
+
+    // Drop the blank line separating the object from the one before or after it
+    let empty = |line: Option<&String>| line.is_some_and(|line| line.trim().is_empty());
+    let at_start = match groups.first() {
+        Some(first_group) if first_group.first_line == first => true,
+        _ => group.objects[0].first_line == first,
+    };
+    if at_start && empty(lines.get(first)) {
+        lines.remove(first);
+    } else if first > 0
+        && empty(lines.get(first - 1))
+        && (first == lines.len() || empty(lines.get(first)))
+    {
+        lines.remove(first - 1);
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+
+    objects::parse_objects_str(&full_path, &updated, &tags)?;
+    fs::write(&full_path, updated)?;
+
     Ok(())
 }
+
+// The values with `value` renamed to `new_value`, keeping quotes and the rest as written --
+// e.g. `[red, "grey"]` -> `[red, "gray"]`. None if no value is renamed.
+fn rename_values(values: &str, value: &str, new_value: &str) -> Option<String> {
+    let is_delimiter = |c: char| c.is_whitespace() || ['[', ']', ','].contains(&c);
+    let mut renamed = String::new();
+    let mut changed = false;
+
+    let mut rest = values;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| !is_delimiter(c))
+            .map(|start| {
+                start
+                    + rest[start..]
+                        .find(is_delimiter)
+                        .unwrap_or(rest.len() - start)
+            })
+            .unwrap_or(rest.len());
+        let (word, next) = rest.split_at(end);
+
+        let unquoted = word
+            .trim_start_matches(is_delimiter)
+            .trim_matches(['"', '\'']);
+        if !unquoted.is_empty() && unquoted.to_lowercase() == value {
+            changed = true;
+            renamed.push_str(&word.replacen(unquoted, new_value, 1));
+        } else {
+            renamed.push_str(word);
+        }
+        rest = next;
+    }
+
+    changed.then_some(renamed)
+}
+
+// Lines of an objects file renaming an attribute, or one of its values when one is given,
+// as (line index, new line). Only attributes of objects are renamed, not object types, and
+// comments are kept. The file must be valid.
+// E.g. renaming grey to gray: "    - colour: [red, grey]" -> "    - colour: [red, gray]"
+pub(crate) fn rename_lines(
+    file_name: &str,
+    content: &str,
+    tags: &HashMap<TagName, TagValues>,
+    tag_name: &str,
+    tag_value: Option<&str>,
+    new_name: &str,
+) -> Result<Vec<(usize, String)>, RulesError> {
+    let tag_name = tag_name.trim().to_lowercase();
+    let tag_value = tag_value.map(|value| value.trim().to_lowercase());
+
+    let parsed = objects::parse_objects_str(file_name, content, tags)?;
+    let lines: Vec<String> = content.lines().map(|l: &str| l.to_string()).collect();
+    let groups = group_lines(&lines, parsed.into_iter().map(|o| o.attributes));
+
+    let mut renamed = Vec::new();
+    for obj in groups.iter().flat_map(|group| &group.objects) {
+        // Attribute whose values are on the lines below it, as a block list
+        let mut block_list: Option<String> = None;
+
+        for (i, line) in lines
+            .iter()
+            .enumerate()
+            .take(obj.last_line + 1)
+            .skip(obj.first_line)
+        {
+            let code_end = line.find('#').unwrap_or(line.len());
+            let (code, comment) = line.split_at(code_end);
+            if code.trim().is_empty() {
+                continue;
+            }
+
+            let indent = code.len() - code.trim_start().len();
+            let new_code = if i != obj.first_line && code.trim_start().starts_with('-') {
+                // "- value" of a block list
+                match (&tag_value, &block_list) {
+                    (Some(value), Some(name)) if *name == tag_name => {
+                        let dash = indent + 1;
+                        rename_values(&code[dash..], value, new_name)
+                            .map(|values| format!("{}{}", &code[..dash], values))
+                    }
+                    _ => None,
+                }
+            } else {
+                // "key: values", after the '-' on the object's first line
+                let start = if i == obj.first_line {
+                    obj.key_indent
+                } else {
+                    indent
+                };
+                let Some((key, values)) = code[start..].split_once(':') else {
+                    continue;
+                };
+                let name = key.trim().to_lowercase();
+                block_list = values.trim().is_empty().then(|| name.clone());
+                if name != tag_name {
+                    continue;
+                }
+
+                match &tag_value {
+                    None => Some(format!(
+                        "{}{}:{}",
+                        &code[..start],
+                        key.replacen(key.trim(), new_name, 1),
+                        values
+                    )),
+                    Some(value) => rename_values(values, value, new_name)
+                        .map(|values| format!("{}{}:{}", &code[..start], key, values)),
+                }
+            };
+
+            if let Some(new_code) = new_code {
+                renamed.push((i, format!("{}{}", new_code, comment)));
+            }
+        }
+    }
+
+    Ok(renamed)
+}
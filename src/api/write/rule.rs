@@ -1,6 +1,9 @@
-use crate::err::RulesError;
+use crate::err::{RuleParseError, RulesError};
 use crate::parser::rules::RuleParser;
 use crate::types::{TagName, TagValues};
+use crate::utils::file;
+
+use super::{read_lines, write_lines};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
@@ -81,9 +84,7 @@ fn write_internal(
         .iter()
         .any(|line: &String| line.trim() == rule_trimmed)
     {
-        return Err(RulesError::RuleParseError(
-            "Rule already exists in file".to_string(),
-        ));
+        return Err(RuleParseError::new("Rule already exists in file").into());
     }
 
     lines.push(rule_trimmed.to_string());
@@ -92,3 +93,88 @@ fn write_internal(
 
     Ok(())
 }
+
+// A line of a .rules file is the rule if their canonical forms are the same, so spacing and
+// case don't matter -- e.g. "-Colour=red" is "- colour = red"
+fn is_same_rule(line: &str, canonical: &str) -> bool {
+    !file::line_blank_or_comment(line)
+        && RuleParser::format_rule(line).is_ok_and(|formatted| formatted == canonical)
+}
+
+// Removes every line of the file with the rule and the annotations above it, keeping the
+// rest of the file as it is
+pub(crate) fn remove_with_base_dir(
+    file_name: &str,
+    rule: &str,
+    base_dir: &str,
+) -> Result<(), RulesError> {
+    let full_path = format!("{}/{}", base_dir, normalise_filename(file_name));
+    let canonical = RuleParser::format_rule(rule)?;
+
+    let (mut lines, trailing_newline) = read_lines(&full_path)?;
+    let mut removed: Vec<usize> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if is_same_rule(line, &canonical) {
+            removed.extend(RuleParser::annotation_lines(&lines, i));
+            removed.push(i);
+        }
+    }
+
+    let mut i = 0;
+    lines.retain(|_| {
+        i += 1;
+        !removed.contains(&(i - 1))
+    });
+
+    if removed.is_empty() {
+        return Err(RuleParseError::new(format!(
+            "Rule '{}' not found in {}",
+            rule.trim(),
+            full_path
+        ))
+        .into());
+    }
+
+    write_lines(&full_path, &lines, trailing_newline)
+}
+
+// Replaces every line of the file with the old rule with the new rule, in place
+pub(crate) fn replace_with_base_dir(
+    file_name: &str,
+    old_rule: &str,
+    new_rule: &str,
+    tags: HashMap<TagName, TagValues>,
+    base_dir: &str,
+) -> Result<(), RulesError> {
+    let full_path = format!("{}/{}", base_dir, normalise_filename(file_name));
+    let old_canonical = RuleParser::format_rule(old_rule)?;
+
+    let parser = RuleParser::new(tags);
+    parser.validate_rule(new_rule)?;
+    let new_canonical = RuleParser::format_rule(new_rule)?;
+
+    let (mut lines, trailing_newline) = read_lines(&full_path)?;
+    if new_canonical != old_canonical && lines.iter().any(|line| is_same_rule(line, &new_canonical))
+    {
+        return Err(RuleParseError::new("Rule already exists in file").into());
+    }
+
+    let mut found = false;
+    for line in lines.iter_mut() {
+        if is_same_rule(line, &old_canonical) {
+            *line = new_rule.trim().to_string();
+            found = true;
+        }
+    }
+
+    if !found {
+        return Err(RuleParseError::new(format!(
+            "Rule '{}' not found in {}",
+            old_rule.trim(),
+            full_path
+        ))
+        .into());
+    }
+
+    write_lines(&full_path, &lines, trailing_newline)
+}
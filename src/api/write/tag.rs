@@ -1,5 +1,8 @@
 use crate::err::RulesError;
 use crate::parser::tags;
+use crate::utils::file;
+
+use super::{read_lines, write_lines};
 use std::fs;
 use std::path::Path;
 
@@ -76,6 +79,8 @@ fn write_internal(
         }
     }
 
+    tags::parse_numeric_tag(&tag_values)?;
+
     let normalised_name = normalise_filename(file_name);
     let full_path = format!("{}/{}", base_dir, normalised_name);
 
@@ -99,8 +104,17 @@ fn write_internal(
         }
 
         match tags::get_name_and_values_from_tag(line) {
-            Ok((extracted_name, _extracted_values)) => {
+            Ok((extracted_name, extracted_values)) => {
                 if extracted_name.trim() == tag_name_trimmed {
+                    if tags::numeric_tag(&extracted_values).is_some()
+                        || tags::numeric_tag(&tag_values).is_some()
+                    {
+                        return Err(RulesError::TagParseError(format!(
+                            "Numeric tag '{}' cannot be defined more than once",
+                            tag_name_trimmed
+                        )));
+                    }
+
                     line.push_str(&format!(", {}", tag_values.join(", ")));
                     tag_exists = true;
                     break;
@@ -121,3 +135,148 @@ fn write_internal(
 
     Ok(())
 }
+
+// Lowercased values of the line if it defines the tag
+fn tag_line_values(line: &str, tag_name: &str) -> Option<Vec<String>> {
+    if file::line_blank_or_comment(line) {
+        return None;
+    }
+
+    match tags::get_name_and_values_from_tag(line) {
+        Ok((name, values)) if name.trim().to_lowercase() == tag_name => {
+            Some(values.iter().map(|v| v.to_lowercase()).collect())
+        }
+        _ => None,
+    }
+}
+
+// Removes a value from every line of the file defining the tag, dropping lines left with no
+// values. Returns the lowercased values removed, once for each line they were removed from.
+pub(crate) fn remove_value_with_base_dir(
+    file_name: &str,
+    tag_name: &str,
+    value: &str,
+    base_dir: &str,
+) -> Result<Vec<String>, RulesError> {
+    let full_path = format!("{}/{}", base_dir, normalise_filename(file_name));
+    let tag_name = tag_name.trim().to_lowercase();
+    let value = value.trim().to_lowercase();
+
+    let (lines, trailing_newline) = read_lines(&full_path)?;
+    let mut updated: Vec<String> = Vec::with_capacity(lines.len());
+    let mut removed: Vec<String> = Vec::new();
+
+    for line in lines {
+        let Some(values) = tag_line_values(&line, &tag_name) else {
+            updated.push(line);
+            continue;
+        };
+
+        if tags::numeric_tag(&values).is_some() {
+            return Err(RulesError::TagParseError(format!(
+                "Numeric tag '{}' has no values to remove, remove the tag instead",
+                tag_name
+            )));
+        }
+
+        if !values.contains(&value) {
+            updated.push(line);
+            continue;
+        }
+
+        // Keep the name and the other values as written
+        let (name, written_values) = line.split_once(':').unwrap_or_default();
+        let remaining: Vec<&str> = written_values
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| v.to_lowercase() != value)
+            .collect();
+
+        removed.extend(std::iter::repeat_n(
+            value.clone(),
+            values.len() - remaining.len(),
+        ));
+        if !remaining.is_empty() {
+            updated.push(format!("{}: {}", name.trim_end(), remaining.join(", ")));
+        }
+    }
+
+    if removed.is_empty() {
+        return Err(RulesError::TagParseError(format!(
+            "Tag '{}' has no value '{}' in {}",
+            tag_name, value, full_path
+        )));
+    }
+
+    write_lines(&full_path, &updated, trailing_newline)?;
+    Ok(removed)
+}
+
+// Removes every line of the file defining the tag. Returns the lowercased values removed.
+pub(crate) fn remove_with_base_dir(
+    file_name: &str,
+    tag_name: &str,
+    base_dir: &str,
+) -> Result<Vec<String>, RulesError> {
+    let full_path = format!("{}/{}", base_dir, normalise_filename(file_name));
+    let tag_name = tag_name.trim().to_lowercase();
+
+    let (lines, trailing_newline) = read_lines(&full_path)?;
+    let mut updated: Vec<String> = Vec::with_capacity(lines.len());
+    let mut removed: Option<Vec<String>> = None;
+
+    for line in lines {
+        match tag_line_values(&line, &tag_name) {
+            Some(values) => removed.get_or_insert_default().extend(values),
+            None => updated.push(line),
+        }
+    }
+
+    let Some(removed) = removed else {
+        return Err(RulesError::TagParseError(format!(
+            "Tag '{}' not found in {}",
+            tag_name, full_path
+        )));
+    };
+
+    write_lines(&full_path, &updated, trailing_newline)?;
+    Ok(removed)
+}
+
+// The line with the tag, or the tag's value when one is given, renamed to new_name, and the
+// rest of the line as written. None if the line doesn't define the tag or the value.
+pub(crate) fn rename_line(
+    line: &str,
+    tag_name: &str,
+    tag_value: Option<&str>,
+    new_name: &str,
+) -> Option<String> {
+    let tag_name = tag_name.trim().to_lowercase();
+    tag_line_values(line, &tag_name)?;
+    let (name, values) = line.split_once(':')?;
+
+    let Some(tag_value) = tag_value else {
+        let written = name.trim().trim_start_matches('-').trim();
+        return Some(format!(
+            "{}:{}",
+            name.replacen(written, new_name, 1),
+            values
+        ));
+    };
+
+    let tag_value = tag_value.trim().to_lowercase();
+    let mut renamed = false;
+    let values: Vec<String> = values
+        .split(',')
+        .map(|value| {
+            if value.trim().to_lowercase() == tag_value {
+                renamed = true;
+                value.replacen(value.trim(), new_name, 1)
+            } else {
+                value.to_string()
+            }
+        })
+        .collect();
+
+    renamed.then(|| format!("{}:{}", name, values.join(",")))
+}
@@ -0,0 +1,333 @@
+use crate::err::RulesError;
+use crate::parser::objects;
+use crate::parser::rules::RuleParser;
+use crate::parser::tables;
+use crate::rules::Rules;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+const DEFAULT_CONFIG_DIR: &str = "config";
+
+// Where the contents of a tags, rules, decision table or objects document come from
+enum Source {
+    Str(String),
+    Reader(Box<dyn Read>),
+    Path(PathBuf),
+}
+
+impl Source {
+    // Name used in error messages, and the document's contents
+    fn read(self) -> Result<(String, String), RulesError> {
+        match self {
+            Source::Str(content) => Ok(("<string>".to_string(), content)),
+            Source::Reader(mut reader) => {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                Ok(("<reader>".to_string(), content))
+            }
+            Source::Path(path) => {
+                let content = fs::read_to_string(&path)?;
+                Ok((path.display().to_string(), content))
+            }
+        }
+    }
+}
+
+fn read_all(sources: Vec<Source>) -> Result<Vec<(String, String)>, RulesError> {
+    sources.into_iter().map(Source::read).collect()
+}
+
+/// Builds a validated `Rules` instance from in-memory strings, readers and file paths.
+///
+/// Sources of each kind can be mixed freely and are read in the order they were added.
+/// Tags are loaded first, then rules, decision tables and objects are validated against
+/// them, exactly as `Rules::load` does for a config directory. Decision table rows are
+/// numbered after every other rule.
+///
+/// # Examples
+/// ```ignore
+/// use rules::RulesBuilder;
+///
+/// let rules = RulesBuilder::new()
+///     .tags_str("- colour: red, blue\n- size: small, large")
+///     .rules_path("config/my_rules.rules")
+///     .rules_str("- colour = red & size = large")
+///     .decision_table_path("config/discounts.csv")
+///     .objects_reader(std::io::stdin())
+///     .build()?;
+///
+/// let result = rules.evaluate();
+/// ```
+pub struct RulesBuilder {
+    config_dir: String,
+    tag_sources: Vec<Source>,
+    rule_sources: Vec<Source>,
+    table_sources: Vec<Source>,
+    object_sources: Vec<Source>,
+}
+
+impl Default for RulesBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RulesBuilder {
+    /// Creates an empty builder. Nothing is read from the config directory.
+    pub fn new() -> Self {
+        Self {
+            config_dir: DEFAULT_CONFIG_DIR.to_string(),
+            tag_sources: Vec::new(),
+            rule_sources: Vec::new(),
+            table_sources: Vec::new(),
+            object_sources: Vec::new(),
+        }
+    }
+
+    /// Sets the directory the built instance writes tags, rules and objects to.
+    ///
+    /// Defaults to `config`. Nothing is read from this directory.
+    pub fn config_dir(mut self, config_dir: impl Into<String>) -> Self {
+        self.config_dir = config_dir.into();
+        self
+    }
+
+    /// Adds tag definitions in `.tags` format -- e.g. `"- colour: red, blue"`.
+    pub fn tags_str(mut self, tags: impl Into<String>) -> Self {
+        self.tag_sources.push(Source::Str(tags.into()));
+        self
+    }
+
+    /// Adds tag definitions read from a reader when `build` is called.
+    pub fn tags_reader(mut self, reader: impl Read + 'static) -> Self {
+        self.tag_sources.push(Source::Reader(Box::new(reader)));
+        self
+    }
+
+    /// Adds tag definitions from a `.tags` file.
+    pub fn tags_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tag_sources.push(Source::Path(path.into()));
+        self
+    }
+
+    /// Adds rules in `.rules` format -- e.g. `"- colour = red & size = large"`.
+    pub fn rules_str(mut self, rules: impl Into<String>) -> Self {
+        self.rule_sources.push(Source::Str(rules.into()));
+        self
+    }
+
+    /// Adds rules read from a reader when `build` is called.
+    pub fn rules_reader(mut self, reader: impl Read + 'static) -> Self {
+        self.rule_sources.push(Source::Reader(Box::new(reader)));
+        self
+    }
+
+    /// Adds rules from a `.rules` file.
+    pub fn rules_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.rule_sources.push(Source::Path(path.into()));
+        self
+    }
+
+    /// Adds a decision table in `.csv` format -- e.g. `"colour,size,outcome\nred,-,tier: gold"`.
+    pub fn decision_table_str(mut self, table: impl Into<String>) -> Self {
+        self.table_sources.push(Source::Str(table.into()));
+        self
+    }
+
+    /// Adds a decision table read from a reader when `build` is called.
+    pub fn decision_table_reader(mut self, reader: impl Read + 'static) -> Self {
+        self.table_sources.push(Source::Reader(Box::new(reader)));
+        self
+    }
+
+    /// Adds a decision table from a `.csv` file.
+    pub fn decision_table_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.table_sources.push(Source::Path(path.into()));
+        self
+    }
+
+    /// Adds an objects document in `.yaml` format, starting with `objects:`.
+    pub fn objects_str(mut self, objects: impl Into<String>) -> Self {
+        self.object_sources.push(Source::Str(objects.into()));
+        self
+    }
+
+    /// Adds an objects document read from a reader when `build` is called.
+    pub fn objects_reader(mut self, reader: impl Read + 'static) -> Self {
+        self.object_sources.push(Source::Reader(Box::new(reader)));
+        self
+    }
+
+    /// Adds an objects document from a `.yaml` file.
+    pub fn objects_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.object_sources.push(Source::Path(path.into()));
+        self
+    }
+
+    /// Reads every source and builds the ruleset.
+    ///
+    /// # Returns
+    /// * `Ok(Rules)` with the tags, rules and objects loaded
+    /// * `Err(RulesError)` if a source cannot be read, or fails to parse or validate
+    pub fn build(self) -> Result<Rules, RulesError> {
+        let mut rules = Rules::new(self.config_dir);
+
+        for (_, content) in read_all(self.tag_sources)? {
+            rules.add_tags(&content)?;
+        }
+
+        let mut rule_files = read_all(self.rule_sources)?;
+        for (name, content) in read_all(self.table_sources)? {
+            let table_rules = tables::table_to_rules(&name, &content, rules.tags())?;
+            rule_files.push((name, table_rules));
+        }
+        let (named_rules, dnf_subrules) =
+            RuleParser::parse_rule_files(rules.tags().clone(), &rule_files)?;
+        rules.set_rules(named_rules, dnf_subrules);
+
+        let object_files = read_all(self.object_sources)?;
+        rules.set_objects(objects::parse_object_files(&object_files, rules.tags())?);
+
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    const TAGS: &str = "- colour: red, blue, green\n- size: small, large";
+
+    #[test]
+    fn test_build_from_strings() {
+        let rules = RulesBuilder::new()
+            .tags_str(TAGS)
+            .rules_str("- colour = red & size = large\n- colour = blue")
+            .objects_str("objects:\n  shapes:\n    - colour: red\n      size: large\n")
+            .build()
+            .unwrap();
+
+        let result = rules.evaluate();
+        assert_eq!(result.objects.len(), 1);
+        assert_eq!(result.get("shapes", 0).unwrap().rule_numbers(), vec![0]);
+
+        let mut obj = HashMap::new();
+        obj.insert("colour".to_string(), vec!["blue".to_string()]);
+        assert_eq!(rules.evaluate_object(&obj)[0].rule_number, 1);
+    }
+
+    #[test]
+    fn test_build_from_mixed_sources() {
+        let rules = RulesBuilder::new()
+            .tags_str("- colour: red, blue")
+            .tags_reader(Cursor::new("- size: small, large"))
+            .rules_reader(Cursor::new("- size = small"))
+            .rules_str("- colour = red")
+            .build()
+            .unwrap();
+
+        assert!(rules.validate_rule("- size = large").is_ok());
+
+        let mut obj = HashMap::new();
+        obj.insert("colour".to_string(), vec!["red".to_string()]);
+        obj.insert("size".to_string(), vec!["small".to_string()]);
+        let matched: Vec<_> = rules
+            .evaluate_object(&obj)
+            .iter()
+            .map(|m| m.rule_number)
+            .collect();
+        assert_eq!(matched, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_build_from_paths() {
+        let rules = RulesBuilder::new()
+            .tags_path("config/my_tags.tags")
+            .rules_path("config/my_rules.rules")
+            .objects_path("config/my_objects.yaml")
+            .build()
+            .unwrap();
+
+        let result = rules.evaluate();
+        assert_eq!(result.objects.len(), 4);
+        assert!(result.get("shapes", 0).unwrap().is_match());
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_rule() {
+        let result = RulesBuilder::new()
+            .tags_str(TAGS)
+            .rules_str("- colour = red\n\n- colour = purple")
+            .build();
+
+        match result {
+            Err(RulesError::RuleParseError(e)) => {
+                assert_eq!(e.location(), "<string>:3:12");
+                assert!(e.message.contains("invalid TagValue"));
+            }
+            _ => panic!("Expected RuleParseError"),
+        }
+    }
+
+    #[test]
+    fn test_build_with_decision_table() {
+        let rules = RulesBuilder::new()
+            .tags_str(TAGS)
+            .rules_str("- colour = green")
+            .decision_table_str("colour,size,outcome\nred,!small,tier: gold\n-,small,tier: bronze")
+            .build()
+            .unwrap();
+
+        // Table rows are numbered after the rules, and keep their row as their line
+        let table_rules: Vec<_> = rules.rules()[1..]
+            .iter()
+            .map(|r| (r.rule_number, r.file.as_str(), r.line))
+            .collect();
+        assert_eq!(table_rules, vec![(1, "<string>", 2), (2, "<string>", 3)]);
+
+        let mut obj = HashMap::new();
+        obj.insert("colour".to_string(), vec!["red".to_string()]);
+        obj.insert("size".to_string(), vec!["large".to_string()]);
+        let matches = rules.evaluate_object(&obj);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].rule.outcome["tier"], "gold");
+
+        let result = RulesBuilder::new()
+            .tags_str(TAGS)
+            .decision_table_str("colour,size\nred,small\nred,huge")
+            .build();
+        match result {
+            Err(RulesError::RuleParseError(e)) => {
+                assert!(
+                    e.message.starts_with("<string>:3: Column 'size':"),
+                    "{}",
+                    e.message
+                )
+            }
+            _ => panic!("Expected RuleParseError"),
+        }
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_object() {
+        let result = RulesBuilder::new()
+            .tags_str(TAGS)
+            .objects_str("objects:\n  shapes:\n    - colour: purple\n")
+            .build();
+
+        match result {
+            Err(RulesError::ObjectParseError(msg)) => assert!(msg.starts_with("<string>:3:")),
+            _ => panic!("Expected ObjectParseError"),
+        }
+    }
+
+    #[test]
+    fn test_build_missing_path() {
+        let result = RulesBuilder::new().tags_path("does/not/exist.tags").build();
+
+        assert!(matches!(result, Err(RulesError::IoError(_))));
+    }
+}
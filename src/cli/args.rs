@@ -0,0 +1,372 @@
+// Command-line argument parsing
+use rules::types::{Object, RuleNumber, Strategy};
+
+const DEFAULT_CONFIG_DIR: &str = "config";
+
+pub const USAGE: &str = "\
+Usage: rules <command> [options]
+
+Commands:
+  validate   Check tags, rules and objects, reporting every problem
+  eval       Print the rules each object matches
+  explain    Show why an object matched or didn't match the rules
+  decide     Print the outcome each object gets from the rules it matches
+  fmt        Rewrite rules files in canonical form
+
+Options:
+  --config-dir <dir>      Directory of .tags, .rules, .csv and .yaml files (default: config)
+  --format <text|json>    Output format (default: text)
+  --object <type:index>   eval, explain, decide: a loaded object -- e.g. shapes:0
+  --attr <name=v1,v2>     eval, explain, decide: an attribute of an object given on the
+                          command line instead, repeated for each attribute
+  --rule <number>         explain: only explain this rule
+  --strategy <first|all|priority|unique>
+                          decide: the first matching rule, every matching rule,
+                          the one with the highest @priority, or that rule only if
+                          no other shares its priority (default: first)
+  --cache <file>          eval, explain, decide: load tags and rules from a cache file,
+                          rewriting it if the config has changed
+  --check                 fmt: report unformatted files instead of rewriting them
+  -h, --help              Print this message
+
+Exit codes:
+  0  Success
+  1  Invalid config, unformatted files with fmt --check, or an object
+     no single rule wins with decide --strategy unique
+  2  Invalid arguments
+  3  Config files could not be read or written";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Validate,
+    Eval,
+    Explain,
+    Decide,
+    Fmt,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+// Object to evaluate or explain
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectArg {
+    // Loaded object, by type and index within its type -- e.g. ("shapes", 0)
+    Loaded(String, usize),
+    // Attributes given with --attr -- e.g. {"colour": ["red", "blue"]}
+    Attributes(Object),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub config_dir: String,
+    pub format: Format,
+    pub object: Option<ObjectArg>,
+    pub rule: Option<RuleNumber>,
+    pub strategy: Strategy,
+    pub check: bool,
+    pub cache: Option<String>,
+}
+
+impl Command {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "validate" => Some(Command::Validate),
+            "eval" => Some(Command::Eval),
+            "explain" => Some(Command::Explain),
+            "decide" => Some(Command::Decide),
+            "fmt" => Some(Command::Fmt),
+            "help" | "-h" | "--help" => Some(Command::Help),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Validate => "validate",
+            Command::Eval => "eval",
+            Command::Explain => "explain",
+            Command::Decide => "decide",
+            Command::Fmt => "fmt",
+            Command::Help => "help",
+        }
+    }
+}
+
+// "shapes:0" -> ("shapes", 0)
+fn parse_object(value: &str) -> Result<ObjectArg, String> {
+    let invalid = || {
+        format!(
+            "--object must be written as <type:index>, found '{}'",
+            value
+        )
+    };
+
+    let (obj_type, index) = value.rsplit_once(':').ok_or_else(invalid)?;
+    let index = index.trim().parse::<usize>().map_err(|_| invalid())?;
+    if obj_type.trim().is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(ObjectArg::Loaded(obj_type.trim().to_string(), index))
+}
+
+// "colour=red,blue" -> ("colour", ["red", "blue"])
+fn parse_attribute(value: &str) -> Result<(String, Vec<String>), String> {
+    let invalid = || format!("--attr must be written as <name=v1,v2>, found '{}'", value);
+
+    let (name, values) = value.split_once('=').ok_or_else(invalid)?;
+    let values: Vec<String> = values
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    if name.trim().is_empty() || values.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok((name.trim().to_string(), values))
+}
+
+pub fn parse(args: &[String]) -> Result<Args, String> {
+    let mut iter = args.iter();
+
+    let command = match iter.next() {
+        Some(arg) => Command::from_arg(arg).ok_or_else(|| format!("Unknown command '{}'", arg))?,
+        None => return Err("Missing command".to_string()),
+    };
+
+    let mut parsed = Args {
+        command,
+        config_dir: DEFAULT_CONFIG_DIR.to_string(),
+        format: Format::default(),
+        object: None,
+        rule: None,
+        strategy: Strategy::default(),
+        check: false,
+        cache: None,
+    };
+
+    while let Some(arg) = iter.next() {
+        // Options take their value as the next argument, or after '=' -- e.g. "--format=json"
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| iter.next().cloned())
+                .ok_or_else(|| format!("Option '{}' requires a value", option))
+        };
+
+        let allowed = match option {
+            "-h" | "--help" => {
+                parsed.command = Command::Help;
+                return Ok(parsed);
+            }
+            "--config-dir" => {
+                parsed.config_dir = value()?;
+                true
+            }
+            "--format" => {
+                parsed.format = match value()?.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    other => {
+                        return Err(format!(
+                            "Unknown format '{}', expected 'text' or 'json'",
+                            other
+                        ));
+                    }
+                };
+                true
+            }
+            "--object" => {
+                if parsed.object.is_some() {
+                    return Err("Only one of --object or --attr can be given".to_string());
+                }
+                parsed.object = Some(parse_object(&value()?)?);
+                matches!(command, Command::Eval | Command::Explain | Command::Decide)
+            }
+            "--attr" => {
+                let (name, values) = parse_attribute(&value()?)?;
+                match &mut parsed.object {
+                    None => {
+                        parsed.object = Some(ObjectArg::Attributes(Object::from([(name, values)])))
+                    }
+                    Some(ObjectArg::Attributes(obj)) => {
+                        obj.entry(name).or_default().extend(values);
+                    }
+                    Some(ObjectArg::Loaded(..)) => {
+                        return Err("Only one of --object or --attr can be given".to_string());
+                    }
+                }
+                matches!(command, Command::Eval | Command::Explain | Command::Decide)
+            }
+            "--rule" => {
+                let rule = value()?;
+                parsed.rule = Some(
+                    rule.parse::<RuleNumber>()
+                        .ok()
+                        .filter(|n| *n >= 0)
+                        .ok_or_else(|| format!("--rule must be a rule number, found '{}'", rule))?,
+                );
+                command == Command::Explain
+            }
+            "--strategy" => {
+                let strategy = value()?;
+                parsed.strategy = Strategy::parse(&strategy).ok_or_else(|| {
+                    format!(
+                        "Unknown strategy '{}', expected 'first', 'all', 'priority' or 'unique'",
+                        strategy
+                    )
+                })?;
+                command == Command::Decide
+            }
+            "--cache" => {
+                parsed.cache = Some(value()?);
+                matches!(command, Command::Eval | Command::Explain | Command::Decide)
+            }
+            "--check" => {
+                if inline_value.is_some() {
+                    return Err("Option '--check' does not take a value".to_string());
+                }
+                parsed.check = true;
+                command == Command::Fmt
+            }
+            _ => return Err(format!("Unknown option '{}'", arg)),
+        };
+
+        if !allowed {
+            return Err(format!(
+                "Option '{}' cannot be used with '{}'",
+                option,
+                command.name()
+            ));
+        }
+    }
+
+    if command == Command::Explain && parsed.object.is_none() {
+        return Err("explain requires --object or --attr".to_string());
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let parsed = args(&["validate"]).unwrap();
+        assert_eq!(parsed.command, Command::Validate);
+        assert_eq!(parsed.config_dir, "config");
+        assert_eq!(parsed.format, Format::Text);
+        assert_eq!(parsed.object, None);
+        assert_eq!(parsed.strategy, Strategy::FirstMatch);
+        assert!(!parsed.check);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let parsed = args(&[
+            "explain",
+            "--config-dir",
+            "my/config",
+            "--format=json",
+            "--object",
+            "shapes:1",
+            "--rule",
+            "2",
+            "--cache",
+            "rules.cache",
+        ])
+        .unwrap();
+
+        assert_eq!(parsed.command, Command::Explain);
+        assert_eq!(parsed.config_dir, "my/config");
+        assert_eq!(parsed.format, Format::Json);
+        assert_eq!(
+            parsed.object,
+            Some(ObjectArg::Loaded("shapes".to_string(), 1))
+        );
+        assert_eq!(parsed.rule, Some(2));
+        assert_eq!(parsed.cache.as_deref(), Some("rules.cache"));
+
+        assert!(args(&["fmt", "--check"]).unwrap().check);
+        assert_eq!(
+            args(&["decide", "--strategy", "priority"])
+                .unwrap()
+                .strategy,
+            Strategy::HighestPriority
+        );
+        assert_eq!(args(&["eval", "--help"]).unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let parsed = args(&[
+            "eval",
+            "--attr",
+            "colour=red, blue",
+            "--attr=size=large",
+            "--attr",
+            "colour=green",
+        ])
+        .unwrap();
+
+        let Some(ObjectArg::Attributes(obj)) = parsed.object else {
+            panic!("Expected attributes");
+        };
+        assert_eq!(obj["colour"], vec!["red", "blue", "green"]);
+        assert_eq!(obj["size"], vec!["large"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases: Vec<(&[&str], &str)> = vec![
+            (&[], "Missing command"),
+            (&["check"], "Unknown command 'check'"),
+            (&["eval", "--verbose"], "Unknown option '--verbose'"),
+            (&["eval", "--config-dir"], "requires a value"),
+            (&["eval", "--format", "xml"], "Unknown format 'xml'"),
+            (&["validate", "--check"], "cannot be used with 'validate'"),
+            (&["fmt", "--cache", "a"], "cannot be used with 'fmt'"),
+            (&["eval", "--rule", "1"], "cannot be used with 'eval'"),
+            (&["eval", "--strategy", "all"], "cannot be used with 'eval'"),
+            (&["decide", "--strategy", "last"], "Unknown strategy 'last'"),
+            (&["explain", "--rule", "1"], "requires --object or --attr"),
+            (&["explain", "--object", "shapes"], "<type:index>"),
+            (&["explain", "--attr", "colour"], "<name=v1,v2>"),
+            (&["explain", "--attr", "colour="], "<name=v1,v2>"),
+            (
+                &["explain", "--object", "shapes:0", "--attr", "size=large"],
+                "Only one of",
+            ),
+            (
+                &["explain", "--object", "shapes:0", "--rule", "-1"],
+                "rule number",
+            ),
+        ];
+
+        for (input, expected) in cases {
+            match args(input) {
+                Err(msg) => assert!(msg.contains(expected), "{:?}: '{}'", input, msg),
+                Ok(parsed) => panic!("{:?} should fail, got {:?}", input, parsed),
+            }
+        }
+    }
+}
@@ -0,0 +1,948 @@
+// Command-line interface -- validate, eval, explain, decide and fmt subcommands
+mod args;
+mod json;
+
+use args::{Args, Command, Format, ObjectArg};
+use json::Json;
+use rules::types::{Conflict, Decision, Object, Outcome, RuleMatch, RuleNumber};
+use rules::{ClauseTrace, Diagnostic, RuleExplanation, Rules, RulesError};
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+pub const EXIT_OK: u8 = 0;
+// Invalid config, unformatted files with fmt --check, or an object no single rule wins
+// with decide --strategy unique
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+// Config files could not be read or written
+pub const EXIT_IO: u8 = 3;
+
+enum CliError {
+    Usage(String),
+    Rules(RulesError),
+}
+
+impl From<RulesError> for CliError {
+    fn from(error: RulesError) -> Self {
+        CliError::Rules(error)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(error: std::io::Error) -> Self {
+        CliError::Rules(RulesError::IoError(error))
+    }
+}
+
+// Runs the command in `args` (without the program name), returning the exit code
+pub fn run(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> u8 {
+    let args = match args::parse(args) {
+        Ok(args) => args,
+        Err(msg) => {
+            let _ = writeln!(err, "error: {}\n\n{}", msg, args::USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let result = match args.command {
+        Command::Validate => validate(&args, out),
+        Command::Eval => eval(&args, out),
+        Command::Explain => explain(&args, out),
+        Command::Decide => decide(&args, out),
+        Command::Fmt => fmt(&args, out),
+        Command::Help => writeln!(out, "{}", args::USAGE)
+            .map(|_| EXIT_OK)
+            .map_err(CliError::from),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(CliError::Usage(msg)) => {
+            let _ = writeln!(err, "error: {}", msg);
+            EXIT_USAGE
+        }
+        Err(CliError::Rules(error)) => {
+            let code = match error {
+                RulesError::IoError(_)
+                | RulesError::GlobPatternError(_)
+                | RulesError::GlobError(_) => EXIT_IO,
+                _ => EXIT_FAILURE,
+            };
+            let message = match &error {
+                RulesError::RuleParseError(e) => e.render(),
+                error => error.to_string(),
+            };
+            let _ = writeln!(err, "error: {}", message);
+            code
+        }
+    }
+}
+
+fn check_config_dir(args: &Args) -> Result<(), CliError> {
+    if !Path::new(&args.config_dir).is_dir() {
+        return Err(CliError::Rules(RulesError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Config directory '{}' does not exist", args.config_dir),
+        ))));
+    }
+    Ok(())
+}
+
+fn load(args: &Args) -> Result<Rules, CliError> {
+    check_config_dir(args)?;
+    let mut rules = Rules::new(&args.config_dir);
+
+    match &args.cache {
+        Some(cache_path) => {
+            rules.load_cached(cache_path)?;
+            rules.load_objects()?;
+        }
+        None => rules.load()?,
+    }
+
+    Ok(rules)
+}
+
+// "1 error" or "2 errors"
+fn count(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, noun)
+    } else {
+        format!("{} {}s", n, noun)
+    }
+}
+
+fn diagnostic_json(diagnostic: &Diagnostic) -> Json {
+    let severity = if diagnostic.is_error() {
+        "error"
+    } else {
+        "warning"
+    };
+
+    Json::object([
+        ("severity", severity.into()),
+        ("file", diagnostic.file.as_str().into()),
+        ("line", diagnostic.line.into()),
+        ("column", diagnostic.column.into()),
+        ("message", diagnostic.message.as_str().into()),
+    ])
+}
+
+fn validate(args: &Args, out: &mut dyn Write) -> Result<u8, CliError> {
+    check_config_dir(args)?;
+    let mut rules = Rules::new(&args.config_dir);
+    let diagnostics = rules.load_with_diagnostics()?;
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+
+    match args.format {
+        Format::Text => {
+            for diagnostic in &diagnostics {
+                writeln!(out, "{}", diagnostic)?;
+            }
+            writeln!(
+                out,
+                "{}: {}, {}",
+                args.config_dir,
+                count(errors, "error"),
+                count(warnings, "warning")
+            )?;
+        }
+        Format::Json => {
+            let json = Json::object([
+                ("valid", (errors == 0).into()),
+                ("errors", errors.into()),
+                ("warnings", warnings.into()),
+                (
+                    "diagnostics",
+                    Json::Array(diagnostics.iter().map(diagnostic_json).collect()),
+                ),
+            ]);
+            writeln!(out, "{}", json)?;
+        }
+    }
+
+    Ok(if errors == 0 { EXIT_OK } else { EXIT_FAILURE })
+}
+
+// Object to evaluate or explain, with a label for output -- e.g. "shapes[0]"
+struct Selected {
+    obj_type: Option<String>,
+    index: Option<usize>,
+    attributes: Object,
+}
+
+impl Selected {
+    fn label(&self) -> String {
+        match (&self.obj_type, self.index) {
+            (Some(obj_type), Some(index)) => format!("{}[{}]", obj_type, index),
+            _ => "object".to_string(),
+        }
+    }
+}
+
+// The objects selected by --object or --attr, or every loaded object
+fn select_objects(args: &Args, rules: &Rules) -> Result<Vec<Selected>, CliError> {
+    match &args.object {
+        Some(ObjectArg::Loaded(obj_type, index)) => {
+            let obj = rules
+                .objects()
+                .iter()
+                .find(|o| o.obj_type.eq_ignore_ascii_case(obj_type) && o.index == *index)
+                .ok_or_else(|| {
+                    CliError::Usage(format!("No object {}[{}] is loaded", obj_type, index))
+                })?;
+
+            Ok(vec![Selected {
+                obj_type: Some(obj.obj_type.clone()),
+                index: Some(obj.index),
+                attributes: obj.attributes.clone(),
+            }])
+        }
+        Some(ObjectArg::Attributes(attributes)) => {
+            rules.validate_object(attributes)?;
+            Ok(vec![Selected {
+                obj_type: None,
+                index: None,
+                attributes: attributes.clone(),
+            }])
+        }
+        None => Ok(rules
+            .objects()
+            .iter()
+            .map(|obj| Selected {
+                obj_type: Some(obj.obj_type.clone()),
+                index: Some(obj.index),
+                attributes: obj.attributes.clone(),
+            })
+            .collect()),
+    }
+}
+
+// Rule number, with the rule's name if it has one -- e.g. "0 [free_shipping]"
+fn rule_label(rule_number: RuleNumber, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{} [{}]", rule_number, name),
+        None => rule_number.to_string(),
+    }
+}
+
+// Name of a loaded rule, if it has one
+fn rule_name(rules: &Rules, rule_number: RuleNumber) -> Option<&str> {
+    rules
+        .rules()
+        .get(rule_number as usize)
+        .and_then(|rule| rule.name.as_deref())
+}
+
+fn rule_match_json(rule_match: &RuleMatch) -> Json {
+    Json::object([
+        ("rule", rule_match.rule_number.into()),
+        ("name", rule_match.rule.name.clone().into()),
+        ("subrules", rule_match.subrules.clone().into()),
+    ])
+}
+
+fn attributes_json(attributes: &Object) -> Json {
+    let mut names: Vec<&String> = attributes.keys().collect();
+    names.sort();
+
+    Json::Object(
+        names
+            .into_iter()
+            .map(|name| (name.clone(), attributes[name].clone().into()))
+            .collect(),
+    )
+}
+
+fn eval(args: &Args, out: &mut dyn Write) -> Result<u8, CliError> {
+    let rules = load(args)?;
+    let selected = select_objects(args, &rules)?;
+
+    let mut results = Vec::new();
+    for obj in &selected {
+        let matches = rules.evaluate_object(&obj.attributes);
+
+        match args.format {
+            Format::Text if matches.is_empty() => writeln!(out, "{}: no match", obj.label())?,
+            Format::Text => {
+                let numbers: Vec<String> = matches
+                    .iter()
+                    .map(|m| rule_label(m.rule_number, m.rule.name.as_deref()))
+                    .collect();
+                writeln!(out, "{}: rules {}", obj.label(), numbers.join(", "))?;
+            }
+            Format::Json => results.push(Json::object([
+                ("type", obj.obj_type.clone().into()),
+                ("index", obj.index.into()),
+                (
+                    "rules",
+                    Json::Array(matches.iter().map(rule_match_json).collect()),
+                ),
+            ])),
+        }
+    }
+
+    if args.format == Format::Json {
+        writeln!(out, "{}", Json::object([("objects", Json::Array(results))]))?;
+    }
+
+    Ok(EXIT_OK)
+}
+
+// "discount: 10, tier: gold", or "no outcome" if it is empty
+fn outcome_text(outcome: &Outcome) -> String {
+    if outcome.is_empty() {
+        return "no outcome".to_string();
+    }
+
+    outcome
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn outcome_json(outcome: &Outcome) -> Json {
+    Json::Object(
+        outcome
+            .iter()
+            .map(|(key, value)| (key.clone(), value.as_str().into()))
+            .collect(),
+    )
+}
+
+fn conflict_json(conflict: &Conflict) -> Json {
+    Json::object([
+        ("priority", conflict.priority.into()),
+        ("rules", conflict.rule_numbers.clone().into()),
+    ])
+}
+
+fn decide(args: &Args, out: &mut dyn Write) -> Result<u8, CliError> {
+    let rules = load(args)?;
+    let selected = select_objects(args, &rules)?;
+
+    let mut results = Vec::new();
+    let mut undecided = false;
+    for obj in &selected {
+        // With --strategy unique, an object no single rule wins is reported, not fatal
+        let (decision, unresolved) = match rules.decide(&obj.attributes, args.strategy) {
+            Ok(decision) => (decision, false),
+            Err(RulesError::RuleConflict(conflict)) => (
+                Decision {
+                    matches: Vec::new(),
+                    conflicts: vec![conflict],
+                },
+                true,
+            ),
+            Err(e) => return Err(e.into()),
+        };
+        undecided |= unresolved;
+
+        match args.format {
+            Format::Text if unresolved => writeln!(
+                out,
+                "{}: no single rule wins, {} all match",
+                obj.label(),
+                decision.conflicts[0]
+            )?,
+            Format::Text => {
+                if !decision.is_match() {
+                    writeln!(out, "{}: no match", obj.label())?;
+                }
+                for rule_match in &decision.matches {
+                    writeln!(
+                        out,
+                        "{}: rule {} => {}",
+                        obj.label(),
+                        rule_label(rule_match.rule_number, rule_match.rule.name.as_deref()),
+                        outcome_text(&rule_match.rule.outcome)
+                    )?;
+                }
+                for conflict in &decision.conflicts {
+                    writeln!(out, "{}: conflict between {}", obj.label(), conflict)?;
+                }
+            }
+            Format::Json => {
+                let rules = decision
+                    .matches
+                    .iter()
+                    .map(|m| {
+                        Json::object([
+                            ("rule", m.rule_number.into()),
+                            ("name", m.rule.name.clone().into()),
+                            ("outcome", outcome_json(&m.rule.outcome)),
+                        ])
+                    })
+                    .collect();
+                results.push(Json::object([
+                    ("type", obj.obj_type.clone().into()),
+                    ("index", obj.index.into()),
+                    ("decided", (!unresolved).into()),
+                    ("rules", Json::Array(rules)),
+                    (
+                        "conflicts",
+                        Json::Array(decision.conflicts.iter().map(conflict_json).collect()),
+                    ),
+                ]));
+            }
+        }
+    }
+
+    if args.format == Format::Json {
+        let json = Json::object([
+            ("strategy", args.strategy.as_str().into()),
+            ("objects", Json::Array(results)),
+        ]);
+        writeln!(out, "{}", json)?;
+    }
+
+    Ok(if undecided { EXIT_FAILURE } else { EXIT_OK })
+}
+
+fn clause_json(clause: &ClauseTrace) -> Json {
+    Json::object([
+        ("tag", clause.tag_name.as_str().into()),
+        ("op", clause.comparison_op.as_str().into()),
+        ("value", clause.tag_value.as_str().into()),
+        ("object_values", clause.object_values.clone().into()),
+        ("passed", clause.passed.into()),
+    ])
+}
+
+fn explanation_json(explanation: &RuleExplanation, name: Option<&str>) -> Json {
+    let subrules = explanation
+        .subrules
+        .iter()
+        .map(|subrule| {
+            Json::object([
+                ("subrule", subrule.subrule_number.into()),
+                ("matched", subrule.is_match().into()),
+                ("expected_count", subrule.expected_count.into()),
+                ("actual_count", subrule.actual_count.into()),
+                (
+                    "clauses",
+                    Json::Array(subrule.clauses.iter().map(clause_json).collect()),
+                ),
+            ])
+        })
+        .collect();
+
+    Json::object([
+        ("rule", explanation.rule_number.into()),
+        ("name", name.into()),
+        ("matched", explanation.is_match().into()),
+        ("subrules", Json::Array(subrules)),
+    ])
+}
+
+// "  pass  doors >= 4 (object: 5)"
+fn write_clause(out: &mut dyn Write, clause: &ClauseTrace) -> std::io::Result<()> {
+    let object_values = if clause.object_values.is_empty() {
+        "none".to_string()
+    } else {
+        clause.object_values.join(", ")
+    };
+
+    writeln!(
+        out,
+        "    {}  {} {} {} (object: {})",
+        if clause.passed { "pass" } else { "FAIL" },
+        clause.tag_name,
+        clause.comparison_op.as_str(),
+        clause.tag_value,
+        object_values
+    )
+}
+
+fn explain(args: &Args, out: &mut dyn Write) -> Result<u8, CliError> {
+    let rules = load(args)?;
+    let selected = select_objects(args, &rules)?;
+    let obj = &selected[0];
+
+    // Every rule, or only the rule given with --rule
+    let explanations: Vec<RuleExplanation> = match args.rule {
+        Some(rule) => {
+            let explanation = rules.explain(&obj.attributes, rule).ok_or_else(|| {
+                CliError::Usage(format!(
+                    "No rule {} is loaded, there are {}",
+                    rule,
+                    count(rules.rule_count(), "rule")
+                ))
+            })?;
+            vec![explanation]
+        }
+        None => rules.explain_all(&obj.attributes),
+    };
+
+    match args.format {
+        Format::Text => {
+            writeln!(out, "{}", obj.label())?;
+
+            let mut names: Vec<&String> = obj.attributes.keys().collect();
+            names.sort();
+            for name in names {
+                writeln!(out, "  {}: {}", name, obj.attributes[name].join(", "))?;
+            }
+
+            for explanation in &explanations {
+                let verdict = if explanation.is_match() {
+                    "match"
+                } else {
+                    "no match"
+                };
+                let label = rule_label(
+                    explanation.rule_number,
+                    rule_name(&rules, explanation.rule_number),
+                );
+                writeln!(out, "rule {}: {}", label, verdict)?;
+
+                for subrule in &explanation.subrules {
+                    writeln!(
+                        out,
+                        "  subrule {}: {} of {} passed",
+                        subrule.subrule_number,
+                        subrule.actual_count,
+                        count(subrule.expected_count as usize, "clause")
+                    )?;
+                    for clause in &subrule.clauses {
+                        write_clause(out, clause)?;
+                    }
+                }
+            }
+        }
+        Format::Json => {
+            let json = Json::object([
+                (
+                    "object",
+                    Json::object([
+                        ("type", obj.obj_type.clone().into()),
+                        ("index", obj.index.into()),
+                        ("attributes", attributes_json(&obj.attributes)),
+                    ]),
+                ),
+                (
+                    "rules",
+                    Json::Array(
+                        explanations
+                            .iter()
+                            .map(|e| explanation_json(e, rule_name(&rules, e.rule_number)))
+                            .collect(),
+                    ),
+                ),
+            ]);
+            writeln!(out, "{}", json)?;
+        }
+    }
+
+    Ok(EXIT_OK)
+}
+
+fn fmt(args: &Args, out: &mut dyn Write) -> Result<u8, CliError> {
+    check_config_dir(args)?;
+
+    let mut files = Vec::new();
+    for entry in glob::glob(&format!("{}/*.rules", args.config_dir)).map_err(RulesError::from)? {
+        let path = entry.map_err(RulesError::from)?;
+        let content = fs::read_to_string(&path)?;
+        let name = path.display().to_string();
+
+        // Format every file before writing any, so a syntax error leaves them all untouched
+        let formatted = Rules::format_rules(&name, &content)?;
+        if formatted != content {
+            files.push((path, name, formatted));
+        }
+    }
+
+    for (path, name, formatted) in &files {
+        if args.check {
+            if args.format == Format::Text {
+                writeln!(out, "{} is not formatted", name)?;
+            }
+        } else {
+            fs::write(path, formatted)?;
+            if args.format == Format::Text {
+                writeln!(out, "formatted {}", name)?;
+            }
+        }
+    }
+
+    if args.format == Format::Json {
+        let names: Vec<&str> = files.iter().map(|(_, name, _)| name.as_str()).collect();
+        let json = Json::object([("check", args.check.into()), ("unformatted", names.into())]);
+        writeln!(out, "{}", json)?;
+    }
+
+    Ok(if args.check && !files.is_empty() {
+        EXIT_FAILURE
+    } else {
+        EXIT_OK
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs the CLI, returning the exit code, stdout and stderr
+    fn run_cli(args: &[&str]) -> (u8, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let code = run(&args, &mut out, &mut err);
+        (
+            code,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    fn setup_test_env(test_name: &str, rules: &str, objects: &str) -> String {
+        let test_dir = format!("src/cli/test_config/{}", test_name);
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+
+        fs::write(
+            format!("{}/test.tags", test_dir),
+            "- colour: red, blue, green\n- size: small, large\n- doors: integer(2..5)",
+        )
+        .unwrap();
+        fs::write(format!("{}/test.rules", test_dir), rules).unwrap();
+        fs::write(format!("{}/test.yaml", test_dir), objects).unwrap();
+        test_dir
+    }
+
+    fn cleanup_test_env(test_dir: &str) {
+        if Path::new(test_dir).exists() {
+            let _ = fs::remove_dir_all(test_dir);
+        }
+    }
+
+    const OBJECTS: &str = "objects:\n  cars:\n    - colour: red\n      doors: 4\n    - colour: blue\n      size: small\n";
+
+    #[test]
+    fn test_cli_validate() {
+        let (code, out, _) = run_cli(&["validate"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "config: 0 errors, 0 warnings\n");
+
+        let test_dir = setup_test_env(
+            "test_cli_validate",
+            "- colour = red\n- colour = purple\n- colour = red",
+            "objects:\n  cars:\n    - doors: 9\n",
+        );
+
+        let (code, out, _) = run_cli(&["validate", "--config-dir", &test_dir]);
+        assert_eq!(code, EXIT_FAILURE);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with(&format!("error: {}/test.rules:2:12:", test_dir)));
+        assert!(lines[1].starts_with("warning:"));
+        assert!(lines[2].starts_with(&format!("error: {}/test.yaml:3:", test_dir)));
+        assert_eq!(lines[3], format!("{}: 2 errors, 1 warning", test_dir));
+
+        let (code, out, _) = run_cli(&["validate", "--config-dir", &test_dir, "--format", "json"]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(out.starts_with(
+            r#"{"valid":false,"errors":2,"warnings":1,"diagnostics":[{"severity":"error","#
+        ));
+        assert!(out.contains(r#""line":2,"column":12,"#));
+
+        cleanup_test_env(&test_dir);
+    }
+
+    #[test]
+    fn test_cli_eval() {
+        let test_dir = setup_test_env(
+            "test_cli_eval",
+            "- colour = red & doors >= 3\n- colour = red, blue",
+            OBJECTS,
+        );
+
+        let (code, out, _) = run_cli(&["eval", "--config-dir", &test_dir]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "cars[0]: rules 0, 1\ncars[1]: rules 1\n");
+
+        let (_, out, _) = run_cli(&["eval", "--config-dir", &test_dir, "--attr", "colour=green"]);
+        assert_eq!(out, "object: no match\n");
+
+        let (_, out, _) = run_cli(&[
+            "eval",
+            "--config-dir",
+            &test_dir,
+            "--object",
+            "cars:1",
+            "--format",
+            "json",
+        ]);
+        assert_eq!(
+            out,
+            "{\"objects\":[{\"type\":\"cars\",\"index\":1,\"rules\":[{\"rule\":1,\"name\":null,\"subrules\":[2]}]}]}\n"
+        );
+
+        let (code, _, err) =
+            run_cli(&["eval", "--config-dir", &test_dir, "--attr", "colour=purple"]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.contains("invalid value"));
+
+        cleanup_test_env(&test_dir);
+
+        // Named rules are shown with their names
+        let test_dir = setup_test_env(
+            "test_cli_eval_named",
+            "@owner: sales\n- [red_cars] colour = red\n- colour = red, blue",
+            OBJECTS,
+        );
+
+        let (_, out, _) = run_cli(&["eval", "--config-dir", &test_dir, "--object", "cars:0"]);
+        assert_eq!(out, "cars[0]: rules 0 [red_cars], 1\n");
+
+        let (_, out, _) = run_cli(&[
+            "explain",
+            "--config-dir",
+            &test_dir,
+            "--object",
+            "cars:0",
+            "--rule",
+            "0",
+            "--format",
+            "json",
+        ]);
+        assert!(out.contains(r#""rules":[{"rule":0,"name":"red_cars","matched":true,"#));
+
+        cleanup_test_env(&test_dir);
+    }
+
+    #[test]
+    fn test_cli_explain() {
+        let test_dir = setup_test_env(
+            "test_cli_explain",
+            "- colour = red & doors >= 3\n- colour = red, blue",
+            OBJECTS,
+        );
+
+        let (code, out, _) = run_cli(&["explain", "--config-dir", &test_dir, "--object", "cars:1"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "cars[1]
+  colour: blue
+  size: small
+rule 0: no match
+  subrule 0: 0 of 2 clauses passed
+    FAIL  colour = red (object: blue)
+    FAIL  doors >= 3 (object: none)
+rule 1: match
+  subrule 1: 0 of 1 clause passed
+    FAIL  colour = red (object: blue)
+  subrule 2: 1 of 1 clause passed
+    pass  colour = blue (object: blue)
+"
+        );
+
+        let (_, out, _) = run_cli(&[
+            "explain",
+            "--config-dir",
+            &test_dir,
+            "--attr",
+            "colour=red",
+            "--rule",
+            "0",
+            "--format",
+            "json",
+        ]);
+        assert_eq!(
+            out,
+            concat!(
+                r#"{"object":{"type":null,"index":null,"attributes":{"colour":["red"]}},"#,
+                r#""rules":[{"rule":0,"name":null,"matched":false,"subrules":[{"subrule":0,"matched":false,"#,
+                r#""expected_count":2,"actual_count":1,"clauses":["#,
+                r#"{"tag":"colour","op":"=","value":"red","object_values":["red"],"passed":true},"#,
+                r#"{"tag":"doors","op":">=","value":"3","object_values":[],"passed":false}]}]}]}"#,
+                "\n"
+            )
+        );
+
+        let (code, _, err) = run_cli(&["explain", "--config-dir", &test_dir, "--object", "cars:5"]);
+        assert_eq!(code, EXIT_USAGE);
+        assert_eq!(err, "error: No object cars[5] is loaded\n");
+
+        let (code, _, err) = run_cli(&[
+            "explain",
+            "--config-dir",
+            &test_dir,
+            "--object",
+            "cars:0",
+            "--rule",
+            "2",
+        ]);
+        assert_eq!(code, EXIT_USAGE);
+        assert_eq!(err, "error: No rule 2 is loaded, there are 2 rules\n");
+
+        cleanup_test_env(&test_dir);
+    }
+
+    #[test]
+    fn test_cli_decide() {
+        let test_dir = setup_test_env(
+            "test_cli_decide",
+            "- colour = red => discount: 5\n@priority: 1\n- [big_red] colour = red & doors >= 3 => tier: gold, discount: 10\n- colour = blue",
+            OBJECTS,
+        );
+
+        let (code, out, _) = run_cli(&["decide", "--config-dir", &test_dir]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "cars[0]: rule 0 => discount: 5\ncars[1]: rule 2 => no outcome\n"
+        );
+
+        let (_, out, _) = run_cli(&[
+            "decide",
+            "--config-dir",
+            &test_dir,
+            "--object",
+            "cars:0",
+            "--strategy",
+            "priority",
+        ]);
+        assert_eq!(
+            out,
+            "cars[0]: rule 1 [big_red] => discount: 10, tier: gold\n"
+        );
+
+        let (_, out, _) = run_cli(&[
+            "decide",
+            "--config-dir",
+            &test_dir,
+            "--attr",
+            "colour=red",
+            "--strategy=all",
+            "--format",
+            "json",
+        ]);
+        assert_eq!(
+            out,
+            concat!(
+                r#"{"strategy":"all","objects":[{"type":null,"index":null,"decided":true,"rules":["#,
+                r#"{"rule":0,"name":null,"outcome":{"discount":"5"}}],"conflicts":[]}]}"#,
+                "\n"
+            )
+        );
+
+        cleanup_test_env(&test_dir);
+
+        // Rules of the same priority conflict, and --strategy unique refuses to pick one
+        let test_dir = setup_test_env(
+            "test_cli_decide_conflicts",
+            "- colour = red => tier: silver\n- doors >= 3 => tier: gold\n",
+            OBJECTS,
+        );
+
+        let (code, out, _) = run_cli(&[
+            "decide",
+            "--config-dir",
+            &test_dir,
+            "--object",
+            "cars:0",
+            "--strategy",
+            "priority",
+        ]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "cars[0]: rule 0 => tier: silver\ncars[0]: conflict between rules 0, 1 at priority 0\n"
+        );
+
+        let (code, out, _) =
+            run_cli(&["decide", "--config-dir", &test_dir, "--strategy", "unique"]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(
+            out,
+            "cars[0]: no single rule wins, rules 0, 1 at priority 0 all match\ncars[1]: no match\n"
+        );
+
+        cleanup_test_env(&test_dir);
+    }
+
+    #[test]
+    fn test_cli_eval_cached() {
+        let test_dir = setup_test_env("test_cli_eval_cached", "- colour = red", OBJECTS);
+        let cache_file = format!("{}/rules.cache", test_dir);
+
+        for _ in 0..2 {
+            let (code, out, _) =
+                run_cli(&["eval", "--config-dir", &test_dir, "--cache", &cache_file]);
+            assert_eq!(code, EXIT_OK);
+            assert_eq!(out, "cars[0]: rules 0\ncars[1]: no match\n");
+            assert!(Path::new(&cache_file).exists());
+        }
+
+        // A stale cache is rewritten
+        fs::write(format!("{}/test.rules", test_dir), "- colour = blue").unwrap();
+        let (_, out, _) = run_cli(&["eval", "--config-dir", &test_dir, "--cache", &cache_file]);
+        assert_eq!(out, "cars[0]: no match\ncars[1]: rules 0\n");
+
+        cleanup_test_env(&test_dir);
+    }
+
+    #[test]
+    fn test_cli_fmt() {
+        let test_dir = setup_test_env("test_cli_fmt", "# Rules\n-colour=RED&doors>=3\n", OBJECTS);
+        let rules_file = format!("{}/test.rules", test_dir);
+
+        let (code, out, _) = run_cli(&["fmt", "--check", "--config-dir", &test_dir]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(out, format!("{} is not formatted\n", rules_file));
+        assert_eq!(
+            fs::read_to_string(&rules_file).unwrap(),
+            "# Rules\n-colour=RED&doors>=3\n"
+        );
+
+        let (code, out, _) = run_cli(&["fmt", "--config-dir", &test_dir]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, format!("formatted {}\n", rules_file));
+        assert_eq!(
+            fs::read_to_string(&rules_file).unwrap(),
+            "# Rules\n- colour = red & doors >= 3\n"
+        );
+
+        let (code, out, _) = run_cli(&[
+            "fmt",
+            "--check",
+            "--config-dir",
+            &test_dir,
+            "--format",
+            "json",
+        ]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "{\"check\":true,\"unformatted\":[]}\n");
+
+        fs::write(&rules_file, "- colour = red &").unwrap();
+        let (code, _, err) = run_cli(&["fmt", "--config-dir", &test_dir]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.starts_with(&format!("error: {}:1:16:", rules_file)));
+        assert!(err.contains("^"));
+
+        cleanup_test_env(&test_dir);
+    }
+
+    #[test]
+    fn test_cli_usage_and_io_errors() {
+        let (code, _, err) = run_cli(&["lint"]);
+        assert_eq!(code, EXIT_USAGE);
+        assert!(err.starts_with("error: Unknown command 'lint'\n\nUsage:"));
+
+        let (code, out, _) = run_cli(&["--help"]);
+        assert_eq!(code, EXIT_OK);
+        assert!(out.starts_with("Usage:"));
+
+        let (code, _, err) = run_cli(&["eval", "--config-dir", "does/not/exist"]);
+        assert_eq!(code, EXIT_IO);
+        assert!(err.contains("Config directory 'does/not/exist' does not exist"));
+    }
+}
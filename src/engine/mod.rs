@@ -0,0 +1,1093 @@
+// Matching engine -- evaluates objects against DNF subrules
+use crate::err::RulesError;
+use crate::parser::tags;
+use crate::parser::types::Interval;
+use crate::types::{
+    ClauseTrace, ComparisonOp, Conflict, Decision, EvaluationResult, NearMiss, Object, ObjectMatch,
+    Rule, RuleExplanation, RuleMatch, RuleNumber, Strategy, SubRule, SubRuleNumber, SubRuleTrace,
+    TagName, TagValues, TypedObject,
+};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// Tag-to-subrule maps, so an object is only checked against subrules that can match it.
+// A subrule only matches if all of its clauses do, so it is indexed by a single clause
+// that needs the object to have the tag:
+// ISEQ -> by tag name and value -- e.g. "colour" -> "blue" -> [SR1]
+// GRTH, GREQ, LETH, LEEQ, ISIN -> by tag name, as any numeric value might compare true
+// NOEQ and NOIN clauses match objects without the tag, so a subrule with only those
+// clauses can't be indexed and is checked against every object.
+#[derive(Default)]
+struct SubRuleIndex {
+    m_values: HashMap<TagName, HashMap<String, Vec<SubRuleNumber>>>,
+    m_numeric: HashMap<TagName, Vec<SubRuleNumber>>,
+    m_unindexed: Vec<SubRuleNumber>,
+    // Tags declared integer or decimal, whose values are keyed by number
+    m_numeric_tags: HashSet<TagName>,
+}
+
+#[derive(Default)]
+pub struct Engine {
+    m_subrules: HashMap<SubRuleNumber, SubRule>,
+    // Subrule numbers in ascending order, so results are deterministic
+    m_order: Vec<SubRuleNumber>,
+    // Subrule numbers of each rule, in ascending order
+    m_by_rule: BTreeMap<RuleNumber, Vec<SubRuleNumber>>,
+    m_index: SubRuleIndex,
+    // Tags declared integer or decimal, whose values are compared as numbers
+    m_numeric_tags: HashSet<TagName>,
+    // Names and metadata of the rules, in rule number order
+    m_rules: Vec<Rule>,
+}
+
+impl SubRuleIndex {
+    // Key values are indexed by, so numerically equal values of a numeric tag share a key --
+    // e.g. "5.0" -> "5". Values of other tags are keyed as they are.
+    fn value_key(&self, tag_name: &str, value: &str) -> String {
+        if !self.m_numeric_tags.contains(tag_name) {
+            return value.to_string();
+        }
+        match value.parse::<f64>() {
+            Ok(number) => number.to_string(),
+            Err(_) => value.to_string(),
+        }
+    }
+
+    // Built in subrule order, so every list is in ascending order
+    fn new(
+        subrules: &HashMap<SubRuleNumber, SubRule>,
+        order: &[SubRuleNumber],
+        numeric_tags: HashSet<TagName>,
+    ) -> Self {
+        let mut index = SubRuleIndex {
+            m_numeric_tags: numeric_tags,
+            ..SubRuleIndex::default()
+        };
+
+        for n in order {
+            let subrule = &subrules[n];
+            let clauses = || subrule.comparison_ops.iter().zip(&subrule.tag_kvs);
+
+            // Equality is the most selective, so prefer it over an ordering clause
+            let equals = clauses().find(|(op, _)| **op == ComparisonOp::ISEQ);
+            let ordering = clauses().find(|(op, _)| {
+                !matches!(
+                    op,
+                    ComparisonOp::ISEQ | ComparisonOp::NOEQ | ComparisonOp::NOIN
+                )
+            });
+
+            match (equals, ordering) {
+                (Some((_, (tag_name, tag_value))), _) => {
+                    let key = index.value_key(tag_name, tag_value);
+                    index
+                        .m_values
+                        .entry(tag_name.clone())
+                        .or_default()
+                        .entry(key)
+                        .or_default()
+                        .push(*n)
+                }
+                (None, Some((_, (tag_name, _)))) => index
+                    .m_numeric
+                    .entry(tag_name.clone())
+                    .or_default()
+                    .push(*n),
+                (None, None) => index.m_unindexed.push(*n),
+            }
+        }
+
+        index
+    }
+
+    // Subrules that might match a normalised object, in ascending order
+    fn candidates(&self, obj: &Object) -> Vec<SubRuleNumber> {
+        let mut candidates: Vec<SubRuleNumber> = self.m_unindexed.clone();
+
+        for (tag_name, values) in obj {
+            if let Some(by_value) = self.m_values.get(tag_name) {
+                for value in values {
+                    if let Some(subrules) = by_value.get(&self.value_key(tag_name, value)) {
+                        candidates.extend(subrules);
+                    }
+                }
+            }
+            if let Some(subrules) = self.m_numeric.get(tag_name) {
+                candidates.extend(subrules);
+            }
+        }
+
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
+impl Engine {
+    // Engine for subrules parsed against these tags, so values of the integer and decimal
+    // tags are compared as numbers and the rest as strings
+    pub fn new(
+        subrules: HashMap<SubRuleNumber, SubRule>,
+        tags: &HashMap<TagName, TagValues>,
+    ) -> Self {
+        let mut order: Vec<SubRuleNumber> = subrules.keys().copied().collect();
+        order.sort_unstable();
+        let numeric_tags: HashSet<TagName> = tags
+            .iter()
+            .filter(|(_, values)| tags::numeric_tag(values).is_some())
+            .map(|(name, _)| name.to_lowercase())
+            .collect();
+        let index = SubRuleIndex::new(&subrules, &order, numeric_tags.clone());
+
+        let mut by_rule: BTreeMap<RuleNumber, Vec<SubRuleNumber>> = BTreeMap::new();
+        for n in &order {
+            by_rule.entry(subrules[n].rule_number).or_default().push(*n);
+        }
+
+        Engine {
+            m_subrules: subrules,
+            m_order: order,
+            m_by_rule: by_rule,
+            m_index: index,
+            m_numeric_tags: numeric_tags,
+            m_rules: Vec::new(),
+        }
+    }
+
+    // Same engine, with the names and metadata of its rules
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Self {
+        self.m_rules = rules;
+        self
+    }
+
+    // Rules in number order, or an empty slice if they were loaded without metadata
+    pub fn rules(&self) -> &[Rule] {
+        &self.m_rules
+    }
+
+    // Rule with this number, or an anonymous rule if it has no metadata
+    fn rule(&self, rule_number: RuleNumber) -> Rule {
+        self.m_rules
+            .get(rule_number as usize)
+            .filter(|rule| rule.rule_number == rule_number)
+            .cloned()
+            .unwrap_or(Rule {
+                rule_number,
+                ..Rule::default()
+            })
+    }
+
+    // Subrules in number order
+    pub fn subrules(&self) -> Vec<&SubRule> {
+        self.m_order.iter().map(|n| &self.m_subrules[n]).collect()
+    }
+
+    // Rules are numbered in order from 0, so this is one more than the highest rule number
+    pub fn rule_count(&self) -> usize {
+        self.m_by_rule
+            .keys()
+            .next_back()
+            .map_or(0, |rule_number| *rule_number as usize + 1)
+    }
+
+    // Object with lowercased attribute names and values, as rules are parsed case-insensitively
+    fn normalise_object(obj: &Object) -> Object {
+        let mut normalised: Object = HashMap::new();
+        for (name, values) in obj {
+            normalised
+                .entry(name.to_lowercase())
+                .or_default()
+                .extend(values.iter().map(|v| v.to_lowercase()));
+        }
+        normalised
+    }
+
+    // Values are equal if they are the same string, or for a numeric tag the same number --
+    // e.g. "5" and "5.0". Values of other tags are only equal as strings, so "1e1" isn't "10".
+    fn values_equal(&self, tag_name: &str, a: &str, b: &str) -> bool {
+        if a == b {
+            return true;
+        }
+        if !self.m_numeric_tags.contains(tag_name) {
+            return false;
+        }
+
+        match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    // A clause matches a multi-valued attribute if:
+    // ISEQ -> any of the object's values is equal to the clause value
+    // NOEQ -> none of the object's values are equal (a missing attribute is never equal)
+    // GRTH, GREQ, LETH, LEEQ -> any of the object's values compares true numerically
+    // (a missing or non-numeric attribute never compares true)
+    // ISIN -> any of the object's values is within the interval
+    // NOIN -> none of the object's values are within the interval (so a missing attribute
+    // matches)
+    fn clause_matches(
+        &self,
+        obj: &Object,
+        op: ComparisonOp,
+        tag_name: &str,
+        tag_value: &str,
+    ) -> bool {
+        let values = obj.get(tag_name).map(Vec::as_slice).unwrap_or_default();
+
+        let ordering = |cmp: fn(f64, f64) -> bool| {
+            let Ok(expected) = tag_value.parse::<f64>() else {
+                return false;
+            };
+            values
+                .iter()
+                .filter_map(|v| v.parse::<f64>().ok())
+                .any(|actual| cmp(actual, expected))
+        };
+
+        // A value is only within the interval if it is a number within both bounds
+        let within = || {
+            Interval::parse_brackets(tag_value).is_ok_and(|interval| {
+                values
+                    .iter()
+                    .filter_map(|v| v.parse::<f64>().ok())
+                    .any(|actual| interval.contains(actual))
+            })
+        };
+
+        match op {
+            ComparisonOp::ISEQ => values
+                .iter()
+                .any(|v| self.values_equal(tag_name, v, tag_value)),
+            ComparisonOp::NOEQ => !values
+                .iter()
+                .any(|v| self.values_equal(tag_name, v, tag_value)),
+            ComparisonOp::GRTH => ordering(|a, b| a > b),
+            ComparisonOp::GREQ => ordering(|a, b| a >= b),
+            ComparisonOp::LETH => ordering(|a, b| a < b),
+            ComparisonOp::LEEQ => ordering(|a, b| a <= b),
+            ComparisonOp::ISIN => within(),
+            ComparisonOp::NOIN => !within(),
+        }
+    }
+
+    // Step 4: actual_count of every subrule the index finds for the object, incremented for
+    // each clause that matches. Counts are kept per call rather than on the stored subrules,
+    // so the engine can be shared between callers.
+    fn count_matches(&self, obj: &Object) -> Vec<(SubRuleNumber, i32)> {
+        self.m_index
+            .candidates(obj)
+            .iter()
+            .map(|n| {
+                let subrule = &self.m_subrules[n];
+                let mut actual_count = 0;
+
+                for (op, (tag_name, tag_value)) in
+                    subrule.comparison_ops.iter().zip(&subrule.tag_kvs)
+                {
+                    if self.clause_matches(obj, *op, tag_name, tag_value) {
+                        actual_count += 1;
+                    }
+                }
+
+                (*n, actual_count)
+            })
+            .collect()
+    }
+
+    // Step 5: subrules whose actual_count == expected_count, in ascending order
+    pub fn matching_subrules(&self, obj: &Object) -> Vec<SubRuleNumber> {
+        let obj = Self::normalise_object(obj);
+
+        self.count_matches(&obj)
+            .into_iter()
+            .filter(|(n, actual_count)| *actual_count == self.m_subrules[n].expected_count)
+            .map(|(n, _)| n)
+            .collect()
+    }
+
+    // A rule matches if any of its subrules match
+    pub fn match_object(&self, obj: &Object) -> Vec<RuleMatch> {
+        let mut rules: BTreeMap<RuleNumber, Vec<SubRuleNumber>> = BTreeMap::new();
+        for subrule_number in self.matching_subrules(obj) {
+            let rule_number = self.m_subrules[&subrule_number].rule_number;
+            rules.entry(rule_number).or_default().push(subrule_number);
+        }
+
+        rules
+            .into_iter()
+            .map(|(rule_number, subrules)| RuleMatch {
+                rule_number,
+                subrules,
+                rule: self.rule(rule_number),
+            })
+            .collect()
+    }
+
+    // Matching rules that share the given priority, if there are several
+    fn conflict(matches: &[RuleMatch], priority: i32) -> Option<Conflict> {
+        let rule_numbers: Vec<RuleNumber> = matches
+            .iter()
+            .filter(|m| m.rule.priority_or_default() == priority)
+            .map(|m| m.rule_number)
+            .collect();
+
+        (rule_numbers.len() > 1).then_some(Conflict {
+            priority,
+            rule_numbers,
+        })
+    }
+
+    // Rules the object matched, chosen by the strategy, and the conflict between them.
+    // Ties between rules of the highest priority go to the first in rule order, so the same
+    // object always gets the same decision, and are reported as a conflict, unless the
+    // strategy is Unique. Priorities don't matter to FirstMatch and AllMatches, so they have
+    // no conflicts.
+    pub fn decide(&self, obj: &Object, strategy: Strategy) -> Result<Decision, RulesError> {
+        let mut matches = self.match_object(obj);
+        let mut conflicts = Vec::new();
+
+        match strategy {
+            Strategy::AllMatches => {}
+            Strategy::FirstMatch => matches.truncate(1),
+            Strategy::HighestPriority | Strategy::Unique => {
+                let Some(highest) = matches.iter().map(|m| m.rule.priority_or_default()).max()
+                else {
+                    return Ok(Decision::default());
+                };
+                if let Some(conflict) = Self::conflict(&matches, highest) {
+                    if strategy == Strategy::Unique {
+                        return Err(RulesError::RuleConflict(conflict));
+                    }
+                    conflicts.push(conflict);
+                }
+
+                // Matches are in rule order, so the first with the highest priority wins
+                let winner = matches
+                    .iter()
+                    .find(|m| m.rule.priority_or_default() == highest)
+                    .map(|m| m.rule_number);
+                matches.retain(|m| Some(m.rule_number) == winner);
+            }
+        }
+
+        Ok(Decision { matches, conflicts })
+    }
+
+    // Steps 4 and 5 for a single rule, keeping the result of every clause.
+    // None if there is no rule with this number.
+    pub fn explain(&self, obj: &Object, rule_number: RuleNumber) -> Option<RuleExplanation> {
+        let subrules = self.m_by_rule.get(&rule_number)?;
+        Some(self.explain_subrules(&Self::normalise_object(obj), rule_number, subrules))
+    }
+
+    // Explanation of every rule, in rule order
+    pub fn explain_all(&self, obj: &Object) -> Vec<RuleExplanation> {
+        let obj = Self::normalise_object(obj);
+
+        self.m_by_rule
+            .iter()
+            .map(|(rule_number, subrules)| self.explain_subrules(&obj, *rule_number, subrules))
+            .collect()
+    }
+
+    // Explanation of a rule from its subrules, for a normalised object
+    fn explain_subrules(
+        &self,
+        obj: &Object,
+        rule_number: RuleNumber,
+        subrules: &[SubRuleNumber],
+    ) -> RuleExplanation {
+        let subrules: Vec<SubRuleTrace> = subrules
+            .iter()
+            .map(|n| {
+                let subrule = &self.m_subrules[n];
+
+                let clauses: Vec<ClauseTrace> = subrule
+                    .comparison_ops
+                    .iter()
+                    .zip(&subrule.tag_kvs)
+                    .map(|(op, (tag_name, tag_value))| ClauseTrace {
+                        tag_name: tag_name.clone(),
+                        comparison_op: *op,
+                        tag_value: tag_value.clone(),
+                        object_values: obj.get(tag_name).cloned().unwrap_or_default(),
+                        passed: self.clause_matches(obj, *op, tag_name, tag_value),
+                    })
+                    .collect();
+
+                SubRuleTrace {
+                    subrule_number: *n,
+                    expected_count: subrule.expected_count,
+                    actual_count: clauses.iter().filter(|c| c.passed).count() as i32,
+                    clauses,
+                }
+            })
+            .collect();
+
+        RuleExplanation {
+            rule_number,
+            subrules,
+        }
+    }
+
+    // Unmatched rules with at least one satisfied clause, closest first, up to `limit`.
+    // A rule is as close as its subrule with the most satisfied clauses, and ties are
+    // broken by fewest missing clauses, then rule order.
+    pub fn near_misses(&self, obj: &Object, limit: usize) -> Vec<NearMiss> {
+        let mut near_misses: Vec<NearMiss> = self
+            .explain_all(obj)
+            .into_iter()
+            .filter(|explanation| !explanation.is_match())
+            .filter_map(|explanation| {
+                let closest = explanation
+                    .subrules
+                    .into_iter()
+                    .filter(|subrule| subrule.actual_count > 0)
+                    .min_by_key(|subrule| {
+                        (
+                            -subrule.actual_count,
+                            subrule.expected_count - subrule.actual_count,
+                        )
+                    })?;
+
+                Some(NearMiss {
+                    rule_number: explanation.rule_number,
+                    subrule_number: closest.subrule_number,
+                    satisfied_count: closest.actual_count,
+                    expected_count: closest.expected_count,
+                    missing_clauses: closest.clauses.into_iter().filter(|c| !c.passed).collect(),
+                })
+            })
+            .collect();
+
+        // Stable, so rules stay in order within a tie
+        near_misses.sort_by_key(|m| (-m.satisfied_count, m.expected_count - m.satisfied_count));
+        near_misses.truncate(limit);
+        near_misses
+    }
+
+    // Matches for each object, in the same order as the objects
+    pub fn evaluate(&self, objs: &[TypedObject]) -> EvaluationResult {
+        let objects = objs
+            .iter()
+            .map(|obj| ObjectMatch {
+                obj_type: obj.obj_type.clone(),
+                index: obj.index,
+                rules: self.match_object(&obj.attributes),
+            })
+            .collect();
+
+        EvaluationResult { objects }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_subrule(rule_number: RuleNumber, clauses: &[(ComparisonOp, &str, &str)]) -> SubRule {
+        SubRule {
+            rule_number,
+            expected_count: clauses.len() as i32,
+            actual_count: 0,
+            comparison_ops: clauses.iter().map(|(op, _, _)| *op).collect(),
+            tag_kvs: clauses
+                .iter()
+                .map(|(_, name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    // Tags declared as integers, so their values compare as numbers
+    fn numeric_tags(names: &[&str]) -> HashMap<TagName, TagValues> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), vec!["integer(..)".to_string()]))
+            .collect()
+    }
+
+    fn rule_numbers(rules: Vec<RuleMatch>) -> Vec<RuleNumber> {
+        rules.iter().map(|r| r.rule_number).collect()
+    }
+
+    fn make_object(attributes: &[(&str, &[&str])]) -> Object {
+        attributes
+            .iter()
+            .map(|(name, values)| {
+                (
+                    name.to_string(),
+                    values.iter().map(|v| v.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    // README example: (colour=blue & shape!circle) | (colour=red & shape!circle)
+    fn readme_engine() -> Engine {
+        let mut subrules = HashMap::new();
+        subrules.insert(
+            0,
+            make_subrule(
+                0,
+                &[
+                    (ComparisonOp::ISEQ, "colour", "blue"),
+                    (ComparisonOp::NOEQ, "shape", "circle"),
+                ],
+            ),
+        );
+        subrules.insert(
+            1,
+            make_subrule(
+                0,
+                &[
+                    (ComparisonOp::ISEQ, "colour", "red"),
+                    (ComparisonOp::NOEQ, "shape", "circle"),
+                ],
+            ),
+        );
+        subrules.insert(
+            2,
+            make_subrule(1, &[(ComparisonOp::ISEQ, "colour", "green")]),
+        );
+        Engine::new(subrules, &HashMap::new())
+    }
+
+    #[test]
+    fn test_matching_subrules_readme_example() {
+        let engine = readme_engine();
+        let obj = make_object(&[
+            ("colour", &["blue"]),
+            ("shape", &["square"]),
+            ("size", &["large"]),
+        ]);
+
+        assert_eq!(engine.matching_subrules(&obj), vec![0]);
+        // Only subrules indexed under the object's values are counted
+        assert_eq!(engine.count_matches(&obj), vec![(0, 2)]);
+        let explanation = engine.explain(&obj, 0).unwrap();
+        assert_eq!(explanation.subrules[1].actual_count, 1);
+    }
+
+    #[test]
+    fn test_matching_rules_no_match() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["blue"]), ("shape", &["circle"])]);
+
+        assert!(rule_numbers(engine.match_object(&obj)).is_empty());
+    }
+
+    #[test]
+    fn test_matching_rules_multi_valued_attribute() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["red", "green"]), ("shape", &["rectangle"])]);
+
+        assert_eq!(engine.matching_subrules(&obj), vec![1, 2]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_matching_rules_not_equals_any_value() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["red"]), ("shape", &["square", "circle"])]);
+
+        assert!(rule_numbers(engine.match_object(&obj)).is_empty());
+    }
+
+    #[test]
+    fn test_matching_rules_not_equals_missing_attribute() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["red"])]);
+
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![0]);
+    }
+
+    #[test]
+    fn test_matching_rules_case_insensitive() {
+        let engine = readme_engine();
+        let obj = make_object(&[("Colour", &["Green"])]);
+
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1]);
+    }
+
+    #[test]
+    fn test_match_object_groups_subrules_by_rule() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["blue", "red"]), ("shape", &["square"])]);
+
+        assert_eq!(
+            engine.match_object(&obj),
+            vec![RuleMatch {
+                rule_number: 0,
+                subrules: vec![0, 1],
+                rule: Rule {
+                    rule_number: 0,
+                    ..Rule::default()
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_decide_strategies() {
+        let mut subrules = HashMap::new();
+        for rule_number in 0..4 {
+            subrules.insert(
+                rule_number,
+                make_subrule(rule_number, &[(ComparisonOp::ISEQ, "colour", "red")]),
+            );
+        }
+        let rules: Vec<Rule> = [None, Some(5), Some(-1), Some(5)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, priority)| Rule {
+                rule_number: i as RuleNumber,
+                priority,
+                ..Rule::default()
+            })
+            .collect();
+        let engine = Engine::new(subrules, &HashMap::new()).with_rules(rules);
+        let obj = make_object(&[("colour", &["red"])]);
+
+        let decided = |strategy| rule_numbers(engine.decide(&obj, strategy).unwrap().matches);
+        assert_eq!(decided(Strategy::FirstMatch), vec![0]);
+        assert_eq!(decided(Strategy::AllMatches), vec![0, 1, 2, 3]);
+        // Rules 1 and 3 share the highest priority, so the first of them wins
+        assert_eq!(decided(Strategy::HighestPriority), vec![1]);
+
+        let none = engine
+            .decide(&make_object(&[("colour", &["blue"])]), Strategy::FirstMatch)
+            .unwrap();
+        assert!(!none.is_match());
+        assert_eq!(none.outcome(), None);
+    }
+
+    #[test]
+    fn test_decide_conflicts() {
+        // Every rule matches, with the priorities given
+        let engine = |priorities: &[Option<i32>]| {
+            let mut subrules = HashMap::new();
+            let mut rules = Vec::new();
+            for (i, priority) in priorities.iter().enumerate() {
+                let rule_number = i as RuleNumber;
+                subrules.insert(
+                    rule_number,
+                    make_subrule(rule_number, &[(ComparisonOp::ISEQ, "colour", "red")]),
+                );
+                rules.push(Rule {
+                    rule_number,
+                    priority: *priority,
+                    ..Rule::default()
+                });
+            }
+            Engine::new(subrules, &HashMap::new()).with_rules(rules)
+        };
+        let obj = make_object(&[("colour", &["red"])]);
+
+        // Rule 3 wins outright, so the tie between rules 0 and 2 below it isn't a conflict
+        let decision = engine(&[Some(5), None, Some(5), Some(9)])
+            .decide(&obj, Strategy::Unique)
+            .unwrap();
+        assert_eq!(rule_numbers(decision.matches.clone()), vec![3]);
+        assert!(!decision.has_conflicts());
+        let decision = engine(&[Some(5), None, Some(5), Some(9)])
+            .decide(&obj, Strategy::HighestPriority)
+            .unwrap();
+        assert!(!decision.has_conflicts());
+
+        // No single rule has the highest priority
+        let tied = engine(&[Some(5), None, Some(5)]);
+        match tied.decide(&obj, Strategy::Unique) {
+            Err(RulesError::RuleConflict(conflict)) => {
+                assert_eq!(conflict.to_string(), "rules 0, 2 at priority 5")
+            }
+            other => panic!("Expected RuleConflict, got {:?}", other),
+        }
+        let decision = tied.decide(&obj, Strategy::HighestPriority).unwrap();
+        assert_eq!(rule_numbers(decision.matches.clone()), vec![0]);
+        assert_eq!(
+            decision.conflicts,
+            vec![Conflict {
+                priority: 5,
+                rule_numbers: vec![0, 2],
+            }]
+        );
+
+        // Rules without a priority share priority 0
+        let decision = engine(&[None, None])
+            .decide(&obj, Strategy::HighestPriority)
+            .unwrap();
+        assert_eq!(decision.conflicts[0].rule_numbers, vec![0, 1]);
+        assert!(
+            !engine(&[None])
+                .decide(&obj, Strategy::Unique)
+                .unwrap()
+                .has_conflicts()
+        );
+
+        // Priorities don't matter to FirstMatch and AllMatches
+        for strategy in [Strategy::FirstMatch, Strategy::AllMatches] {
+            assert!(!tied.decide(&obj, strategy).unwrap().has_conflicts());
+        }
+    }
+
+    #[test]
+    fn test_evaluate_resets_counts() {
+        let engine = readme_engine();
+        let objs = vec![
+            TypedObject {
+                obj_type: "shapes".to_string(),
+                index: 0,
+                attributes: make_object(&[("colour", &["blue"]), ("shape", &["square"])]),
+            },
+            TypedObject {
+                obj_type: "shapes".to_string(),
+                index: 1,
+                attributes: make_object(&[("colour", &["blue"]), ("shape", &["circle"])]),
+            },
+        ];
+
+        let result = engine.evaluate(&objs);
+        assert_eq!(result.objects.len(), 2);
+        assert_eq!(result.get("shapes", 0).unwrap().rule_numbers(), vec![0]);
+        assert!(!result.get("shapes", 1).unwrap().is_match());
+    }
+
+    #[test]
+    fn test_matching_rules_numeric_comparisons() {
+        let mut subrules = HashMap::new();
+        subrules.insert(0, make_subrule(0, &[(ComparisonOp::GRTH, "doors", "3")]));
+        subrules.insert(1, make_subrule(1, &[(ComparisonOp::GREQ, "doors", "3")]));
+        subrules.insert(2, make_subrule(2, &[(ComparisonOp::LETH, "doors", "3")]));
+        subrules.insert(3, make_subrule(3, &[(ComparisonOp::LEEQ, "doors", "3")]));
+        subrules.insert(4, make_subrule(4, &[(ComparisonOp::ISEQ, "doors", "3")]));
+        let engine = Engine::new(subrules, &numeric_tags(&["doors"]));
+
+        let obj = make_object(&[("doors", &["3"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1, 3, 4]);
+
+        let obj = make_object(&[("doors", &["5"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![0, 1]);
+
+        let obj = make_object(&[("doors", &["2.5"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![2, 3]);
+
+        let obj = make_object(&[("doors", &["3.0"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn test_matching_rules_string_values_compare_exactly() {
+        let mut subrules = HashMap::new();
+        subrules.insert(0, make_subrule(0, &[(ComparisonOp::ISEQ, "code", "inf")]));
+        subrules.insert(1, make_subrule(1, &[(ComparisonOp::ISEQ, "code", "10")]));
+        subrules.insert(2, make_subrule(2, &[(ComparisonOp::NOEQ, "code", "10")]));
+        let engine = Engine::new(subrules, &numeric_tags(&["doors"]));
+
+        let obj = make_object(&[("code", &["infinity"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![2]);
+
+        let obj = make_object(&[("code", &["1e1"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![2]);
+
+        let obj = make_object(&[("code", &["10"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1]);
+    }
+
+    #[test]
+    fn test_matching_rules_numeric_missing_attribute() {
+        let mut subrules = HashMap::new();
+        subrules.insert(0, make_subrule(0, &[(ComparisonOp::LETH, "doors", "3")]));
+        let engine = Engine::new(subrules, &HashMap::new());
+
+        let obj = make_object(&[("colour", &["red"])]);
+        assert!(rule_numbers(engine.match_object(&obj)).is_empty());
+    }
+
+    #[test]
+    fn test_matching_rules_intervals() {
+        // doors = 2..4, doors ! 2..4, price in (1.5, 10)
+        let mut subrules = HashMap::new();
+        subrules.insert(
+            0,
+            make_subrule(0, &[(ComparisonOp::ISIN, "doors", "[2,4]")]),
+        );
+        subrules.insert(
+            1,
+            make_subrule(1, &[(ComparisonOp::NOIN, "doors", "[2,4]")]),
+        );
+        subrules.insert(
+            2,
+            make_subrule(2, &[(ComparisonOp::ISIN, "price", "(1.5,10)")]),
+        );
+        let engine = Engine::new(subrules, &HashMap::new());
+
+        let obj = make_object(&[("doors", &["4"]), ("price", &["10"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![0]);
+
+        let obj = make_object(&[("doors", &["5"]), ("price", &["1.6"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1, 2]);
+
+        // Both bounds are checked against the same value, so 1 and 9 aren't within 2..4
+        let obj = make_object(&[("doors", &["1", "9"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1]);
+
+        // A value within the range means the object isn't outside it
+        let obj = make_object(&[("doors", &["1", "3"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![0]);
+
+        // A missing attribute is never within the range
+        let obj = make_object(&[("colour", &["red"])]);
+        assert_eq!(rule_numbers(engine.match_object(&obj)), vec![1]);
+    }
+
+    #[test]
+    fn test_explain_traces_every_clause() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["Red"]), ("shape", &["circle"])]);
+
+        let explanation = engine.explain(&obj, 0).unwrap();
+        assert_eq!(explanation.rule_number, 0);
+        assert!(!explanation.is_match());
+
+        assert_eq!(explanation.subrules.len(), 2);
+        assert_eq!(explanation.subrules[0].actual_count, 0);
+
+        let subrule = &explanation.subrules[1];
+        assert_eq!(subrule.subrule_number, 1);
+        assert_eq!((subrule.expected_count, subrule.actual_count), (2, 1));
+        assert!(!subrule.is_match());
+
+        let clause = &subrule.clauses[0];
+        assert_eq!(
+            (clause.tag_name.as_str(), clause.tag_value.as_str()),
+            ("colour", "red")
+        );
+        assert_eq!(clause.comparison_op, ComparisonOp::ISEQ);
+        assert_eq!(clause.object_values, vec!["red"]);
+        assert!(clause.passed);
+
+        let clause = &subrule.clauses[1];
+        assert_eq!(clause.comparison_op, ComparisonOp::NOEQ);
+        assert_eq!(clause.object_values, vec!["circle"]);
+        assert!(!clause.passed);
+    }
+
+    #[test]
+    fn test_explain_matches_match_object() {
+        let engine = readme_engine();
+        let obj = make_object(&[("colour", &["green"]), ("shape", &["rectangle"])]);
+
+        let matched: Vec<RuleNumber> = (0..engine.rule_count() as RuleNumber)
+            .filter(|n| engine.explain(&obj, *n).unwrap().is_match())
+            .collect();
+        assert_eq!(matched, rule_numbers(engine.match_object(&obj)));
+
+        // A missing attribute has no values, and passes "!"
+        let obj = make_object(&[("colour", &["green"])]);
+        let missing = &engine.explain(&obj, 0).unwrap().subrules[0].clauses[1];
+        assert_eq!(missing.tag_name, "shape");
+        assert!(missing.object_values.is_empty());
+        assert!(missing.passed);
+
+        assert!(engine.explain(&obj, 99).is_none());
+    }
+
+    #[test]
+    fn test_near_misses_ranked_by_satisfied_clauses() {
+        let mut subrules = HashMap::new();
+        subrules.insert(
+            0,
+            make_subrule(
+                0,
+                &[
+                    (ComparisonOp::ISEQ, "colour", "red"),
+                    (ComparisonOp::ISEQ, "size", "large"),
+                    (ComparisonOp::ISEQ, "shape", "square"),
+                ],
+            ),
+        );
+        subrules.insert(
+            1,
+            make_subrule(
+                1,
+                &[
+                    (ComparisonOp::ISEQ, "colour", "red"),
+                    (ComparisonOp::ISEQ, "size", "large"),
+                ],
+            ),
+        );
+        subrules.insert(
+            2,
+            make_subrule(
+                2,
+                &[
+                    (ComparisonOp::ISEQ, "colour", "red"),
+                    (ComparisonOp::ISEQ, "shape", "circle"),
+                    (ComparisonOp::ISEQ, "size", "large"),
+                ],
+            ),
+        );
+        // Rule 3 is closest through its second subrule
+        subrules.insert(
+            3,
+            make_subrule(3, &[(ComparisonOp::ISEQ, "colour", "blue")]),
+        );
+        subrules.insert(
+            4,
+            make_subrule(
+                3,
+                &[
+                    (ComparisonOp::ISEQ, "colour", "red"),
+                    (ComparisonOp::ISEQ, "shape", "circle"),
+                    (ComparisonOp::GREQ, "doors", "4"),
+                ],
+            ),
+        );
+        // Matched rules and rules with nothing satisfied are not near misses
+        subrules.insert(5, make_subrule(4, &[(ComparisonOp::ISEQ, "colour", "red")]));
+        subrules.insert(
+            6,
+            make_subrule(5, &[(ComparisonOp::ISEQ, "colour", "green")]),
+        );
+        let engine = Engine::new(subrules, &HashMap::new());
+
+        let obj = make_object(&[
+            ("colour", &["red"]),
+            ("shape", &["circle"]),
+            ("size", &["small"]),
+        ]);
+        let near_misses = engine.near_misses(&obj, 10);
+
+        let ranked: Vec<(RuleNumber, SubRuleNumber, i32, i32)> = near_misses
+            .iter()
+            .map(|m| {
+                (
+                    m.rule_number,
+                    m.subrule_number,
+                    m.satisfied_count,
+                    m.expected_count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![(2, 2, 2, 3), (3, 4, 2, 3), (1, 1, 1, 2), (0, 0, 1, 3)]
+        );
+
+        let missing: Vec<(&str, &str)> = near_misses[0]
+            .missing_clauses
+            .iter()
+            .map(|c| (c.tag_name.as_str(), c.tag_value.as_str()))
+            .collect();
+        assert_eq!(missing, vec![("size", "large")]);
+        assert_eq!(
+            near_misses[0].missing_clauses[0].object_values,
+            vec!["small"]
+        );
+
+        assert_eq!(engine.near_misses(&obj, 2).len(), 2);
+        assert!(engine.near_misses(&obj, 0).is_empty());
+    }
+
+    #[test]
+    fn test_index_candidates() {
+        let mut subrules = HashMap::new();
+        subrules.insert(
+            0,
+            make_subrule(
+                0,
+                &[
+                    (ComparisonOp::NOEQ, "shape", "circle"),
+                    (ComparisonOp::ISEQ, "colour", "blue"),
+                ],
+            ),
+        );
+        subrules.insert(1, make_subrule(1, &[(ComparisonOp::ISEQ, "colour", "red")]));
+        subrules.insert(
+            2,
+            make_subrule(
+                2,
+                &[
+                    (ComparisonOp::GREQ, "doors", "4"),
+                    (ComparisonOp::ISEQ, "size", "large"),
+                ],
+            ),
+        );
+        subrules.insert(3, make_subrule(3, &[(ComparisonOp::LETH, "doors", "3")]));
+        subrules.insert(4, make_subrule(4, &[(ComparisonOp::NOEQ, "colour", "red")]));
+        subrules.insert(5, make_subrule(5, &[(ComparisonOp::ISEQ, "weight", "5.0")]));
+        let engine = Engine::new(subrules, &numeric_tags(&["doors", "weight"]));
+
+        let candidates = |attributes: &[(&str, &[&str])]| {
+            engine
+                .m_index
+                .candidates(&Engine::normalise_object(&make_object(attributes)))
+        };
+
+        // NOEQ-only subrules are always checked
+        assert_eq!(candidates(&[]), vec![4]);
+        assert_eq!(candidates(&[("colour", &["Blue"])]), vec![0, 4]);
+        assert_eq!(
+            candidates(&[("colour", &["green"]), ("size", &["large"])]),
+            vec![2, 4]
+        );
+        assert_eq!(candidates(&[("doors", &["2"])]), vec![3, 4]);
+        // Numerically equal values of a numeric tag share a key
+        assert_eq!(candidates(&[("weight", &["5"])]), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_index_matches_every_subrule_check() {
+        let mut subrules = HashMap::new();
+        let clauses: Vec<Vec<(ComparisonOp, &str, &str)>> = vec![
+            vec![(ComparisonOp::ISEQ, "colour", "red")],
+            vec![
+                (ComparisonOp::NOEQ, "colour", "red"),
+                (ComparisonOp::NOEQ, "size", "small"),
+            ],
+            vec![
+                (ComparisonOp::GRTH, "doors", "2"),
+                (ComparisonOp::NOEQ, "colour", "blue"),
+            ],
+            vec![
+                (ComparisonOp::ISEQ, "doors", "4"),
+                (ComparisonOp::ISEQ, "size", "large"),
+            ],
+            vec![(ComparisonOp::LEEQ, "doors", "3")],
+        ];
+        for (i, clauses) in clauses.iter().enumerate() {
+            subrules.insert(i as SubRuleNumber, make_subrule(i as RuleNumber, clauses));
+        }
+        let engine = Engine::new(subrules, &numeric_tags(&["doors"]));
+
+        let objects = vec![
+            make_object(&[]),
+            make_object(&[("colour", &["red"])]),
+            make_object(&[
+                ("colour", &["blue"]),
+                ("doors", &["4.0"]),
+                ("size", &["large"]),
+            ]),
+            make_object(&[
+                ("colour", &["green"]),
+                ("doors", &["3"]),
+                ("size", &["small"]),
+            ]),
+            make_object(&[("doors", &["five"])]),
+        ];
+
+        // explain checks every subrule without the index
+        for obj in &objects {
+            let expected: Vec<RuleNumber> = (0..engine.rule_count() as RuleNumber)
+                .filter(|n| engine.explain(obj, *n).unwrap().is_match())
+                .collect();
+            assert_eq!(
+                rule_numbers(engine.match_object(obj)),
+                expected,
+                "{:?}",
+                obj
+            );
+        }
+    }
+}
@@ -1,5 +1,7 @@
 // Errors used across the codebase
 
+use crate::types::{Conflict, RuleReference};
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -18,8 +20,211 @@ pub enum RulesError {
     TagParseError(String),
 
     #[error("Error parsing Rule: {0}")]
-    RuleParseError(String),
+    RuleParseError(Box<RuleParseError>),
 
     #[error("Error parsing Object: {0}")]
     ObjectParseError(String),
+
+    #[error("Error caching Rules: {0}")]
+    CacheError(String),
+
+    // A tag or tag value can't be removed while rules reference it -- e.g. "colour: grey"
+    #[error(
+        "Tag '{tag}' is still used by the rules at {}",
+        rules.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    TagInUse {
+        tag: String,
+        rules: Vec<RuleReference>,
+    },
+
+    // No single rule wins a decision that needs one -- e.g. rules 2 and 5 at priority 10
+    #[error("No single rule wins, {0} all match")]
+    RuleConflict(Conflict),
+}
+
+/// A rule parse error, with the rule and the position of the offending span in it when they
+/// are known.
+///
+/// `Display` gives a single line -- e.g. `config/my_rules.rules:3:3: Rule contains
+/// invalid TagName: colur`. Use `render` for the rule with the span underlined, or `message`
+/// for the problem alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleParseError {
+    /// What is wrong with the rule
+    pub message: String,
+    /// File the rule was read from, if it was read from a file
+    pub file: Option<String>,
+    /// 1-based line of the rule in `file`
+    pub line: Option<usize>,
+    /// 1-based column of the start of the offending span in `source`, if known
+    pub column: Option<usize>,
+    /// Number of characters in the offending span
+    pub len: usize,
+    /// The rule as written, if known -- e.g. `- colur = red`
+    pub source: Option<String>,
+    // Index of the offending token, until it is resolved to a column
+    pub(crate) token: Option<usize>,
+}
+
+impl RuleParseError {
+    // Error without a position, which a span can be added to once the rule is known
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        RuleParseError {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    // Error at a span of characters in a rule -- start is 0-based
+    pub(crate) fn at_span(message: impl Into<String>, start: usize, len: usize) -> Self {
+        RuleParseError {
+            message: message.into(),
+            column: Some(start + 1),
+            len,
+            ..Default::default()
+        }
+    }
+
+    // Error at a token of a rule, resolved to a span once token positions are known
+    pub(crate) fn at_token(message: impl Into<String>, token: usize) -> Self {
+        RuleParseError {
+            message: message.into(),
+            token: Some(token),
+            ..Default::default()
+        }
+    }
+
+    /// Location of the error -- e.g. `config/my_rules.rules:3:3`, `column 3` for a rule
+    /// that wasn't read from a file, or an empty string if it isn't known.
+    pub fn location(&self) -> String {
+        if self.file.is_none() && self.line.is_none() {
+            return self
+                .column
+                .map(|c| format!("column {}", c))
+                .unwrap_or_default();
+        }
+
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.file.clone());
+        parts.extend(self.line.map(|l| l.to_string()));
+        parts.extend(self.column.map(|c| c.to_string()));
+        parts.join(":")
+    }
+
+    /// Renders the error with the rule and a caret under the offending span. Only the
+    /// single line of `Display` if the rule or span isn't known.
+    ///
+    /// # Examples
+    /// ```text
+    /// config/my_rules.rules:3:3: Rule contains invalid TagName: colur
+    ///   |
+    /// 3 | - colur = red
+    ///   |   ^^^^^
+    /// ```
+    pub fn render(&self) -> String {
+        let (Some(source), Some(column)) = (&self.source, self.column) else {
+            return self.to_string();
+        };
+
+        let gutter = self.line.map(|l| l.to_string()).unwrap_or_default();
+        let padding = " ".repeat(gutter.len());
+
+        format!(
+            "{}\n{} |\n{} | {}\n{} | {}{}",
+            self,
+            padding,
+            gutter,
+            source,
+            padding,
+            " ".repeat(column.saturating_sub(1)),
+            "^".repeat(self.len.max(1))
+        )
+    }
+}
+
+impl std::fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let location = self.location();
+        match (&self.source, location.is_empty()) {
+            (_, false) => write!(f, "{}: {}", location, self.message),
+            (Some(source), true) => write!(f, "'{}': {}", source, self.message),
+            (None, true) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<RuleParseError> for RulesError {
+    fn from(error: RuleParseError) -> Self {
+        RulesError::RuleParseError(Box::new(error))
+    }
+}
+
+/// How serious a diagnostic is. Errors stop a config from loading, warnings don't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or warning found while checking config files, with where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// File the problem was found in
+    pub file: String,
+    /// 1-based line of the problem in `file`
+    pub line: usize,
+    /// 1-based column of the problem, if known
+    pub column: Option<usize>,
+    /// What is wrong
+    pub message: String,
+}
+
+impl Diagnostic {
+    // Error diagnostic for an error parsing a line of a file
+    pub(crate) fn error(error: RulesError, file: &str, line: usize) -> Self {
+        let (column, message) = match error {
+            RulesError::RuleParseError(e) => (e.column, e.message),
+            RulesError::TagParseError(msg) | RulesError::ObjectParseError(msg) => (None, msg),
+            other => (None, other.to_string()),
+        };
+
+        Diagnostic {
+            severity: Severity::Error,
+            file: file.to_string(),
+            line,
+            column,
+            message,
+        }
+    }
+
+    pub(crate) fn warning(message: impl Into<String>, file: &str, line: usize) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            file: file.to_string(),
+            line,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        write!(f, "{}: {}:{}", severity, self.file, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
 }
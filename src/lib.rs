@@ -7,14 +7,22 @@ pub mod types;
 // src/lib.rs
 
 mod api;
+mod engine;
 mod parser;
 mod utils;
 
-// Re-export the main Rules struct
+// Re-export the main Rules struct and its builder
+pub use builder::RulesBuilder;
 pub use rules::Rules;
 
 // Re-export error types for users to handle
-pub use err::RulesError;
+pub use err::{Diagnostic, RuleParseError, RulesError, Severity};
+
+// Re-export evaluation result types
+pub use types::{
+    ClauseTrace, Conflict, Decision, EvaluationResult, LineChange, NearMiss, ObjectMatch, Outcome,
+    Rule, RuleExplanation, RuleMatch, RuleReference, Strategy, SubRuleTrace,
+};
 
 // Keep the lower-level API available for advanced users
 pub mod write {
@@ -25,4 +33,5 @@ pub mod write {
 
 pub use api::entry::evaluate;
 
+mod builder;
 mod rules;
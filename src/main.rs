@@ -1,6 +1,12 @@
-use rules::err::RulesError;
-use rules::orchestrator::Orchestrator;
+mod cli;
 
-fn main() -> Result<(), RulesError> {
-    Orchestrator::run()
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    ExitCode::from(cli::run(
+        &args,
+        &mut std::io::stdout(),
+        &mut std::io::stderr(),
+    ))
 }
@@ -2,34 +2,53 @@
 use crate::types;
 use std::collections::HashMap;
 
-use crate::{err::RulesError, parser::rules::RuleParser, parser::tags};
+use crate::{
+    engine::Engine, err::RulesError, parser::objects, parser::rules::RuleParser, parser::tags,
+};
+
+const CONFIG_DIR: &str = "config";
 
 #[derive(Default)]
 pub struct Orchestrator {
     m_tags: HashMap<types::TagName, types::TagValues>,
     m_subrules: HashMap<types::SubRuleNumber, types::SubRule>,
+    // Built from m_subrules once they are loaded
+    m_engine: Engine,
+    m_objects: Vec<types::TypedObject>,
 }
 
 impl Orchestrator {
     fn map_tags(&mut self) -> Result<(), RulesError> {
-        let tags: Vec<types::Tag> = tags::parse_tags()?;
+        let tags: Vec<types::Tag> = tags::parse_tags(CONFIG_DIR)?;
         for tag in tags {
-            self.m_tags.insert(tag.name, tag.values);
+            // Normalize to lowercase for consistent lookup
+            let name = tag.name.to_lowercase();
+            let values: types::TagValues = tag.values.iter().map(|v| v.to_lowercase()).collect();
+
+            tags::merge_tag(&mut self.m_tags, name, values)?;
         }
 
         Ok(())
     }
 
     fn map_subrules(&mut self) -> Result<(), RulesError> {
-        let dnf_subrules: Vec<types::SubRule> = RuleParser::parse_rules(self.m_tags.clone())?;
+        let (rules, dnf_subrules): (Vec<types::Rule>, Vec<types::SubRule>) =
+            RuleParser::parse_rules(CONFIG_DIR, self.m_tags.clone())?;
         for (i, subrule) in dnf_subrules.into_iter().enumerate() {
             self.m_subrules.insert(i as i32, subrule);
         }
+        self.m_engine = Engine::new(self.m_subrules.clone(), &self.m_tags).with_rules(rules);
 
         Ok(())
     }
 
-    pub fn run() -> Result<(), RulesError> {
+    fn map_objects(&mut self) -> Result<(), RulesError> {
+        self.m_objects = objects::parse_objects(CONFIG_DIR, &self.m_tags)?;
+
+        Ok(())
+    }
+
+    pub fn load() -> Result<Orchestrator, RulesError> {
         let mut orch: Orchestrator = Orchestrator::default();
 
         // Parsing and storage
@@ -38,6 +57,24 @@ impl Orchestrator {
         // Parsing, convertion to DNF and storage
         orch.map_subrules()?;
 
+        // Parsing, validation against tags and storage
+        orch.map_objects()?;
+
+        Ok(orch)
+    }
+
+    pub fn objects(&self) -> &[types::TypedObject] {
+        &self.m_objects
+    }
+
+    // Matches every loaded object against every loaded rule
+    pub fn evaluate(&self) -> types::EvaluationResult {
+        self.m_engine.evaluate(&self.m_objects)
+    }
+
+    pub fn run() -> Result<(), RulesError> {
+        Self::load()?;
+
         Ok(())
     }
 }
@@ -1,4 +1,5 @@
 pub mod objects;
 pub mod rules;
+pub mod tables;
 pub mod tags;
 pub mod types;
@@ -1,7 +1,691 @@
 // Parser for objects in .yaml files in config dir
-use crate::{err::RulesError, types::Object};
+//
+// Supports the subset of YAML used by objects files:
+//
+// objects:
+//   shapes:                        <- object type
+//     - colour: [red, green]       <- flow list
+//       shape: rectangle           <- scalar
+//       size:                      <- block list
+//         - large
+use crate::err::{Diagnostic, RulesError};
+use crate::parser::tags;
+use crate::types::{Object, ObjectType, TagName, TagValues, TypedObject};
+use crate::utils::file;
+use crate::utils::string;
+
+use std::collections::HashMap;
+
+const OBJECTS_KEY: &str = "objects";
+
+// Non-blank line with comments stripped -- e.g. (line number 4, indent 6, "shape: rectangle")
+struct YamlLine<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+// Object being built, the column its keys start at and the line it started on
+struct PendingObject {
+    attributes: Object,
+    key_indent: usize,
+    line: usize,
+}
+
+// An error at a 1-based line of an objects file
+struct ObjectError {
+    line: usize,
+    message: String,
+}
+
+struct ObjectsFileParser<'a> {
+    m_tags: &'a HashMap<TagName, TagValues>,
+    m_objects: Vec<TypedObject>,
+    // Current object type and the indent of its group line
+    m_group: Option<(ObjectType, usize)>,
+    m_object: Option<PendingObject>,
+    // Attribute waiting for block list values, and the line it was declared on
+    m_block_list: Option<(String, usize)>,
+}
+
+fn object_error(line: usize, msg: impl std::fmt::Display) -> ObjectError {
+    ObjectError {
+        line,
+        message: msg.to_string(),
+    }
+}
+
+impl ObjectError {
+    // The error as reported for its file -- e.g. "objects.yaml:4: Duplicate attribute 'colour'"
+    fn in_file(self, file_name: &str) -> RulesError {
+        RulesError::ObjectParseError(format!("{}:{}: {}", file_name, self.line, self.message))
+    }
+}
+
+fn yaml_lines(content: &str) -> Result<Vec<YamlLine<'_>>, ObjectError> {
+    let mut lines = Vec::new();
+
+    for (i, raw) in content.lines().enumerate() {
+        let number = i + 1;
+        let without_comment = match raw.find('#') {
+            Some(pos) => &raw[..pos],
+            None => raw,
+        };
+
+        let text = without_comment.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let leading =
+            &without_comment[..without_comment.len() - without_comment.trim_start().len()];
+        if leading.contains('\t') {
+            return Err(object_error(number, "Tabs cannot be used for indentation"));
+        }
+
+        lines.push(YamlLine {
+            number,
+            indent: leading.len(),
+            text,
+        });
+    }
+
+    Ok(lines)
+}
+
+// "key: value" -> ("key", "value")
+fn split_key_value(text: &str) -> Option<(&str, &str)> {
+    let (key, value) = text.split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+fn parse_scalar(raw: &str) -> Result<String, String> {
+    let raw = raw.trim();
+    let unquoted = if raw.len() >= 2
+        && ((raw.starts_with('"') && raw.ends_with('"'))
+            || (raw.starts_with('\'') && raw.ends_with('\'')))
+    {
+        &raw[1..raw.len() - 1]
+    } else {
+        raw
+    };
+
+    if unquoted.is_empty() {
+        return Err("Values cannot be empty".to_string());
+    }
+    if unquoted.contains(' ') {
+        return Err(format!("Value '{}' cannot contain spaces", unquoted));
+    }
+    if unquoted.contains(['[', ']', ':']) {
+        return Err(format!("Value '{}' contains invalid characters", unquoted));
+    }
+
+    Ok(unquoted.to_lowercase())
+}
+
+// "red" -> ["red"], "[red, green]" -> ["red", "green"]
+fn parse_values(raw: &str) -> Result<Vec<String>, String> {
+    if let Some(inner) = raw.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| format!("List '{}' is missing closing ']'", raw))?;
+
+        if inner.trim().is_empty() {
+            return Err("List must contain at least one value".to_string());
+        }
+
+        return inner.split(',').map(parse_scalar).collect();
+    }
+
+    Ok(vec![parse_scalar(raw)?])
+}
+
+fn assign_indices(objects: &mut [TypedObject]) {
+    let mut counts: HashMap<ObjectType, usize> = HashMap::new();
+    for obj in objects.iter_mut() {
+        let count = counts.entry(obj.obj_type.clone()).or_insert(0);
+        obj.index = *count;
+        *count += 1;
+    }
+}
+
+impl<'a> ObjectsFileParser<'a> {
+    fn new(tags: &'a HashMap<TagName, TagValues>) -> Self {
+        ObjectsFileParser {
+            m_tags: tags,
+            m_objects: Vec::new(),
+            m_group: None,
+            m_object: None,
+            m_block_list: None,
+        }
+    }
+
+    fn error(&self, line: usize, msg: impl std::fmt::Display) -> ObjectError {
+        object_error(line, msg)
+    }
+
+    fn finish_block_list(&mut self) -> Result<(), ObjectError> {
+        if let Some((name, line)) = self.m_block_list.take() {
+            let has_values = self
+                .m_object
+                .as_ref()
+                .is_some_and(|obj| obj.attributes.contains_key(&name));
+
+            if !has_values {
+                return Err(self.error(line, format!("Attribute '{}' has no values", name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish_object(&mut self) -> Result<(), ObjectError> {
+        self.finish_block_list()?;
+
+        if let Some(pending) = self.m_object.take() {
+            let (obj_type, _) = self
+                .m_group
+                .clone()
+                .ok_or_else(|| self.error(pending.line, "Object must belong to an object type"))?;
+
+            self.m_objects.push(TypedObject {
+                obj_type,
+                index: 0,
+                attributes: pending.attributes,
+            });
+        }
+
+        Ok(())
+    }
+
+    // "key: value" line inside an object
+    fn add_attribute(&mut self, line: usize, text: &str) -> Result<(), ObjectError> {
+        self.finish_block_list()?;
+
+        let (key, raw_values) = split_key_value(text).ok_or_else(|| {
+            self.error(
+                line,
+                format!("Expected 'attribute: value', found '{}'", text),
+            )
+        })?;
+
+        let name = key.to_lowercase();
+        if name.is_empty() || name.contains(' ') {
+            return Err(self.error(line, format!("Invalid attribute name '{}'", key)));
+        }
+
+        let obj = self
+            .m_object
+            .as_ref()
+            .ok_or_else(|| self.error(line, "Attribute must belong to an object"))?;
+        if obj.attributes.contains_key(&name) {
+            return Err(self.error(line, format!("Duplicate attribute '{}'", name)));
+        }
+
+        // Values follow on the next lines as a block list
+        if raw_values.is_empty() {
+            self.m_block_list = Some((name, line));
+            return Ok(());
+        }
+
+        let values = parse_values(raw_values).map_err(|e| self.error(line, e))?;
+        self.insert_values(line, name, values)
+    }
+
+    fn insert_values(
+        &mut self,
+        line: usize,
+        name: String,
+        values: Vec<String>,
+    ) -> Result<(), ObjectError> {
+        validate_attribute(&name, &values, self.m_tags).map_err(|e| match e {
+            RulesError::ObjectParseError(msg) => self.error(line, msg),
+            other => self.error(line, other),
+        })?;
+
+        if let Some(obj) = self.m_object.as_mut() {
+            obj.attributes.entry(name).or_default().extend(values);
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &YamlLine) -> Result<(), ObjectError> {
+        let group_indent = self.m_group.as_ref().map(|(_, indent)| *indent);
+
+        // "- value" item of a block list
+        if let Some((name, _)) = self.m_block_list.clone() {
+            let key_indent = self.m_object.as_ref().map_or(0, |obj| obj.key_indent);
+            if let Some(item) = line.text.strip_prefix('-')
+                && line.indent >= key_indent
+                && !item.contains(':')
+            {
+                let values = parse_values(item.trim()).map_err(|e| self.error(line.number, e))?;
+                return self.insert_values(line.number, name, values);
+            }
+        }
+
+        // "- key: value" starts a new object
+        if let Some(rest) = line.text.strip_prefix('-') {
+            let group_indent = group_indent
+                .ok_or_else(|| self.error(line.number, "Object must belong to an object type"))?;
+            if line.indent <= group_indent {
+                return Err(self.error(
+                    line.number,
+                    "Objects must be indented under their object type",
+                ));
+            }
+
+            self.finish_object()?;
+
+            let first_attribute = rest.trim_start();
+            if first_attribute.is_empty() {
+                return Err(self.error(line.number, "Object must have at least one attribute"));
+            }
+
+            self.m_object = Some(PendingObject {
+                attributes: HashMap::new(),
+                key_indent: line.indent + line.text.len() - first_attribute.len(),
+                line: line.number,
+            });
+            return self.add_attribute(line.number, first_attribute);
+        }
+
+        // "key: value" continues the current object
+        if let Some(obj) = &self.m_object
+            && line.indent == obj.key_indent
+        {
+            return self.add_attribute(line.number, line.text);
+        }
+
+        // "type:" starts a new object type group
+        match split_key_value(line.text) {
+            Some((key, "")) if group_indent.is_none_or(|indent| indent == line.indent) => {
+                if key.is_empty() || key.contains(' ') {
+                    return Err(self.error(line.number, format!("Invalid object type '{}'", key)));
+                }
+
+                self.finish_object()?;
+                self.m_group = Some((key.to_lowercase(), line.indent));
+                Ok(())
+            }
+            _ => Err(self.error(
+                line.number,
+                format!("Unexpected line '{}', check indentation", line.text),
+            )),
+        }
+    }
+
+    fn parse(mut self, content: &str) -> Result<Vec<TypedObject>, ObjectError> {
+        let lines = yaml_lines(content)?;
+        let mut lines = lines.iter();
+
+        // File must start with "objects:", unless it is empty
+        let Some(first) = lines.next() else {
+            return Ok(Vec::new());
+        };
+        if first.indent != 0 || split_key_value(first.text) != Some((OBJECTS_KEY, "")) {
+            return Err(self.error(
+                first.number,
+                format!("Expected '{}:' at the top of the file", OBJECTS_KEY),
+            ));
+        }
+
+        for line in lines {
+            if line.indent == 0 {
+                return Err(self.error(
+                    line.number,
+                    format!("Only one top-level '{}:' key is allowed", OBJECTS_KEY),
+                ));
+            }
+            self.parse_line(line)?;
+        }
+
+        self.finish_object()?;
+
+        let mut objects = self.m_objects;
+        assign_indices(&mut objects);
+        Ok(objects)
+    }
+}
+
+// Checks an attribute's name is a TagName and its values are valid TagValues
+pub fn validate_attribute(
+    name: &str,
+    values: &[String],
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<(), RulesError> {
+    let valid_values = tags.get(name).ok_or_else(|| {
+        RulesError::ObjectParseError(format!(
+            "Object contains invalid attribute: '{}' is not a valid TagName{}",
+            name,
+            string::did_you_mean(name, tags.keys())
+        ))
+    })?;
+
+    let numeric = tags::numeric_tag(valid_values);
+    for value in values {
+        if let Some(numeric) = numeric {
+            numeric.parse_value(value).map_err(|e| {
+                RulesError::ObjectParseError(format!(
+                    "Object contains invalid value for numeric TagName '{}': {}",
+                    name, e
+                ))
+            })?;
+        } else if !valid_values.contains(value) {
+            return Err(RulesError::ObjectParseError(format!(
+                "Object contains invalid value: '{}' is not a valid value for TagName '{}'{}",
+                value,
+                name,
+                string::did_you_mean(value, valid_values)
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_object(obj: &Object, tags: &HashMap<TagName, TagValues>) -> Result<(), RulesError> {
+    let mut names: Vec<&String> = obj.keys().collect();
+    names.sort();
+
+    for name in names {
+        let values: Vec<String> = obj[name].iter().map(|v| v.to_lowercase()).collect();
+        if values.is_empty() {
+            return Err(RulesError::ObjectParseError(format!(
+                "Attribute '{}' has no values",
+                name
+            )));
+        }
+        validate_attribute(&name.to_lowercase(), &values, tags)?;
+    }
 
-pub fn validate_object(obj: Object) -> Result<(), RulesError> {
-    // TODO: Check its tags are valid
     Ok(())
 }
+
+// Parses the contents of one objects file. `file_name` is only used in error messages.
+pub fn parse_objects_str(
+    file_name: &str,
+    content: &str,
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<Vec<TypedObject>, RulesError> {
+    ObjectsFileParser::new(tags)
+        .parse(content)
+        .map_err(|e| e.in_file(file_name))
+}
+
+pub fn parse_objects(
+    config_dir: &str,
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<Vec<TypedObject>, RulesError> {
+    let all_files = file::read_named_files_in_dir(&format!("{}/*.yaml", config_dir))?;
+    parse_object_files(&all_files, tags)
+}
+
+// Same as parse_objects, but for (file name, contents) pairs already in memory
+pub fn parse_object_files(
+    all_files: &[(String, String)],
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<Vec<TypedObject>, RulesError> {
+    let mut objects: Vec<TypedObject> = Vec::new();
+
+    for (file_name, content) in all_files.iter() {
+        objects.extend(parse_objects_str(file_name, content, tags)?);
+    }
+
+    // Objects of the same type may be spread across files
+    assign_indices(&mut objects);
+    Ok(objects)
+}
+
+// Same as parse_object_files, but skips invalid files instead of stopping at the first one.
+// Parsing a file stops at its first error, so there is at most one diagnostic per file.
+pub fn parse_object_files_with_diagnostics(
+    all_files: &[(String, String)],
+    tags: &HashMap<TagName, TagValues>,
+) -> (Vec<TypedObject>, Vec<Diagnostic>) {
+    let mut objects: Vec<TypedObject> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for (file_name, content) in all_files.iter() {
+        match ObjectsFileParser::new(tags).parse(content) {
+            Ok(file_objects) => objects.extend(file_objects),
+            Err(e) => diagnostics.push(Diagnostic::error(
+                RulesError::ObjectParseError(e.message),
+                file_name,
+                e.line,
+            )),
+        }
+    }
+
+    assign_indices(&mut objects);
+    (objects, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_tags() -> HashMap<String, Vec<String>> {
+        let mut tags = HashMap::new();
+        tags.insert(
+            "colour".to_string(),
+            vec!["red".to_string(), "green".to_string(), "grey".to_string()],
+        );
+        tags.insert(
+            "shape".to_string(),
+            vec!["circle".to_string(), "rectangle".to_string()],
+        );
+        tags.insert(
+            "size".to_string(),
+            vec!["small".to_string(), "large".to_string()],
+        );
+        tags.insert("wheels".to_string(), vec!["integer(2..5)".to_string()]);
+        tags
+    }
+
+    fn parse(content: &str) -> Result<Vec<TypedObject>, RulesError> {
+        parse_objects_str("test.yaml", content, &create_test_tags())
+    }
+
+    fn values(obj: &TypedObject, name: &str) -> Vec<String> {
+        obj.attributes[name].clone()
+    }
+
+    fn expect_error(content: &str, expected: &[&str]) {
+        match parse(content) {
+            Err(RulesError::ObjectParseError(msg)) => {
+                for part in expected {
+                    assert!(msg.contains(part), "'{}' should contain '{}'", msg, part);
+                }
+            }
+            other => panic!("Expected ObjectParseError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_objects_groups_and_values() {
+        let content = "\
+# Objects
+objects:
+  shapes:
+    - colour: [Red, green]
+      shape: rectangle
+      size: large
+
+    - colour: green # inline comment
+      shape: circle
+
+  cars:
+    - colour: grey
+      size: small
+";
+        let objects = parse(content).unwrap();
+
+        assert_eq!(objects.len(), 3);
+        assert_eq!(objects[0].obj_type, "shapes");
+        assert_eq!(objects[0].index, 0);
+        assert_eq!(values(&objects[0], "colour"), vec!["red", "green"]);
+        assert_eq!(values(&objects[0], "shape"), vec!["rectangle"]);
+        assert_eq!(objects[1].obj_type, "shapes");
+        assert_eq!(objects[1].index, 1);
+        assert_eq!(objects[1].attributes.len(), 2);
+        assert_eq!(objects[2].obj_type, "cars");
+        assert_eq!(objects[2].index, 0);
+        assert_eq!(values(&objects[2], "size"), vec!["small"]);
+    }
+
+    #[test]
+    fn test_parse_objects_block_list() {
+        let content = "\
+objects:
+  shapes:
+    - colour:
+        - red
+        - green
+      size: small
+";
+        let objects = parse(content).unwrap();
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(values(&objects[0], "colour"), vec!["red", "green"]);
+        assert_eq!(values(&objects[0], "size"), vec!["small"]);
+    }
+
+    #[test]
+    fn test_parse_objects_empty_file() {
+        assert!(parse("# nothing here\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_objects_missing_objects_key() {
+        expect_error(
+            "shapes:\n  - colour: red\n",
+            &["test.yaml:1:", "'objects:'"],
+        );
+    }
+
+    #[test]
+    fn test_parse_objects_invalid_attribute() {
+        let content = "objects:\n  shapes:\n    - colour: red\n      doors: 3\n";
+        expect_error(content, &["test.yaml:4:", "invalid attribute", "doors"]);
+    }
+
+    #[test]
+    fn test_parse_objects_invalid_value() {
+        let content = "objects:\n  shapes:\n    - colour: [red, purple]\n";
+        expect_error(content, &["test.yaml:3:", "invalid value", "purple"]);
+    }
+
+    #[test]
+    fn test_parse_objects_duplicate_attribute() {
+        let content = "objects:\n  shapes:\n    - colour: red\n      colour: green\n";
+        expect_error(content, &["test.yaml:4:", "Duplicate attribute"]);
+    }
+
+    #[test]
+    fn test_parse_objects_unterminated_list() {
+        let content = "objects:\n  shapes:\n    - colour: [red, green\n";
+        expect_error(content, &["test.yaml:3:", "missing closing"]);
+    }
+
+    #[test]
+    fn test_parse_objects_object_without_type() {
+        expect_error(
+            "objects:\n  - colour: red\n",
+            &["test.yaml:2:", "object type"],
+        );
+    }
+
+    #[test]
+    fn test_parse_objects_empty_block_list() {
+        let content = "objects:\n  shapes:\n    - colour:\n      size: small\n";
+        expect_error(content, &["test.yaml:3:", "has no values"]);
+    }
+
+    #[test]
+    fn test_parse_objects_bad_indentation() {
+        let content = "objects:\n  shapes:\n    - colour: red\n         size: small\n";
+        expect_error(content, &["test.yaml:4:", "indentation"]);
+    }
+
+    #[test]
+    fn test_parse_objects_tab_indentation() {
+        expect_error("objects:\n\tshapes:\n", &["test.yaml:2:", "Tabs"]);
+    }
+
+    #[test]
+    fn test_validate_object() {
+        let tags = create_test_tags();
+        let mut obj: Object = HashMap::new();
+        obj.insert("Colour".to_string(), vec!["RED".to_string()]);
+        assert!(validate_object(&obj, &tags).is_ok());
+
+        obj.insert("shape".to_string(), vec!["square".to_string()]);
+        assert!(matches!(
+            validate_object(&obj, &tags),
+            Err(RulesError::ObjectParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_objects_numeric_values() {
+        let objects = parse("objects:\n  cars:\n    - wheels: 3\n      colour: red\n").unwrap();
+        assert_eq!(values(&objects[0], "wheels"), vec!["3"]);
+
+        expect_error(
+            "objects:\n  cars:\n    - wheels: 7\n",
+            &["test.yaml:3:", "'7' is above the maximum of 5"],
+        );
+        expect_error(
+            "objects:\n  cars:\n    - wheels: three\n",
+            &["test.yaml:3:", "'three' is not an integer"],
+        );
+    }
+
+    #[test]
+    fn test_parse_objects_suggests_closest_match() {
+        expect_error(
+            "objects:\n  shapes:\n    - colur: red\n",
+            &["'colur' is not a valid TagName, did you mean 'colour'?"],
+        );
+        expect_error(
+            "objects:\n  shapes:\n    - shape: rectangel\n",
+            &["did you mean 'rectangle'?"],
+        );
+    }
+
+    #[test]
+    fn test_parse_object_files_with_diagnostics() {
+        let files = vec![
+            (
+                "a.yaml".to_string(),
+                "objects:\n  shapes:\n    - colour: red\n".to_string(),
+            ),
+            (
+                "b.yaml".to_string(),
+                "objects:\n  shapes:\n    - colour: purple\n".to_string(),
+            ),
+            (
+                "c.yaml".to_string(),
+                "objects:\n  shapes:\n    - colour: green\n".to_string(),
+            ),
+        ];
+
+        let (objects, diagnostics) =
+            parse_object_files_with_diagnostics(&files, &create_test_tags());
+
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[1].index, 1);
+        assert_eq!(values(&objects[1], "colour"), vec!["green"]);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, "b.yaml");
+        assert_eq!(diagnostics[0].line, 3);
+        assert!(
+            diagnostics[0]
+                .message
+                .starts_with("Object contains invalid value")
+        );
+    }
+}
@@ -1,7 +1,11 @@
 // Parser for .rules files
-use crate::err::RulesError;
-use crate::parser::types::{AstRule, MappedRuleTokens, Node, Token, TokenDepth, TokenType};
-use crate::types::{self, ComparisonOp, SubRule};
+use crate::err::{Diagnostic, RuleParseError, RulesError};
+use crate::parser::tables;
+use crate::parser::tags;
+use crate::parser::types::{
+    AstRule, Interval, MappedRuleTokens, Node, Pruned, Token, TokenDepth, TokenSpan, TokenType,
+};
+use crate::types::{self, ComparisonOp, Outcome, Rule, SubRule};
 use crate::utils::file;
 use crate::utils::string;
 
@@ -14,20 +18,224 @@ static TOKEN_PRECEDENCE: LazyLock<HashMap<&str, i32>> = LazyLock::new(|| {
     m.insert("&", 1);
     m.insert("=", 2);
     m.insert("!", 2);
+    m.insert(">", 2);
+    m.insert(">=", 2);
+    m.insert("<", 2);
+    m.insert("<=", 2);
+    m.insert("in", 2);
     m
 });
 
 // All valid operator characters in rule syntax
-const ALL_OP_CHARS: &[char] = &['(', ')', '=', '!', '&', '|', ','];
+const ALL_OP_CHARS: &[char] = &['(', ')', '=', '!', '&', '|', ',', '>', '<'];
 // Operators that expect a TagValue on the right-hand side
-const RHS_CHARS: &[char] = &['=', '!', ',', ')'];
+const RHS_CHARS: &[char] = &['=', '!', ',', ')', '>', '<'];
+// Comparison operators only valid for numeric tags
+const NUMERIC_ONLY_OPS: &[&str] = &[">", ">=", "<", "<=", "in"];
+// Comparison operator written as a word -- e.g. "age in [18, 65)". It is only an operator
+// straight after a TagName, so "in" can still be a tag name or value.
+const IN_KEYWORD: &str = "in";
 // Operators that expect a TagName on the left-hand side
 const LHS_CHARS: &[char] = &['&', '|', '('];
+// Metadata annotations, on the lines before the rule they describe -- e.g. "@owner: sales"
+const ANNOTATION_PREFIX: char = '@';
+const OWNER_ANNOTATION: &str = "owner";
+const DESCRIPTION_ANNOTATION: &str = "description";
+const PRIORITY_ANNOTATION: &str = "priority";
+
+// A rule line with its 0-based line and metadata, or the 0-based line of an annotation error
+type RuleLine<'a> = Result<(usize, &'a str, Rule), (usize, RulesError)>;
+
+// A problem with a line of a .rules file -- an invalid rule or annotation with its file and
+// 1-based line, or a warning
+enum RuleFileIssue {
+    Error(RulesError, String, usize),
+    Warning(Diagnostic),
+}
 
 pub struct RuleParser {
     m_mapped_tags: HashMap<types::TagName, types::TagValues>,
 }
 
+// Splits a rule into tokens one character at a time, keeping the span of each token
+#[derive(Default)]
+struct Tokeniser {
+    m_tokens: Vec<String>,
+    m_spans: Vec<TokenSpan>,
+    m_word: String,
+    m_word_start: usize,
+    // Positions of the '(' still open
+    m_open_parens: Vec<usize>,
+
+    // For comma expansion
+    m_last_tag_name: Option<String>,
+    m_last_comparison_op: Option<String>,
+    m_prev_char: Option<char>,
+
+    // Interval after "in" being read as a single TagValue -- e.g. "[18,65)", and its start
+    m_interval: Option<(String, usize)>,
+}
+
+impl Tokeniser {
+    fn paren_depth(&self) -> i32 {
+        self.m_open_parens.len() as i32
+    }
+
+    // Type expected for the next token, with an error at the token that can't be followed
+    // if the tokens so far are invalid
+    fn expected_token_type(&self) -> Result<TokenType, RulesError> {
+        RuleParser::get_expected_token_type(&self.m_tokens, self.paren_depth()).map_err(|e| match e
+        {
+            RulesError::RuleParseError(mut e) => {
+                if let Some(&(start, len)) = e.token.take().and_then(|i| self.m_spans.get(i)) {
+                    e.column = Some(start + 1);
+                    e.len = len;
+                }
+                RulesError::RuleParseError(e)
+            }
+            other => other,
+        })
+    }
+
+    fn push(&mut self, token: String, start: usize, len: usize) {
+        self.m_tokens.push(token);
+        self.m_spans.push((start, len));
+    }
+
+    // Pushes the word being built as a token, remembering it if it's a TagName or "in"
+    fn push_word(&mut self) -> Result<(), RulesError> {
+        if self.m_word.is_empty() {
+            return Ok(());
+        }
+
+        let token = self.m_word.trim().to_string();
+        let len = self.m_word.chars().count();
+        let expected_token_type = self.expected_token_type()?;
+        self.push(token.clone(), self.m_word_start, len);
+
+        if expected_token_type == TokenType::TagName {
+            self.m_last_tag_name = Some(token);
+        } else if expected_token_type == TokenType::ComparisonOp {
+            self.m_last_comparison_op = Some(token);
+        }
+
+        self.m_word.clear();
+        Ok(())
+    }
+
+    fn next_char(&mut self, i: usize, c: char) -> Result<(), RulesError> {
+        if let Some((value, start)) = self.m_interval.as_mut() {
+            if c != ' ' {
+                value.push(c);
+            }
+            if c == ']' || c == ')' {
+                let (value, start) = (value.clone(), *start);
+                self.push(value, start, i + 1 - start);
+                self.m_interval = None;
+            }
+            return Ok(());
+        }
+
+        if c == '[' || c == '(' {
+            self.push_word()?;
+
+            if let Some(last) = self.m_tokens.len().checked_sub(1)
+                && RuleParser::is_in_keyword(&self.m_tokens, last)
+            {
+                self.m_interval = Some((c.to_string(), i));
+                return Ok(());
+            }
+        }
+
+        // Join '>' or '<' directly followed by '=' into a single ">=" or "<=" token
+        if c == '='
+            && matches!(self.m_prev_char, Some('>') | Some('<'))
+            && let (Some(last), Some(span)) = (self.m_tokens.last_mut(), self.m_spans.last_mut())
+        {
+            last.push(c);
+            span.1 += 1;
+            self.m_last_comparison_op = Some(last.clone());
+            self.m_prev_char = Some(c);
+            return Ok(());
+        }
+        self.m_prev_char = Some(c);
+
+        if ALL_OP_CHARS.contains(&c) {
+            self.push_word()?;
+
+            // Expand comma to regular OR expression
+            if c == ',' {
+                let (Some(tag_name), Some(comparison_op)) = (
+                    self.m_last_tag_name.clone(),
+                    self.m_last_comparison_op.clone(),
+                ) else {
+                    return Err(RuleParseError::at_span(
+                        "Comma must follow a complete tag comparison",
+                        i,
+                        1,
+                    )
+                    .into());
+                };
+
+                self.push("|".to_string(), i, 1);
+                self.push(tag_name, i, 1);
+                self.push(comparison_op, i, 1);
+
+                return Ok(());
+            }
+
+            let expected_token_type = self.expected_token_type()?;
+            let token = c.to_string();
+            self.push(token.clone(), i, 1);
+
+            if expected_token_type == TokenType::ComparisonOp {
+                self.m_last_comparison_op = Some(token);
+            }
+
+            if c == '(' {
+                self.m_open_parens.push(i);
+            } else if c == ')' && self.m_open_parens.pop().is_none() {
+                return Err(RuleParseError::at_span("Unmatched closing parenthesis", i, 1).into());
+            }
+        } else if c == ' ' {
+            self.push_word()?;
+        } else {
+            if self.m_word.is_empty() {
+                self.m_word_start = i;
+            }
+            self.m_word.push(c);
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Vec<String>, Vec<TokenSpan>), RulesError> {
+        if !self.m_word.is_empty() {
+            let token = self.m_word.trim().to_string();
+            let len = self.m_word.chars().count();
+            self.push(token, self.m_word_start, len);
+        }
+
+        if let Some((value, start)) = self.m_interval {
+            return Err(RuleParseError::at_span(
+                format!(
+                    "Unterminated interval: '{}' must end with ']' or ')'",
+                    value
+                ),
+                start,
+                1,
+            )
+            .into());
+        }
+
+        if let Some(&start) = self.m_open_parens.last() {
+            return Err(RuleParseError::at_span("Unmatched opening parenthesis", start, 1).into());
+        }
+
+        Ok((self.m_tokens, self.m_spans))
+    }
+}
+
 impl RuleParser {
     pub fn new(tags: HashMap<types::TagName, types::TagValues>) -> Self {
         RuleParser {
@@ -44,77 +252,516 @@ impl RuleParser {
             return Ok(());
         }
 
-        let original_line = line.to_string();
+        let original_line = line.trim_end();
+
+        let line = string::normalise_rule(line)
+            .map_err(|e| Self::add_error_context(e, original_line, 0, &[]))?;
 
-        let line =
-            string::normalise(line).map_err(|e| Self::add_error_context(e, &original_line))?;
+        let offset = Self::rule_offset(original_line, &line);
 
-        let tokens: MappedRuleTokens =
-            Self::map_rule_tokens(&Self::tokenise_rule(&line.to_string())?);
+        let (tokens, spans) = Self::tokenise_rule_with_spans(&line)
+            .map_err(|e| Self::add_error_context(e, original_line, offset, &[]))?;
+        let tokens: MappedRuleTokens = Self::map_rule_tokens(&tokens);
 
-        Self::check_rule_syntax(&tokens).map_err(|e| Self::add_error_context(e, &original_line))?;
+        Self::check_rule_syntax(&tokens)
+            .map_err(|e| Self::add_error_context(e, original_line, offset, &spans))?;
 
         self.check_valid_tags(&tokens)
-            .map_err(|e| Self::add_error_context(e, &original_line))?;
+            .map_err(|e| Self::add_error_context(e, original_line, offset, &spans))?;
+
+        Self::rule_outcome(original_line)
+            .map_err(|e| Self::add_error_context(e, original_line, 0, &[]))?;
 
         Ok(())
     }
 
-    fn add_error_context(error: RulesError, rule: &str) -> RulesError {
+    // Keys and values of the outcome a rule declares after "=>", in the order written.
+    // Empty if it declares none.
+    // E.g. "- colour = red => Discount: 10, tier: gold" -> [("discount", "10"), ("tier", "gold")]
+    fn outcome_pairs(rule: &str) -> Result<Vec<(String, String)>, RulesError> {
+        let (conditions, Some(outcome)) = string::split_rule_outcome(rule) else {
+            return Ok(Vec::new());
+        };
+
+        // Errors are at a span of the rule -- e.g. the key or the "key: value" pair -- so the
+        // position of each part is counted in characters from the start of the rule
+        let separator_start = conditions.chars().count();
+        let mut start = separator_start + string::OUTCOME_SEPARATOR.len();
+        let span = |text: &str, start: usize| {
+            let leading = text.chars().take_while(|c| c.is_whitespace()).count();
+            (start + leading, text.trim().chars().count())
+        };
+
+        if outcome.trim().is_empty() {
+            return Err(RuleParseError::at_span(
+                format!(
+                    "Rule has '{}' but no outcome after it",
+                    string::OUTCOME_SEPARATOR
+                ),
+                separator_start,
+                string::OUTCOME_SEPARATOR.len(),
+            )
+            .into());
+        }
+
+        let mut pairs: Vec<(String, String)> = Vec::new();
+        for pair in outcome.split(',') {
+            let pair_offset = start;
+            start += pair.chars().count() + 1;
+            let (pair_start, pair_len) = span(pair, pair_offset);
+
+            let Some((key, value)) = pair.split_once(':') else {
+                return Err(RuleParseError::at_span(
+                    format!(
+                        "Outcome must be written as 'key: value, key: value', found '{}'",
+                        pair.trim()
+                    ),
+                    pair_start,
+                    pair_len,
+                )
+                .into());
+            };
+            let (key_start, key_len) = span(key, pair_offset);
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                return Err(RuleParseError::at_span(
+                    format!(
+                        "Invalid outcome key '{}', keys can only contain letters, digits, '_', '-' and '.'",
+                        key
+                    ),
+                    key_start,
+                    key_len,
+                )
+                .into());
+            }
+            if value.is_empty() {
+                return Err(RuleParseError::at_span(
+                    format!("Outcome key '{}' has no value", key),
+                    pair_start,
+                    pair_len,
+                )
+                .into());
+            }
+            if pairs.iter().any(|(k, _)| *k == key) {
+                return Err(RuleParseError::at_span(
+                    format!("Outcome key '{}' is given more than once", key),
+                    key_start,
+                    key_len,
+                )
+                .into());
+            }
+
+            pairs.push((key, value.to_string()));
+        }
+
+        Ok(pairs)
+    }
+
+    // Outcome a rule declares after "=>", empty if it declares none
+    // E.g. "- colour = red => discount: 10, tier: gold" -> {discount: 10, tier: gold}
+    pub fn rule_outcome(rule: &str) -> Result<Outcome, RulesError> {
+        Ok(Self::outcome_pairs(rule)?.into_iter().collect())
+    }
+
+    // End of a rule in canonical form, with its outcome if it has one -- e.g. " => tier: gold"
+    fn outcome_suffix(rule: &str) -> Result<String, RulesError> {
+        let pairs = Self::outcome_pairs(rule)?;
+        if pairs.is_empty() {
+            return Ok(String::new());
+        }
+
+        let pairs: Vec<String> = pairs
+            .into_iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+        Ok(format!(
+            " {} {}",
+            string::OUTCOME_SEPARATOR,
+            pairs.join(", ")
+        ))
+    }
+
+    // Column of the normalised rule in the original line, to report errors against
+    fn rule_offset(original_line: &str, normalised: &str) -> usize {
+        original_line
+            .find(normalised)
+            .map(|i| original_line[..i].chars().count())
+            .unwrap_or(0)
+    }
+
+    // Canonical form of a rule, checking its syntax but not its tags
+    // E.g. "-(Colour=red,BLUE)&size!large" -> "- (colour = red, blue) & size ! large"
+    pub fn format_rule(rule: &str) -> Result<String, RulesError> {
+        let original_line = rule.trim_end();
+        let line = string::normalise_rule(rule)
+            .map_err(|e| Self::add_error_context(e, original_line, 0, &[]))?;
+        let offset = Self::rule_offset(original_line, &line);
+
+        let (tokens, spans) = Self::tokenise_rule_with_spans(&line)
+            .map_err(|e| Self::add_error_context(e, original_line, offset, &[]))?;
+        let mapped: MappedRuleTokens = Self::map_rule_tokens(&tokens);
+        Self::check_rule_syntax(&mapped)
+            .map_err(|e| Self::add_error_context(e, original_line, offset, &spans))?;
+
+        let chars: Vec<char> = line.chars().collect();
+        let mut formatted = Self::rule_prefix(rule);
+        let mut i = 0;
+
+        while i < mapped.len() {
+            let (token, token_type, _paren_depth) = &mapped[i];
+            let token = token.to_lowercase();
+
+            // Put comma shorthand back -- e.g. "red | colour = blue" from "red, blue"
+            if token == "|" && chars.get(spans[i].0) == Some(&',') {
+                formatted.push_str(", ");
+                i += 3;
+                continue;
+            }
+
+            match (token.as_str(), token_type) {
+                ("(" | ")", _) | (_, TokenType::Negation) => formatted.push_str(&token),
+                (_, TokenType::LogicalOp | TokenType::ComparisonOp) => {
+                    formatted.push_str(&format!(" {} ", token))
+                }
+                // Intervals are spaced like lists -- e.g. "[18, 65)"
+                (_, TokenType::TagValue) => formatted.push_str(&token.replace(',', ", ")),
+                _ => formatted.push_str(&token),
+            }
+
+            i += 1;
+        }
+
+        formatted.push_str(
+            &Self::outcome_suffix(rule)
+                .map_err(|e| Self::add_error_context(e, original_line, 0, &[]))?,
+        );
+
+        Ok(formatted)
+    }
+
+    // Tag names and values of a rule's comparisons, lowercased and in order
+    // E.g. "- colour = red, blue & size ! large" -> [colour red, colour blue, size large]
+    fn rule_clauses(rule: &str) -> Result<Vec<(String, String)>, RulesError> {
+        let line = string::normalise_rule(rule)?;
+        let tokens = Self::tokenise_rule(&line)?;
+        let mapped = Self::map_rule_tokens(&tokens);
+        Self::check_rule_syntax(&mapped)?;
+
+        Ok(mapped
+            .iter()
+            .enumerate()
+            .filter(|(_, (token, token_type, _))| {
+                *token_type == TokenType::TagName && Self::operator_char(token).is_none()
+            })
+            .filter_map(|(i, (tag_name, _, _))| {
+                mapped
+                    .get(i + 2)
+                    .map(|(tag_value, _, _)| (tag_name.to_lowercase(), tag_value.to_lowercase()))
+            })
+            .collect())
+    }
+
+    // True if the rule compares the tag, or the tag with the value when one is given
+    // E.g. "- colour = red, grey" references colour, and colour with grey
+    pub fn references(
+        rule: &str,
+        tag_name: &str,
+        tag_value: Option<&str>,
+    ) -> Result<bool, RulesError> {
+        let tag_name = tag_name.trim().to_lowercase();
+        let tag_value = tag_value.map(|v| v.trim().to_lowercase());
+
+        Ok(Self::rule_clauses(rule)?.iter().any(|(name, value)| {
+            *name == tag_name && tag_value.as_ref().is_none_or(|v| v == value)
+        }))
+    }
+
+    // Replaces comparisons for which `removed` is true with their value for an object that
+    // can't have the tag or value: "colour = grey" is always false, while "colour ! grey",
+    // "doors ! 2..4" and "!(doors > 2)" are always true
+    fn prune(node: Node, removed: &dyn Fn(&str, &str) -> bool) -> Result<Pruned, RulesError> {
+        if let Token::And | Token::Or = node.token {
+            let is_and = node.token == Token::And;
+            let (Some(left), Some(right)) = (node.left, node.right) else {
+                return Err(RuleParseError::new(format!(
+                    "{:?} node is missing an operand",
+                    node.token
+                ))
+                .into());
+            };
+
+            let left = Self::prune(*left, removed)?;
+            let right = Self::prune(*right, removed)?;
+
+            return Ok(match (left, right) {
+                (Pruned::Always(value), other) | (other, Pruned::Always(value)) => {
+                    // false & A -> false, true | A -> true, true & A -> A, false | A -> A
+                    if value != is_and {
+                        Pruned::Always(value)
+                    } else {
+                        other
+                    }
+                }
+                (Pruned::Node(left), Pruned::Node(right)) => Pruned::Node(Node {
+                    token: node.token,
+                    left: Some(Box::new(left)),
+                    right: Some(Box::new(right)),
+                    tag_kv: None,
+                    negated: false,
+                }),
+            });
+        }
+
+        let Some((tag_name, tag_value)) = &node.tag_kv else {
+            return Ok(Pruned::Node(node));
+        };
+        if !removed(tag_name, tag_value) {
+            return Ok(Pruned::Node(node));
+        }
+
+        Ok(Pruned::Always(
+            node.token == Token::NotEquals || node.negated,
+        ))
+    }
+
+    // Operands of a chain of '|' -- e.g. [A, B, C] for "A | B | C"
+    fn or_operands<'a>(node: &'a Node, operands: &mut Vec<&'a Node>) {
+        match (&node.token, &node.left, &node.right) {
+            (Token::Or, Some(left), Some(right)) => {
+                Self::or_operands(left, operands);
+                Self::or_operands(right, operands);
+            }
+            _ => operands.push(node),
+        }
+    }
+
+    // Canonical text of an expression, with parentheses only where precedence needs them, and
+    // comma shorthand for neighbouring alternatives of the same comparison
+    // E.g. "(colour = red, blue) & size ! large"
+    fn render_node(node: &Node) -> String {
+        match (&node.token, &node.left, &node.right, &node.tag_kv) {
+            (Token::Or, ..) => {
+                let mut operands = Vec::new();
+                Self::or_operands(node, &mut operands);
+
+                let mut text = String::new();
+                let mut previous: Option<(&Token, &str)> = None;
+                for operand in operands {
+                    // Interval values have commas of their own, and negated comparisons are
+                    // written in full, so neither can be shortened
+                    let comparison = operand
+                        .tag_kv
+                        .as_ref()
+                        .filter(|(_, value)| !value.contains(',') && !operand.negated)
+                        .map(|(name, value)| (&operand.token, name.as_str(), value.as_str()));
+
+                    match comparison {
+                        Some((token, name, value)) if previous == Some((token, name)) => {
+                            text.push_str(", ");
+                            text.push_str(value);
+                        }
+                        _ => {
+                            if !text.is_empty() {
+                                text.push_str(" | ");
+                            }
+                            text.push_str(&Self::render_node(operand));
+                        }
+                    }
+                    previous = comparison.map(|(token, name, _)| (token, name));
+                }
+                text
+            }
+            (Token::And, Some(left), Some(right), _) => {
+                let operand = |node: &Node| match node.token {
+                    Token::Or => format!("({})", Self::render_node(node)),
+                    _ => Self::render_node(node),
+                };
+                format!("{} & {}", operand(left), operand(right))
+            }
+            (token, _, _, Some((tag_name, tag_value))) => {
+                let comparison = format!(
+                    "{} {} {}",
+                    tag_name,
+                    token.as_str(),
+                    tag_value.replace(',', ", ")
+                );
+                if node.negated {
+                    format!("!({})", comparison)
+                } else {
+                    comparison
+                }
+            }
+            (token, ..) => token.to_string(),
+        }
+    }
+
+    // The rule without its comparisons of the tag, or of the tag with the value when one is
+    // given, in canonical form. Each comparison is replaced with its value for an object that
+    // can't have the tag or value, so the rule still matches the objects it matched before.
+    // Ok(None) if the rule can no longer match anything.
+    // E.g. removing grey: "- colour = red, grey & size = large" -> "- colour = red & size = large"
+    pub fn remove_references(
+        rule: &str,
+        tag_name: &str,
+        tag_value: Option<&str>,
+    ) -> Result<Option<String>, RulesError> {
+        let tag_name = tag_name.trim().to_lowercase();
+        let tag_value = tag_value.map(|v| v.trim().to_lowercase());
+        let removed = |name: &str, value: &str| {
+            name == tag_name && tag_value.as_deref().is_none_or(|v| v == value)
+        };
+
+        let line = string::normalise_rule(rule)?;
+        let root = Self::build_ast(Self::tokenise_rule(&line)?)?;
+
+        match Self::prune(root, &removed)? {
+            Pruned::Node(node) => Ok(Some(format!(
+                "{}{}{}",
+                Self::rule_prefix(rule),
+                Self::render_node(&node),
+                Self::outcome_suffix(rule)?
+            ))),
+            Pruned::Always(false) => Ok(None),
+            Pruned::Always(true) => Err(RuleParseError::new(format!(
+                "'{}': Rule would match every object without '{}'",
+                rule.trim(),
+                match &tag_value {
+                    Some(value) => format!("{} = {}", tag_name, value),
+                    None => tag_name.clone(),
+                }
+            ))
+            .into()),
+        }
+    }
+
+    // The rule with the tag, or the tag's value when one is given, renamed to new_name, and
+    // the rest of the rule exactly as written. Only whole tokens are renamed, so renaming
+    // grey doesn't touch "greyish". Ok(None) if the rule doesn't reference it.
+    // E.g. grey -> gray: "- colour = red,Grey & size ! large" -> "- colour = red,gray & size ! large"
+    pub fn rename_references(
+        rule: &str,
+        tag_name: &str,
+        tag_value: Option<&str>,
+        new_name: &str,
+    ) -> Result<Option<String>, RulesError> {
+        let original_line = rule.trim_end();
+        let line = string::normalise_rule(rule)?;
+        let offset = Self::rule_offset(original_line, &line);
+
+        let (tokens, spans) = Self::tokenise_rule_with_spans(&line)?;
+        let mapped = Self::map_rule_tokens(&tokens);
+        Self::check_rule_syntax(&mapped)?;
+
+        let tag_name = tag_name.trim().to_lowercase();
+        let tag_value = tag_value.map(|v| v.trim().to_lowercase());
+        let chars: Vec<char> = line.chars().collect();
+
+        // Spans of the tokens to rename. Comma shorthand repeats the tag name with the span
+        // of the comma, which is left alone.
+        let mut renamed: Vec<TokenSpan> = Vec::new();
+        for (i, (token, token_type, _)) in mapped.iter().enumerate() {
+            if *token_type != TokenType::TagName
+                || Self::operator_char(token).is_some()
+                || token.to_lowercase() != tag_name
+            {
+                continue;
+            }
+
+            let span = match &tag_value {
+                None => spans[i],
+                Some(tag_value) => match mapped.get(i + 2) {
+                    Some((value, _, _)) if value.to_lowercase() == *tag_value => spans[i + 2],
+                    _ => continue,
+                },
+            };
+            if chars.get(span.0) != Some(&',') && !renamed.contains(&span) {
+                renamed.push(span);
+            }
+        }
+
+        if renamed.is_empty() {
+            return Ok(None);
+        }
+
+        let mut chars: Vec<char> = original_line.chars().collect();
+        renamed.sort();
+        for (start, len) in renamed.into_iter().rev() {
+            chars.splice(offset + start..offset + start + len, new_name.chars());
+        }
+
+        Ok(Some(chars.into_iter().collect()))
+    }
+
+    // Adds the rule to an error, resolving its token to its span in the rule.
+    // offset is the column the tokenised rule starts at, after the leading dash.
+    fn add_error_context(
+        error: RulesError,
+        rule: &str,
+        offset: usize,
+        spans: &[TokenSpan],
+    ) -> RulesError {
         match error {
-            RulesError::RuleParseError(msg) => {
-                RulesError::RuleParseError(format!("'{}': {}", rule, msg))
+            RulesError::RuleParseError(mut e) => {
+                if let Some((start, len)) = e.token.take().and_then(|i| spans.get(i)) {
+                    e.column = Some(start + 1);
+                    e.len = *len;
+                }
+                e.column = e.column.map(|c| c + offset);
+                e.source.get_or_insert_with(|| rule.to_string());
+                RulesError::RuleParseError(e)
             }
             other => other,
         }
     }
 
     fn get_expected_token_type(
-        parsed_tokens: &Vec<String>,
-        paren_depth: i32,
+        parsed_tokens: &[String],
+        _paren_depth: i32,
     ) -> Result<TokenType, RulesError> {
         // If no tokens yet, first token should be TagName or opening paren
         if parsed_tokens.is_empty() {
             return Ok(TokenType::TagName);
         }
 
-        let last_token = parsed_tokens.last().unwrap();
-
         // Last token is an operator
-        let c = if last_token.len() == 1 {
-            last_token.chars().next()
-        } else {
-            None
-        };
+        let c = Self::token_operator_char(parsed_tokens, parsed_tokens.len() - 1);
 
         if let Some(ch) = c {
             // Last token is an operator
             if ALL_OP_CHARS.contains(&ch) {
-                if ch == '(' {
+                if Self::is_negation(parsed_tokens, parsed_tokens.len() - 1) {
+                    // After a prefix '!', expect a group or comparison as if it wasn't there
+                    Ok(TokenType::TagName)
+                } else if ch == '(' {
                     // After '(', could be TagName or another '(' for nesting
                     Ok(TokenType::TagName) // Both '(' and TagName are valid here
                 } else if ch == ')' {
-                    // After ')', could be LogicalOp, another ')', or end of expression
-                    if paren_depth > 0 {
-                        // Still inside parens, could be ')' or LogicalOp
-                        Ok(TokenType::LogicalOp) // Accept both
-                    } else {
-                        // All parens closed, must be LogicalOp or end
-                        Ok(TokenType::LogicalOp)
-                    }
+                    // After ')', could be LogicalOp, another ')' (if still inside parens),
+                    // or end of expression
+                    Ok(TokenType::LogicalOp)
                 } else if RHS_CHARS.contains(&ch) {
                     Ok(TokenType::TagValue)
                 } else if LHS_CHARS.contains(&ch) {
                     Ok(TokenType::TagName)
                 } else {
-                    Err(RulesError::RuleParseError(
-                        format!("Invalid token encountered: {}", ch).to_string(),
-                    ))
+                    Err(RuleParseError::at_token(
+                        format!("Invalid token encountered: {}", ch),
+                        parsed_tokens.len() - 1,
+                    )
+                    .into())
                 }
             } else {
-                Err(RulesError::RuleParseError(
-                    format!("Invalid token encountered: {}", ch).to_string(),
-                ))
+                Err(RuleParseError::at_token(
+                    format!("Invalid token encountered: {}", ch),
+                    parsed_tokens.len() - 1,
+                )
+                .into())
             }
         } else {
             // Last token is a word, check operator before it
@@ -124,19 +771,18 @@ impl RuleParser {
             }
 
             let second_to_last_token = &parsed_tokens[parsed_tokens.len() - 2];
-            if second_to_last_token.len() > 1 {
-                return Err(RulesError::RuleParseError(
+            let Some(c) = Self::token_operator_char(parsed_tokens, parsed_tokens.len() - 2) else {
+                return Err(RuleParseError::at_token(
                     format!(
                         "Expected operator but got string instead: {}",
                         second_to_last_token
-                    )
-                    .to_string(),
-                ));
-            }
-
-            let c: char = second_to_last_token.chars().next().unwrap();
+                    ),
+                    parsed_tokens.len() - 1,
+                )
+                .into());
+            };
 
-            if c == '(' {
+            if c == '(' || Self::is_negation(parsed_tokens, parsed_tokens.len() - 2) {
                 Ok(TokenType::ComparisonOp)
             } else if c == ')' {
                 Ok(TokenType::TagName)
@@ -145,117 +791,93 @@ impl RuleParser {
             } else if LHS_CHARS.contains(&c) {
                 Ok(TokenType::ComparisonOp)
             } else {
-                Err(RulesError::RuleParseError(
-                    format!("Invalid token encountered: {}", c).to_string(),
-                ))
+                Err(RuleParseError::at_token(
+                    format!("Invalid token encountered: {}", c),
+                    parsed_tokens.len() - 1,
+                )
+                .into())
             }
         }
     }
 
-    fn tokenise_rule(rule: &String) -> Result<Vec<String>, RulesError> {
-        let mut parsed_tokens: Vec<String> = Vec::new();
-        let mut current_word = String::new();
-        let mut paren_depth = 0;
-
-        // For comma expansion
-        let mut last_tag_name: Option<String> = None;
-        let mut last_comparison_op: Option<String> = None;
-
-        for c in rule.trim().chars() {
-            if ALL_OP_CHARS.contains(&c) {
-                if !current_word.is_empty() {
-                    let expected_token_type =
-                        Self::get_expected_token_type(&parsed_tokens, paren_depth)?;
-                    let token = current_word.trim().to_string();
-                    parsed_tokens.push(token.clone());
-
-                    if expected_token_type == TokenType::TagName {
-                        last_tag_name = Some(token);
-                    }
-
-                    current_word.clear();
-                }
-
-                // Expand comma to regular OR expression
-                if c == ',' {
-                    let tag_name = last_tag_name.as_ref().ok_or_else(|| {
-                        RulesError::RuleParseError(
-                            "Comma must follow a complete tag comparison".to_string(),
-                        )
-                    })?;
-                    let comparison_op = last_comparison_op.as_ref().ok_or_else(|| {
-                        RulesError::RuleParseError(
-                            "Comma must follow a complete tag comparison".to_string(),
-                        )
-                    })?;
-
-                    parsed_tokens.push("|".to_string());
-                    parsed_tokens.push(tag_name.to_string());
-                    parsed_tokens.push(comparison_op.to_string());
-
-                    continue;
-                }
-
-                let expected_token_type =
-                    Self::get_expected_token_type(&parsed_tokens, paren_depth)?;
-                let token = c.to_string();
-                parsed_tokens.push(token.clone());
-
-                if expected_token_type == TokenType::ComparisonOp {
-                    last_comparison_op = Some(token);
-                }
+    // True if the token at index is a prefix '!' negating the group or comparison after it,
+    // rather than a not-equals comparison -- e.g. "!(colour = red)" vs "colour ! red"
+    fn is_negation(parsed_tokens: &[String], index: usize) -> bool {
+        parsed_tokens[index] == "!"
+            && matches!(
+                Self::get_expected_token_type(&parsed_tokens[..index], 0),
+                Ok(TokenType::TagName)
+            )
+    }
 
-                if c == '(' {
-                    paren_depth += 1;
-                } else if c == ')' {
-                    paren_depth -= 1;
-                    if paren_depth < 0 {
-                        return Err(RulesError::RuleParseError(
-                            "Unmatched closing parenthesis".to_string(),
-                        ));
-                    }
-                }
-            } else if c == ' ' {
-                if !current_word.is_empty() {
-                    let expected_token_type =
-                        Self::get_expected_token_type(&parsed_tokens, paren_depth)?;
-                    let token = current_word.trim().to_string();
-                    parsed_tokens.push(token.clone());
-
-                    if expected_token_type == TokenType::TagName {
-                        last_tag_name = Some(token);
-                    }
+    // True if the token at index is the "in" comparison operator, which it only is straight
+    // after a TagName -- e.g. "age in [18,65)", but not "status = in"
+    fn is_in_keyword(parsed_tokens: &[String], index: usize) -> bool {
+        parsed_tokens
+            .get(index)
+            .is_some_and(|t| t.eq_ignore_ascii_case(IN_KEYWORD))
+            && index > 0
+            && Self::token_operator_char(parsed_tokens, index - 1).is_none()
+    }
 
-                    current_word.clear();
+    // Operator character of the token at index, which for "in" depends on where it is
+    // E.g. "age in [18,65)" -> '=' for "in", "status = in" -> None for "in"
+    fn token_operator_char(parsed_tokens: &[String], index: usize) -> Option<char> {
+        if Self::is_in_keyword(parsed_tokens, index) {
+            return Some('=');
+        }
+        Self::operator_char(&parsed_tokens[index])
+    }
+
+    // Operator character of an operator token, or None for words -- including "in", which
+    // can also be a word
+    // E.g. "&" -> '&', ">=" -> '>', "3" -> None
+    fn operator_char(token: &str) -> Option<char> {
+        match token {
+            ">=" => Some('>'),
+            "<=" => Some('<'),
+            _ => {
+                let mut chars = token.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if ALL_OP_CHARS.contains(&c) => Some(c),
+                    _ => None,
                 }
-            } else {
-                current_word.push(c);
             }
         }
+    }
 
-        if !current_word.is_empty() {
-            let token = current_word.trim().to_string();
-            parsed_tokens.push(token);
-        }
+    fn tokenise_rule(rule: &str) -> Result<Vec<String>, RulesError> {
+        let (tokens, _spans) = Self::tokenise_rule_with_spans(rule)?;
+        Ok(tokens)
+    }
 
-        if paren_depth != 0 {
-            return Err(RulesError::RuleParseError(
-                "Unmatched opening parenthesis".to_string(),
-            ));
+    // Tokens of a rule, and the span of the rule each token was read from.
+    // Tokens added by comma expansion have the span of the comma.
+    fn tokenise_rule_with_spans(rule: &str) -> Result<(Vec<String>, Vec<TokenSpan>), RulesError> {
+        let mut tokeniser = Tokeniser::default();
+        let leading_spaces = rule.chars().take_while(|c| c.is_whitespace()).count();
+
+        for (i, c) in rule.trim().chars().enumerate() {
+            tokeniser.next_char(leading_spaces + i, c)?;
         }
 
-        Ok(parsed_tokens)
+        tokeniser.finish()
     }
 
-    fn map_rule_tokens(tokens: &Vec<String>) -> MappedRuleTokens {
+    fn map_rule_tokens(tokens: &[String]) -> MappedRuleTokens {
         let mut mapped_token_list: Vec<(String, TokenType, TokenDepth)> = Vec::new();
         let mut parsed_tokens: Vec<String> = Vec::new();
         let mut paren_depth = 0;
 
         for token in tokens {
-            let expected_token_type = Self::get_expected_token_type(&parsed_tokens, paren_depth);
+            let mut expected_token_type =
+                Self::get_expected_token_type(&parsed_tokens, paren_depth).unwrap();
+            if token == "!" && expected_token_type == TokenType::TagName {
+                expected_token_type = TokenType::Negation;
+            }
+
             parsed_tokens.push(token.clone());
-            mapped_token_list.push((token.clone(), expected_token_type.unwrap(), paren_depth));
+            mapped_token_list.push((token.clone(), expected_token_type, paren_depth));
 
             if token == "(" {
                 paren_depth += 1;
@@ -268,86 +890,159 @@ impl RuleParser {
     }
 
     fn check_rule_syntax(tokens: &MappedRuleTokens) -> Result<(), RulesError> {
-        let mut prev_token: Option<&TokenType> = None;
+        let mut prev_token: Option<(usize, &TokenType)> = None;
 
-        for (key, token_type, _paren_depth) in tokens.iter() {
+        for (i, (key, token_type, _paren_depth)) in tokens.iter().enumerate() {
             if key == "(" || key == ")" {
                 continue;
             }
 
-            match (prev_token, token_type) {
+            match (prev_token.map(|(_, t)| t), token_type) {
                 // Valid transitions
                 (None, TokenType::TagName) => {}
                 (Some(TokenType::TagName), TokenType::ComparisonOp) => {}
                 (Some(TokenType::ComparisonOp), TokenType::TagValue) => {}
-                (Some(TokenType::TagValue), TokenType::LogicalOp) => {}
+                (Some(TokenType::TagValue), TokenType::LogicalOp) if key == "&" || key == "|" => {}
                 (Some(TokenType::LogicalOp), TokenType::TagName) => {}
 
+                // A prefix '!' goes wherever a group or comparison can start
+                (None | Some(TokenType::LogicalOp | TokenType::Negation), TokenType::Negation) => {}
+                (Some(TokenType::Negation), TokenType::TagName) => {}
+
                 // Invalid transitions
                 (None, _) => {
-                    return Err(RulesError::RuleParseError(format!(
-                        "Rule must start with a tag name, found {:?}",
-                        token_type
-                    )));
+                    return Err(RuleParseError::at_token(
+                        format!("Rule must start with a tag name, found {:?}", token_type),
+                        i,
+                    )
+                    .into());
+                }
+                (Some(TokenType::TagValue), TokenType::LogicalOp) => {
+                    return Err(RuleParseError::at_token(
+                        format!("Expected '&' or '|' after a TagValue, found '{}'", key),
+                        i,
+                    )
+                    .into());
                 }
                 (Some(prev), current) => {
-                    return Err(RulesError::RuleParseError(format!(
-                        "Invalid token sequence: {:?} followed by {:?}",
-                        prev, current
-                    )));
+                    return Err(RuleParseError::at_token(
+                        format!(
+                            "Invalid token sequence: {:?} followed by {:?}",
+                            prev, current
+                        ),
+                        i,
+                    )
+                    .into());
                 }
             }
 
-            prev_token = Some(token_type);
+            prev_token = Some((i, token_type));
         }
 
         match prev_token {
-            Some(TokenType::TagValue) => Ok(()),
-            Some(other) => Err(RulesError::RuleParseError(format!(
-                "Rule must end with a tag value, ended with {:?}",
-                other
-            ))),
-            None => Err(RulesError::RuleParseError("Empty rule".to_string())),
+            Some((_, TokenType::TagValue)) => Ok(()),
+            Some((i, other)) => Err(RuleParseError::at_token(
+                format!("Rule must end with a tag value, ended with {:?}", other),
+                i,
+            )
+            .into()),
+            None => Err(RuleParseError::at_span("Empty rule", 0, 0).into()),
         }
     }
 
     fn check_valid_tags(&self, tokens: &MappedRuleTokens) -> Result<(), RulesError> {
         let mut last_tag_name: Option<String> = None;
+        let mut last_comparison_op: Option<(usize, String)> = None;
 
-        for (key, token_type, _paren_depth) in tokens.iter() {
+        for (i, (key, token_type, _paren_depth)) in tokens.iter().enumerate() {
             let key = String::from(key).to_lowercase();
-            if *token_type == TokenType::TagName {
+            if *token_type == TokenType::Negation {
+                continue;
+            } else if *token_type == TokenType::TagName {
                 if key == "(" || key == ")" {
                     continue;
                 }
 
                 if !self.m_mapped_tags.contains_key(&key) {
-                    return Err(RulesError::RuleParseError(format!(
-                        "Rule contains invalid TagName: {}",
-                        key
-                    )));
+                    return Err(RuleParseError::at_token(
+                        format!(
+                            "Rule contains invalid TagName: {}{}",
+                            key,
+                            string::did_you_mean(&key, self.m_mapped_tags.keys())
+                        ),
+                        i,
+                    )
+                    .into());
                 }
                 last_tag_name = Some(key.clone());
+            } else if *token_type == TokenType::ComparisonOp {
+                last_comparison_op = Some((i, key.clone()));
             } else if *token_type == TokenType::TagValue {
                 let tag_name = last_tag_name.as_ref().ok_or_else(|| {
-                    RulesError::RuleParseError(format!(
-                        "TagValue '{}' has no associated TagName",
-                        key
-                    ))
+                    RuleParseError::at_token(
+                        format!("TagValue '{}' has no associated TagName", key),
+                        i,
+                    )
                 })?;
 
                 let valid_values = self.m_mapped_tags.get(tag_name).ok_or_else(|| {
-                    RulesError::RuleParseError(format!(
-                        "No TagName '{}' found for TagValue '{}'",
-                        tag_name, key
-                    ))
+                    RuleParseError::at_token(
+                        format!("No TagName '{}' found for TagValue '{}'", tag_name, key),
+                        i,
+                    )
                 })?;
 
+                let (op_index, op) = last_comparison_op
+                    .as_ref()
+                    .map(|(op_index, op)| (*op_index, op.as_str()))
+                    .unwrap_or((i, ""));
+                if let Some(numeric) = tags::numeric_tag(valid_values) {
+                    if let Err(e) = Self::check_numeric_value(&numeric, op, &key) {
+                        return Err(RuleParseError::at_token(
+                            format!(
+                                "Rule contains invalid TagValue for numeric TagName '{}': {}",
+                                tag_name, e
+                            ),
+                            i,
+                        )
+                        .into());
+                    }
+                    continue;
+                }
+
+                if key.contains("..") {
+                    return Err(RuleParseError::at_token(
+                        format!(
+                            "Range '{}' is only valid for numeric tags, but TagName '{}' is not numeric",
+                            key, tag_name
+                        ),
+                        i,
+                    )
+                    .into());
+                }
+
+                if NUMERIC_ONLY_OPS.contains(&op) {
+                    return Err(RuleParseError::at_token(
+                        format!(
+                            "Comparison '{}' is only valid for numeric tags, but TagName '{}' is not numeric",
+                            op, tag_name
+                        ),
+                        op_index,
+                    )
+                    .into());
+                }
+
                 if !valid_values.contains(&key) {
-                    return Err(RulesError::RuleParseError(format!(
-                        "Rule contains invalid TagValue: '{}' is not a valid value for TagName '{}'",
-                        key, tag_name
-                    )));
+                    return Err(RuleParseError::at_token(
+                        format!(
+                            "Rule contains invalid TagValue: '{}' is not a valid value for TagName '{}'{}",
+                            key,
+                            tag_name,
+                            string::did_you_mean(&key, valid_values)
+                        ),
+                        i,
+                    )
+                    .into());
                 }
             }
         }
@@ -355,12 +1050,52 @@ impl RuleParser {
         Ok(())
     }
 
-    fn find_lowest_prec_op_index(tokens: &Vec<String>) -> usize {
+    // Checks a value of a numeric tag, which may be a range or interval
+    // E.g. "4", "10..20" after '=' or '!', "[18,65)" after "in"
+    fn check_numeric_value(
+        numeric: &types::NumericTag,
+        op: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        let Some(interval) = Interval::parse(op, value)? else {
+            return numeric.parse_value(value).map(|_| ());
+        };
+
+        if op != "=" && op != "!" && op != IN_KEYWORD {
+            return Err(format!(
+                "Comparison '{}' cannot be used with range '{}', use '=' or '!'",
+                op, value
+            ));
+        }
+
+        let lower = match &interval.lower {
+            Some(bound) => Some((numeric.parse_value(&bound.value)?, bound.inclusive)),
+            None => None,
+        };
+        let upper = match &interval.upper {
+            Some(bound) => Some((numeric.parse_value(&bound.value)?, bound.inclusive)),
+            None => None,
+        };
+
+        if let (Some((min, min_inclusive)), Some((max, max_inclusive))) = (lower, upper)
+            && (min > max || (min == max && !(min_inclusive && max_inclusive)))
+        {
+            return Err(format!("Range '{}' does not contain any values", value));
+        }
+
+        Ok(())
+    }
+
+    fn find_lowest_prec_op_index(tokens: &[String]) -> usize {
         let mut lowest_prec_token: Option<(usize, TokenDepth, i32)> = None; // Index, parenthesis depth and precedence
-        let mapped_tokens: MappedRuleTokens = Self::map_rule_tokens(&tokens);
+        let mapped_tokens: MappedRuleTokens = Self::map_rule_tokens(tokens);
 
         for (i, token) in mapped_tokens.iter().enumerate() {
             let paren_depth: i32 = token.2;
+            // Words are never operators, even "in" as a tag name or value
+            if matches!(token.1, TokenType::TagName | TokenType::TagValue) {
+                continue;
+            }
             let token: &String = &token.0;
 
             if let Some(&token_prec) = TOKEN_PRECEDENCE.get(token.as_str()) {
@@ -368,9 +1103,9 @@ impl RuleParser {
 
                 if let Some(lowest) = lowest_prec_token {
                     let lowest_prec_token_depth: i32 = lowest.1;
-                    if paren_depth < lowest_prec_token_depth {
-                        reassign = true;
-                    } else if lowest_prec_token_depth == paren_depth && token_prec < lowest.2 {
+                    if paren_depth < lowest_prec_token_depth
+                        || (lowest_prec_token_depth == paren_depth && token_prec < lowest.2)
+                    {
                         reassign = true;
                     }
                 } else {
@@ -390,63 +1125,184 @@ impl RuleParser {
         tokens.iter().any(|t| t == "&" || t == "|")
     }
 
+    // Errors are at the index of the token in tokens
     fn create_leaf_node(tokens: Vec<String>) -> Result<Node, RulesError> {
         if tokens.len() != 3 {
-            return Err(RulesError::RuleParseError(format!(
-                "Invalid leaf node: expected 3 tokens, got {}",
-                tokens.len()
-            )));
+            return Err(RuleParseError::at_token(
+                format!("Invalid leaf node: expected 3 tokens, got {}", tokens.len()),
+                0,
+            )
+            .into());
         }
 
         // Middle token is comparator
         let operator = &tokens[1];
-        let token = if operator == "=" {
-            crate::parser::types::Token::Equals
-        } else if operator == "!" {
-            crate::parser::types::Token::NotEquals
-        } else {
-            return Err(RulesError::RuleParseError(format!(
-                "Invalid comparison operator: {}",
-                operator
-            )));
+        let token = match Token::from_token(operator) {
+            Some(
+                token @ (Token::Equals
+                | Token::NotEquals
+                | Token::Greater
+                | Token::GreaterEq
+                | Token::Less
+                | Token::LessEq
+                | Token::In),
+            ) => token,
+            _ => {
+                return Err(RuleParseError::at_token(
+                    format!("Invalid comparison operator: {}", operator),
+                    1,
+                )
+                .into());
+            }
         };
 
         Ok(Node {
             token,
             left: None,
             right: None,
+            tag_kv: Some((tokens[0].to_lowercase(), tokens[2].to_lowercase())),
+            negated: false,
         })
     }
 
-    // Recursive function to build AST from tokens
-    fn build_ast(tokens: Vec<String>) -> Result<Node, RulesError> {
-        let tokens: Vec<String> = if tokens.len() > 2
-            && tokens.first() == Some(&"(".to_string())
-            && tokens.last() == Some(&")".to_string())
-        {
-            tokens[1..tokens.len() - 1].to_vec()
-        } else {
-            tokens
-        };
+    // True if the first token is an opening paren whose match is the last token
+    // E.g. ["(", "a", ")"] is wrapped, ["(", "a", ")", "&", "(", "b", ")"] is not
+    fn is_wrapped_in_parens(tokens: &[String]) -> bool {
+        if tokens.len() < 2 || tokens[0] != "(" || tokens[tokens.len() - 1] != ")" {
+            return false;
+        }
 
-        // If no logical operators, this is a leaf node
-        // E.g., ["colour", "=", "red"] is a leaf
-        if !Self::contains_logical_op(&tokens) {
-            return Self::create_leaf_node(tokens);
+        let mut depth = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            if token == "(" {
+                depth += 1;
+            } else if token == ")" {
+                depth -= 1;
+                if depth == 0 {
+                    return i == tokens.len() - 1;
+                }
+            }
         }
 
-        let op_index = Self::find_lowest_prec_op_index(&tokens);
+        false
+    }
+
+    // Index just past the operand of a prefix '!' at the start of tokens
+    // E.g. ["!", "(", "a", "=", "b", ")", "&", ...] -> 6, ["!", "a", "=", "b"] -> 4
+    fn negated_operand_end(tokens: &[String]) -> usize {
+        let start = tokens.iter().take_while(|t| *t == "!").count();
+        if tokens.get(start).is_none_or(|t| t != "(") {
+            return start + 3;
+        }
+
+        let mut depth = 0;
+        for (i, token) in tokens.iter().enumerate().skip(start) {
+            if token == "(" {
+                depth += 1;
+            } else if token == ")" {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+        }
+
+        tokens.len()
+    }
+
+    // Pushes a negation down to the comparisons with De Morgan's laws
+    // !(A & B) -> !A | !B, !(A | B) -> !A & !B, !(colour = red) -> colour ! red
+    // Orderings and intervals have no opposite operator that also matches objects without
+    // the tag, so are marked as negated instead -- e.g. !(doors > 2) matches doors: 1 and
+    // objects without doors, but not doors: [1, 5]
+    // position is the index of the '!' in the rule's tokens, for errors
+    fn negate(node: Node, position: usize) -> Result<Node, RulesError> {
+        let token = match node.token {
+            Token::And | Token::Or => {
+                let token = if node.token == Token::And {
+                    Token::Or
+                } else {
+                    Token::And
+                };
+                let left = node.left.map(|n| Self::negate(*n, position)).transpose()?;
+                let right = node.right.map(|n| Self::negate(*n, position)).transpose()?;
+
+                return Ok(Node {
+                    token,
+                    left: left.map(Box::new),
+                    right: right.map(Box::new),
+                    tag_kv: None,
+                    negated: false,
+                });
+            }
+            Token::Equals => Token::NotEquals,
+            Token::NotEquals => Token::Equals,
+            Token::Greater | Token::GreaterEq | Token::Less | Token::LessEq | Token::In => {
+                return Ok(Node {
+                    negated: !node.negated,
+                    ..node
+                });
+            }
+            other => {
+                return Err(RuleParseError::at_token(
+                    format!("Cannot negate token: {}", other),
+                    position,
+                )
+                .into());
+            }
+        };
+
+        Ok(Node { token, ..node })
+    }
+
+    // Recursive function to build AST from tokens
+    fn build_ast(tokens: Vec<String>) -> Result<Node, RulesError> {
+        Self::build_ast_at(tokens, 0)
+    }
+
+    // Same as build_ast, with start the index of the first of tokens in the rule's tokens,
+    // so errors are at the token they are about
+    fn build_ast_at(tokens: Vec<String>, start: usize) -> Result<Node, RulesError> {
+        let mut tokens: Vec<String> = tokens;
+        let mut start = start;
+        while Self::is_wrapped_in_parens(&tokens) {
+            tokens = tokens[1..tokens.len() - 1].to_vec();
+            start += 1;
+        }
+
+        // A prefix '!' applying to the whole of tokens is pushed down to its comparisons
+        // E.g. ["!", "(", "colour", "=", "red", ")"] becomes colour ! red
+        if tokens.first().is_some_and(|t| t == "!")
+            && Self::negated_operand_end(&tokens) == tokens.len()
+        {
+            let operand = Self::build_ast_at(tokens[1..].to_vec(), start + 1)?;
+            return Self::negate(operand, start);
+        }
+
+        // If no logical operators, this is a leaf node
+        // E.g., ["colour", "=", "red"] is a leaf
+        if !Self::contains_logical_op(&tokens) {
+            return Self::create_leaf_node(tokens).map_err(|e| match e {
+                RulesError::RuleParseError(mut e) => {
+                    e.token = e.token.map(|i| start + i);
+                    RulesError::RuleParseError(e)
+                }
+                other => other,
+            });
+        }
+
+        let op_index = Self::find_lowest_prec_op_index(&tokens);
 
         let operator_str = &tokens[op_index];
-        let operator_token = if operator_str == "&" {
-            crate::parser::types::Token::And
-        } else if operator_str == "|" {
-            crate::parser::types::Token::Or
-        } else {
-            return Err(RulesError::RuleParseError(format!(
-                "Expected logical operator, found: {}",
-                operator_str
-            )));
+        let operator_token = match Token::from_token(operator_str) {
+            Some(token @ (Token::And | Token::Or)) => token,
+            _ => {
+                return Err(RuleParseError::at_token(
+                    format!("Expected logical operator, found: {}", operator_str),
+                    start + op_index,
+                )
+                .into());
+            }
         };
 
         // Split tokens into left and right subtrees
@@ -456,84 +1312,452 @@ impl RuleParser {
         let right_tokens = tokens[op_index + 1..].to_vec();
 
         // Build subtrees
-        let left_child = Self::build_ast(left_tokens)?;
-        let right_child = Self::build_ast(right_tokens)?;
+        let left_child = Self::build_ast_at(left_tokens, start)?;
+        let right_child = Self::build_ast_at(right_tokens, start + op_index + 1)?;
 
         Ok(Node {
             token: operator_token,
             left: Some(Box::new(left_child)),
             right: Some(Box::new(right_child)),
+            tag_kv: None,
+            negated: false,
         })
     }
 
     fn string_to_rule(&self, rule_str: &str) -> Result<AstRule, RulesError> {
         // Validate the rule syntax first
-        Self::validate_rule(&self, rule_str)?;
+        self.validate_rule(rule_str)?;
 
         // Tokenize the rule string into a vector of tokens
-        // E.g., "colour = red & size = large" becomes:
+        // E.g., "- colour = red & size = large" becomes:
         // ["colour", "=", "red", "&", "size", "=", "large"]
-        let tokens = Self::tokenise_rule(&rule_str.to_string())?;
-        let root: Node = Self::build_ast(tokens)?;
+        let original_line = rule_str.trim_end();
+        let line = string::normalise_rule(rule_str)?;
+        let offset = Self::rule_offset(original_line, &line);
+        let (tokens, spans) = Self::tokenise_rule_with_spans(&line)?;
+        let root: Node = Self::build_ast(tokens)
+            .map_err(|e| Self::add_error_context(e, original_line, offset, &spans))?;
 
         Ok(AstRule { root_node: root })
     }
 
+    fn child<'a>(node: &'a Node, child: &'a Option<Box<Node>>) -> Result<&'a Node, RulesError> {
+        child.as_deref().ok_or_else(|| {
+            RuleParseError::new(format!("{:?} node is missing an operand", node.token)).into()
+        })
+    }
+
     fn ast_to_dnf(&self, node: &Node) -> Result<Vec<SubRule>, RulesError> {
         match node.token {
-            Token::Equals | Token::NotEquals => {
-                // create subrule
-                Ok(vec![])
+            Token::Equals
+            | Token::NotEquals
+            | Token::Greater
+            | Token::GreaterEq
+            | Token::Less
+            | Token::LessEq
+            | Token::In => {
+                let (tag_name, tag_value) = node.tag_kv.clone().ok_or_else(|| {
+                    RuleParseError::new(format!(
+                        "{:?} node is missing its tag name and value",
+                        node.token
+                    ))
+                })?;
+
+                let comparison_op = match node.token {
+                    Token::Equals => ComparisonOp::ISEQ,
+                    Token::NotEquals => ComparisonOp::NOEQ,
+                    Token::Greater => ComparisonOp::GRTH,
+                    Token::GreaterEq => ComparisonOp::GREQ,
+                    Token::Less => ComparisonOp::LETH,
+                    _ => ComparisonOp::LEEQ,
+                };
+
+                // A negated ordering is the complement of the interval it's true for
+                // E.g. "!(doors > 2)" becomes (doors not in (2,))
+                let interval = if node.negated && node.token != Token::In {
+                    Interval::from_ordering(comparison_op, &tag_value)
+                } else {
+                    Interval::parse(node.token.as_str(), &tag_value).map_err(RuleParseError::new)?
+                };
+
+                // A range or interval is a single clause, so one of the object's values has to
+                // be within both bounds -- e.g. "weight = 10..20" becomes (weight in [10,20]),
+                // and "weight ! 10..20" its exact complement (weight not in [10,20])
+                if let Some(interval) = interval {
+                    let comparison_op = if node.token == Token::NotEquals || node.negated {
+                        ComparisonOp::NOIN
+                    } else {
+                        ComparisonOp::ISIN
+                    };
+
+                    return Ok(vec![SubRule {
+                        rule_number: 0,
+                        expected_count: 1,
+                        actual_count: 0,
+                        comparison_ops: vec![comparison_op],
+                        tag_kvs: vec![(tag_name, interval.to_string())],
+                    }]);
+                }
+
+                // Single clause subrule
+                Ok(vec![SubRule {
+                    rule_number: 0,
+                    expected_count: 1,
+                    actual_count: 0,
+                    comparison_ops: vec![comparison_op],
+                    tag_kvs: vec![(tag_name, tag_value)],
+                }])
             }
             Token::Or => {
-                let left_clauses = self.ast_to_dnf(node.left.as_ref().unwrap())?;
-                let right_clauses = self.ast_to_dnf(node.right.as_ref().unwrap())?;
-                // concatenate the clauses (add)
-                Ok(vec![])
+                let mut left_clauses = self.ast_to_dnf(Self::child(node, &node.left)?)?;
+                let right_clauses = self.ast_to_dnf(Self::child(node, &node.right)?)?;
+
+                // (A | B) | (C) -> A | B | C
+                left_clauses.extend(right_clauses);
+                Ok(left_clauses)
             }
             Token::And => {
-                let left_clauses = self.ast_to_dnf(node.left.as_ref().unwrap())?;
-                let right_clauses = self.ast_to_dnf(node.right.as_ref().unwrap())?;
-                // cross multiply
-                Ok(vec![])
+                let left_clauses = self.ast_to_dnf(Self::child(node, &node.left)?)?;
+                let right_clauses = self.ast_to_dnf(Self::child(node, &node.right)?)?;
+
+                // (A | B) & (C | D) -> (A & C) | (A & D) | (B & C) | (B & D)
+                let mut product: Vec<SubRule> =
+                    Vec::with_capacity(left_clauses.len() * right_clauses.len());
+                for left in &left_clauses {
+                    for right in &right_clauses {
+                        let mut combined = left.clone();
+                        combined
+                            .comparison_ops
+                            .extend(right.comparison_ops.iter().copied());
+                        combined.tag_kvs.extend(right.tag_kvs.iter().cloned());
+                        combined.expected_count = combined.tag_kvs.len() as i32;
+                        product.push(combined);
+                    }
+                }
+
+                Ok(product)
             }
-            _ => Err(RulesError::RuleParseError(
-                format!("Invalid token: {}", node.token).to_string(),
-            )),
+            _ => Err(RuleParseError::new(format!("Invalid token: {}", node.token)).into()),
         }
     }
 
-    fn rule_to_dnf_subrule(&self, rule: AstRule) -> Result<SubRule, RulesError> {
+    fn rule_to_dnf_subrules(&self, rule: AstRule) -> Result<Vec<SubRule>, RulesError> {
         self.ast_to_dnf(&rule.root_node)
     }
 
     // Main entry point for parsing rule files.
-    // Converts all .rules files into Disjunctive Normal Form (DNF) subrules.
+    // Converts all .rules files into their rules and Disjunctive Normal Form (DNF) subrules.
     pub fn parse_rules(
+        config_dir: &str,
+        mapped_tags: HashMap<types::TagName, types::TagValues>,
+    ) -> Result<(Vec<Rule>, Vec<SubRule>), RulesError> {
+        let all_files = tables::read_rule_files(config_dir, &mapped_tags)?;
+        Self::parse_rule_files(mapped_tags, &all_files)
+    }
+
+    // True if the line is a metadata annotation of the next rule -- e.g. "@owner: sales"
+    pub fn is_annotation(line: &str) -> bool {
+        line.trim_start().starts_with(ANNOTATION_PREFIX)
+    }
+
+    // Adds an annotation line to the metadata of the rule after it
+    fn add_annotation(rule: &mut Rule, line: &str) -> Result<(), RulesError> {
+        let annotation = line.trim().trim_start_matches(ANNOTATION_PREFIX);
+        let (key, value) = annotation.split_once(':').ok_or_else(|| {
+            RuleParseError::new(format!(
+                "Annotation must be written as '@key: value', found '{}'",
+                line.trim()
+            ))
+        })?;
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+
+        if value.is_empty() {
+            return Err(RuleParseError::new(format!("Annotation '@{}' has no value", key)).into());
+        }
+
+        let duplicate = || {
+            RuleParseError::new(format!(
+                "Annotation '@{}' is given more than once for the rule",
+                key
+            ))
+            .into()
+        };
+
+        match key.as_str() {
+            OWNER_ANNOTATION | DESCRIPTION_ANNOTATION => {
+                let field = if key == OWNER_ANNOTATION {
+                    &mut rule.owner
+                } else {
+                    &mut rule.description
+                };
+                if field.is_some() {
+                    return Err(duplicate());
+                }
+                *field = Some(value.to_string());
+            }
+            PRIORITY_ANNOTATION => {
+                if rule.priority.is_some() {
+                    return Err(duplicate());
+                }
+                rule.priority = Some(value.parse::<i32>().map_err(|_| {
+                    RuleParseError::new(format!("Priority must be an integer, found '{}'", value))
+                })?);
+            }
+            _ => {
+                return Err(RuleParseError::new(format!(
+                    "Unknown annotation '@{}', expected @{}, @{} or @{}",
+                    key, OWNER_ANNOTATION, DESCRIPTION_ANNOTATION, PRIORITY_ANNOTATION
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Indexes of the annotation lines that belong to the rule at the index, in order.
+    // Blank and comment lines between them are skipped, but not kept.
+    pub fn annotation_lines<S: AsRef<str>>(lines: &[S], rule_index: usize) -> Vec<usize> {
+        let mut annotations: Vec<usize> = (0..rule_index)
+            .rev()
+            .map(|i| (i, lines[i].as_ref()))
+            .take_while(|(_, line)| file::line_blank_or_comment(line) || Self::is_annotation(line))
+            .filter(|(_, line)| Self::is_annotation(line))
+            .map(|(i, _)| i)
+            .collect();
+        annotations.reverse();
+        annotations
+    }
+
+    // Name of a named rule -- e.g. "free_shipping" for "- [free_shipping] colour = red"
+    pub(crate) fn rule_name(rule: &str) -> Option<&str> {
+        rule.trim_end()
+            .strip_prefix('-')
+            .and_then(|rest| string::split_rule_name(rest).ok())
+            .and_then(|(name, _)| name)
+    }
+
+    // Start of a rule in canonical form, with its name if it has one -- e.g. "- [free_shipping] "
+    fn rule_prefix(rule: &str) -> String {
+        match Self::rule_name(rule) {
+            Some(name) => format!("- [{}] ", name),
+            None => "- ".to_string(),
+        }
+    }
+
+    // Rule lines of a .rules file, with their 0-based line and the metadata of their rule,
+    // or the error and line of an annotation that can't be read. Annotations belong to the
+    // next rule in the same file.
+    fn rule_lines<'a>(file_name: &str, content: &'a str) -> Vec<RuleLine<'a>> {
+        let mut lines = Vec::new();
+        let mut rule = Rule::default();
+        let mut annotation_line: Option<usize> = None;
+
+        for (i, line) in content.lines().enumerate() {
+            if file::line_blank_or_comment(line) {
+                continue;
+            }
+
+            if Self::is_annotation(line) {
+                match Self::add_annotation(&mut rule, line) {
+                    Ok(()) => {
+                        annotation_line.get_or_insert(i);
+                    }
+                    Err(e) => lines.push(Err((i, e))),
+                }
+                continue;
+            }
+
+            rule.name = Self::rule_name(line).map(str::to_string);
+            rule.file = file_name.to_string();
+            rule.line = i + 1;
+            lines.push(Ok((i, line, std::mem::take(&mut rule))));
+            annotation_line = None;
+        }
+
+        if let Some(i) = annotation_line {
+            lines.push(Err((
+                i,
+                RuleParseError::new("Annotation has no rule after it").into(),
+            )));
+        }
+
+        lines
+    }
+
+    // Checks no earlier rule has the rule's name, then remembers where it was used
+    fn check_unique_name(
+        names: &mut HashMap<String, (String, usize)>,
+        rule: &Rule,
+    ) -> Result<(), RulesError> {
+        let Some(name) = &rule.name else {
+            return Ok(());
+        };
+
+        if let Some((file_name, line)) = names.get(name) {
+            return Err(Self::name_used_error(name, file_name, *line));
+        }
+
+        names.insert(name.clone(), (rule.file.clone(), rule.line));
+        Ok(())
+    }
+
+    // Error for a rule named like the rule at the 1-based line of the file
+    pub(crate) fn name_used_error(name: &str, file_name: &str, line: usize) -> RulesError {
+        RuleParseError::new(format!(
+            "Rule name '{}' is already used by the rule at {}:{}",
+            name, file_name, line
+        ))
+        .into()
+    }
+
+    // Adds the file and 1-based line number a rule was read from to its error
+    fn add_file_context(error: RulesError, file_name: &str, line_number: usize) -> RulesError {
+        match error {
+            RulesError::RuleParseError(mut e) => {
+                e.file = Some(file_name.to_string());
+                e.line = Some(line_number);
+                RulesError::RuleParseError(e)
+            }
+            other => other,
+        }
+    }
+
+    // Formats every rule in the contents of a .rules file, keeping comments and blank lines
+    pub fn format_rule_file(file_name: &str, content: &str) -> Result<String, RulesError> {
+        let mut formatted = String::new();
+
+        for (i, line) in content.lines().enumerate() {
+            if file::line_blank_or_comment(line) || Self::is_annotation(line) {
+                formatted.push_str(line.trim_end());
+            } else {
+                let rule = Self::format_rule(line)
+                    .map_err(|e| Self::add_file_context(e, file_name, i + 1))?;
+                formatted.push_str(&rule);
+            }
+            formatted.push('\n');
+        }
+
+        Ok(formatted)
+    }
+
+    // Same as parse_rules, but for the names and contents of .rules files already in memory.
+    // Rules are numbered in order across all files. Fails on the first invalid rule or
+    // annotation.
+    pub fn parse_rule_files(
+        mapped_tags: HashMap<types::TagName, types::TagValues>,
+        all_files: &[(String, String)],
+    ) -> Result<(Vec<Rule>, Vec<SubRule>), RulesError> {
+        let (rules, dnf_subrules, issues) = Self::check_rule_files(mapped_tags, all_files);
+
+        let first_error = issues.into_iter().find_map(|issue| match issue {
+            RuleFileIssue::Error(e, file_name, line) => {
+                Some(Self::add_file_context(e, &file_name, line))
+            }
+            RuleFileIssue::Warning(_) => None,
+        });
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok((rules, dnf_subrules)),
+        }
+    }
+
+    // Same as parse_rule_files, but keeps going past invalid rules. Returns the rules and
+    // subrules of the valid rules, numbered in order, with an error for each invalid rule or
+    // annotation and a warning for each rule that repeats an earlier one.
+    pub fn parse_rule_files_with_diagnostics(
+        mapped_tags: HashMap<types::TagName, types::TagValues>,
+        all_files: &[(String, String)],
+    ) -> (Vec<Rule>, Vec<SubRule>, Vec<Diagnostic>) {
+        let (rules, dnf_subrules, issues) = Self::check_rule_files(mapped_tags, all_files);
+
+        let diagnostics = issues
+            .into_iter()
+            .map(|issue| match issue {
+                RuleFileIssue::Error(e, file_name, line) => Diagnostic::error(e, &file_name, line),
+                RuleFileIssue::Warning(warning) => warning,
+            })
+            .collect();
+
+        (rules, dnf_subrules, diagnostics)
+    }
+
+    // Parses every rule of the files, keeping the valid rules and their subrules numbered in
+    // order, with the problems found in file order
+    fn check_rule_files(
         mapped_tags: HashMap<types::TagName, types::TagValues>,
-    ) -> Result<Vec<SubRule>, RulesError> {
+        all_files: &[(String, String)],
+    ) -> (Vec<Rule>, Vec<SubRule>, Vec<RuleFileIssue>) {
         let parser = RuleParser {
             m_mapped_tags: mapped_tags,
         };
 
+        let mut rules: Vec<Rule> = Vec::new();
         let mut dnf_subrules: Vec<SubRule> = Vec::new();
-        let all_files: Vec<String> = file::read_files_in_dir("config/*.rules")?;
-
-        for file in all_files.iter() {
-            for line in file.lines() {
-                if file::line_blank_or_comment(line) {
-                    continue;
+        let mut issues: Vec<RuleFileIssue> = Vec::new();
+        let mut names: HashMap<String, (String, usize)> = HashMap::new();
+
+        // Rules seen so far in canonical form with their priority, and where they were first
+        // seen
+        let mut seen: HashMap<(String, Option<i32>), (String, usize)> = HashMap::new();
+
+        for (file_name, content) in all_files.iter() {
+            for rule_line in Self::rule_lines(file_name, content) {
+                let (i, line, mut rule) = match rule_line {
+                    Ok(rule_line) => rule_line,
+                    Err((i, e)) => {
+                        issues.push(RuleFileIssue::Error(e, file_name.clone(), i + 1));
+                        continue;
+                    }
+                };
+
+                // Parse string to AST, then convert to DNF representation. Only a rule that
+                // parses takes its name, so a broken rule doesn't also clash with a later one.
+                let parsed = parser
+                    .string_to_rule(line)
+                    .and_then(|ast| parser.rule_to_dnf_subrules(ast))
+                    .and_then(|subrules| Ok((subrules, Self::rule_outcome(line)?)))
+                    .and_then(|parsed| Self::check_unique_name(&mut names, &rule).map(|_| parsed));
+                let (mut subrules, outcome) = match parsed {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        issues.push(RuleFileIssue::Error(e, file_name.clone(), i + 1));
+                        continue;
+                    }
+                };
+                rule.outcome = outcome;
+
+                // Only the whole rule is a repeat -- its name, conditions, outcome and priority
+                let canonical = Self::format_rule(line).unwrap_or_else(|_| line.to_string());
+                match seen.get(&(canonical.clone(), rule.priority)) {
+                    Some((first_file, first_line)) => {
+                        issues.push(RuleFileIssue::Warning(Diagnostic::warning(
+                            format!(
+                                "Rule is the same as the rule at {}:{}",
+                                first_file, first_line
+                            ),
+                            file_name,
+                            i + 1,
+                        )))
+                    }
+                    None => {
+                        seen.insert((canonical, rule.priority), (file_name.clone(), i + 1));
+                    }
                 }
 
-                // Parse string to AST, then convert to DNF representation
-                let rule: AstRule = parser.string_to_rule(line)?;
-                let subrule: SubRule = parser.rule_to_dnf_subrule(rule)?;
+                // Every subrule remembers which rule it came from
+                rule.rule_number = rules.len() as types::RuleNumber;
+                for subrule in subrules.iter_mut() {
+                    subrule.rule_number = rule.rule_number;
+                }
 
-                dnf_subrules.push(subrule);
+                dnf_subrules.extend(subrules);
+                rules.push(rule);
             }
         }
 
-        Ok(dnf_subrules)
+        (rules, dnf_subrules, issues)
     }
 }
 
@@ -665,7 +1889,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_simple_rule() {
         let rule = "colour = red";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -676,7 +1900,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_with_parentheses() {
         let rule = "(colour = red)";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -687,7 +1911,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_nested_parentheses() {
         let rule = "((colour = red))";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -698,7 +1922,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_with_and_operator() {
         let rule = "colour = red & size = large";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -712,7 +1936,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_with_or_operator() {
         let rule = "colour = red | colour = blue";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -726,7 +1950,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_with_not_equals() {
         let rule = "colour ! red";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -737,7 +1961,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_complex_nested() {
         let rule = "((colour = red) & (size = large))";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -753,11 +1977,11 @@ mod tests {
     #[test]
     fn test_tokenise_rule_unmatched_opening_paren() {
         let rule = "(colour = red";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Unmatched opening parenthesis"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Unmatched opening parenthesis"));
         } else {
             panic!("Expected RuleParseError about unmatched opening parenthesis");
         }
@@ -766,11 +1990,11 @@ mod tests {
     #[test]
     fn test_tokenise_rule_unmatched_closing_paren() {
         let rule = "colour = red)";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Unmatched closing parenthesis"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Unmatched closing parenthesis"));
         } else {
             panic!("Expected RuleParseError about unmatched closing parenthesis");
         }
@@ -779,7 +2003,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_extra_whitespace() {
         let rule = "  colour   =   red  ";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -790,7 +2014,7 @@ mod tests {
     #[test]
     fn test_tokenise_rule_no_spaces() {
         let rule = "colour=red";
-        let result = RuleParser::tokenise_rule(&rule.to_string());
+        let result = RuleParser::tokenise_rule(rule);
 
         assert!(result.is_ok());
         let tokens = result.unwrap();
@@ -1091,8 +2315,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("must start with a tag name"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("must start with a tag name"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1106,8 +2330,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("must start with a tag name"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("must start with a tag name"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1123,8 +2347,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Invalid token sequence"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Invalid token sequence"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1140,8 +2364,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Invalid token sequence"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Invalid token sequence"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1157,8 +2381,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Invalid token sequence"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Invalid token sequence"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1176,8 +2400,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Invalid token sequence"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Invalid token sequence"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1194,8 +2418,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("must end with a tag value"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("must end with a tag value"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1209,8 +2433,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("must end with a tag value"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("must end with a tag value"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1226,8 +2450,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("must end with a tag value"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("must end with a tag value"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1238,8 +2462,8 @@ mod tests {
         let tokens = vec![];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Empty rule"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Empty rule"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1253,8 +2477,8 @@ mod tests {
         ];
         let result = RuleParser::check_rule_syntax(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("Empty rule"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("Empty rule"));
         } else {
             panic!("Expected RuleParseError");
         }
@@ -1279,6 +2503,8 @@ mod tests {
             "shape".to_string(),
             vec!["circle".to_string(), "square".to_string()],
         );
+        tags.insert("doors".to_string(), vec!["integer(2..5)".to_string()]);
+        tags.insert("price".to_string(), vec!["decimal(..)".to_string()]);
         tags
     }
 
@@ -1317,9 +2543,9 @@ mod tests {
 
         let result = parser.check_valid_tags(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("invalid TagName"));
-            assert!(msg.contains("invalid_tag"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("invalid TagName"));
+            assert!(e.message.contains("invalid_tag"));
         } else {
             panic!("Expected RuleParseError about invalid tag name");
         }
@@ -1339,9 +2565,9 @@ mod tests {
 
         let result = parser.check_valid_tags(&tokens);
         assert!(result.is_err());
-        if let Err(RulesError::RuleParseError(msg)) = result {
-            assert!(msg.contains("invalid TagValue"));
-            assert!(msg.contains("purple"));
+        if let Err(RulesError::RuleParseError(e)) = result {
+            assert!(e.message.contains("invalid TagValue"));
+            assert!(e.message.contains("purple"));
         } else {
             panic!("Expected RuleParseError about invalid tag value");
         }
@@ -1479,8 +2705,10 @@ mod tests {
                 "Expected rule to be invalid due to unknown tag: {}",
                 rule
             );
-            if let Err(RulesError::RuleParseError(msg)) = result {
-                assert!(msg.contains("invalid TagName") || msg.contains("invalid TagValue"));
+            if let Err(RulesError::RuleParseError(e)) = result {
+                assert!(
+                    e.message.contains("invalid TagName") || e.message.contains("invalid TagValue")
+                );
             }
         }
     }
@@ -1500,9 +2728,1206 @@ mod tests {
                 "Expected rule to be invalid due to unknown value: {}",
                 rule
             );
-            if let Err(RulesError::RuleParseError(msg)) = result {
-                assert!(msg.contains("invalid TagValue"));
+            if let Err(RulesError::RuleParseError(e)) = result {
+                assert!(e.message.contains("invalid TagValue"));
+            }
+        }
+    }
+
+    // Helper to parse a single rule line into DNF subrules
+    fn rule_to_dnf(rule: &str) -> Vec<SubRule> {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+        let ast = parser.string_to_rule(rule).unwrap();
+        parser.rule_to_dnf_subrules(ast).unwrap()
+    }
+
+    // Tests for build_ast with parenthesised groups
+    #[test]
+    fn test_build_ast_does_not_strip_unmatched_outer_parens() {
+        let tokens = RuleParser::tokenise_rule("(colour = red) & (size = large)").unwrap();
+        let result = RuleParser::build_ast(tokens);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().token, Token::And);
+    }
+
+    #[test]
+    fn test_build_ast_strips_nested_outer_parens() {
+        let tokens = RuleParser::tokenise_rule("((colour = red))").unwrap();
+        let node = RuleParser::build_ast(tokens).unwrap();
+
+        assert_eq!(node.token, Token::Equals);
+        assert_eq!(node.tag_kv, Some(("colour".to_string(), "red".to_string())));
+    }
+
+    // Tests for ast_to_dnf
+    #[test]
+    fn test_ast_to_dnf_leaf() {
+        let subrules = rule_to_dnf("- colour ! red");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(subrules[0].expected_count, 1);
+        assert_eq!(subrules[0].actual_count, 0);
+        assert_eq!(subrules[0].comparison_ops, vec![ComparisonOp::NOEQ]);
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![("colour".to_string(), "red".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_or_concatenates() {
+        let subrules = rule_to_dnf("- colour = red | size = large");
+
+        assert_eq!(subrules.len(), 2);
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![("colour".to_string(), "red".to_string())]
+        );
+        assert_eq!(
+            subrules[1].tag_kvs,
+            vec![("size".to_string(), "large".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_and_combines_clauses() {
+        let subrules = rule_to_dnf("- colour = red & size ! small");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(subrules[0].expected_count, 2);
+        assert_eq!(
+            subrules[0].comparison_ops,
+            vec![ComparisonOp::ISEQ, ComparisonOp::NOEQ]
+        );
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![
+                ("colour".to_string(), "red".to_string()),
+                ("size".to_string(), "small".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_readme_example() {
+        // (colour=blue & shape!circle) | (colour=red & shape!circle)
+        let subrules = rule_to_dnf("- (colour = blue, red) & shape ! circle");
+
+        assert_eq!(subrules.len(), 2);
+        for subrule in &subrules {
+            assert_eq!(subrule.expected_count, 2);
+            assert_eq!(
+                subrule.comparison_ops,
+                vec![ComparisonOp::ISEQ, ComparisonOp::NOEQ]
+            );
+        }
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![
+                ("colour".to_string(), "blue".to_string()),
+                ("shape".to_string(), "circle".to_string())
+            ]
+        );
+        assert_eq!(
+            subrules[1].tag_kvs,
+            vec![
+                ("colour".to_string(), "red".to_string()),
+                ("shape".to_string(), "circle".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_and_cross_multiplies() {
+        let subrules =
+            rule_to_dnf("- (colour = red | colour = blue) & (size = small | size = large)");
+
+        assert_eq!(subrules.len(), 4);
+        let pairs: Vec<(String, String)> = subrules
+            .iter()
+            .map(|sr| (sr.tag_kvs[0].1.clone(), sr.tag_kvs[1].1.clone()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("red".to_string(), "small".to_string()),
+                ("red".to_string(), "large".to_string()),
+                ("blue".to_string(), "small".to_string()),
+                ("blue".to_string(), "large".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_nested_precedence() {
+        // colour=red | (size=small & shape=circle) -> 2 subrules of 1 and 2 clauses
+        let subrules = rule_to_dnf("- colour = red | size = small & shape = circle");
+
+        assert_eq!(subrules.len(), 2);
+        assert_eq!(subrules[0].expected_count, 1);
+        assert_eq!(subrules[1].expected_count, 2);
+    }
+
+    #[test]
+    fn test_ast_to_dnf_lowercases_clauses() {
+        let subrules = rule_to_dnf("- Colour = RED");
+
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![("colour".to_string(), "red".to_string())]
+        );
+    }
+
+    // Tests for numeric tags
+    #[test]
+    fn test_tokenise_rule_numeric_comparisons() {
+        let tokens = RuleParser::tokenise_rule("doors>=4 & price<9.99").unwrap();
+        assert_eq!(tokens, vec!["doors", ">=", "4", "&", "price", "<", "9.99"]);
+
+        let tokens = RuleParser::tokenise_rule("doors = 3 | doors <= 2").unwrap();
+        assert_eq!(tokens, vec!["doors", "=", "3", "|", "doors", "<=", "2"]);
+    }
+
+    #[test]
+    fn test_validate_rule_numeric() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let valid_rules = vec![
+            "- doors >= 4",
+            "- doors > 2 & doors < 5",
+            "- doors = 3 | colour = red",
+            "- doors ! 4",
+            "- price <= 10.5",
+            "- price > -5",
+        ];
+
+        for rule in valid_rules {
+            assert!(
+                parser.validate_rule(rule).is_ok(),
+                "Expected rule to be valid: {}",
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_rule_numeric_invalid() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let invalid_rules = vec![
+            ("- doors >= four", "'four' is not an integer"),
+            ("- doors > 2.5", "'2.5' is not an integer"),
+            ("- doors < 9", "'9' is above the maximum of 5"),
+            ("- price > cheap", "'cheap' is not a number"),
+            (
+                "- colour > red",
+                "Comparison '>' is only valid for numeric tags",
+            ),
+            (
+                "- size <= large",
+                "Comparison '<=' is only valid for numeric tags",
+            ),
+        ];
+
+        for (rule, expected) in invalid_rules {
+            match parser.validate_rule(rule) {
+                Err(RulesError::RuleParseError(e)) => {
+                    assert!(
+                        e.message.contains(expected),
+                        "'{}' should contain '{}'",
+                        e.message,
+                        expected
+                    )
+                }
+                other => panic!("Expected RuleParseError for '{}', got {:?}", rule, other),
             }
         }
     }
+
+    #[test]
+    fn test_ast_to_dnf_numeric_ops() {
+        let subrules = rule_to_dnf("- doors > 2 & doors <= 4 | price >= 1.5 & price < 3");
+
+        assert_eq!(subrules.len(), 2);
+        assert_eq!(
+            subrules[0].comparison_ops,
+            vec![ComparisonOp::GRTH, ComparisonOp::LEEQ]
+        );
+        assert_eq!(
+            subrules[1].comparison_ops,
+            vec![ComparisonOp::GREQ, ComparisonOp::LETH]
+        );
+        assert_eq!(
+            subrules[1].tag_kvs,
+            vec![
+                ("price".to_string(), "1.5".to_string()),
+                ("price".to_string(), "3".to_string()),
+            ]
+        );
+    }
+
+    // Tests for ranges and intervals
+    #[test]
+    fn test_tokenise_rule_intervals() {
+        let tokens = RuleParser::tokenise_rule("doors = 2..4 & price in [1.5, 10)").unwrap();
+        assert_eq!(
+            tokens,
+            vec!["doors", "=", "2..4", "&", "price", "in", "[1.5,10)"]
+        );
+
+        let tokens = RuleParser::tokenise_rule("(price in (0,5]) | colour = red").unwrap();
+        assert_eq!(
+            tokens,
+            vec!["(", "price", "in", "(0,5]", ")", "|", "colour", "=", "red"]
+        );
+
+        let tokens = RuleParser::tokenise_rule("price in [1, 2), [5, 6)").unwrap();
+        assert_eq!(
+            tokens,
+            vec!["price", "in", "[1,2)", "|", "price", "in", "[5,6)"]
+        );
+
+        assert!(RuleParser::tokenise_rule("price in [1, 2").is_err());
+    }
+
+    #[test]
+    fn test_validate_rule_intervals() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let valid_rules = vec![
+            "- doors = 2..4",
+            "- doors = 3..",
+            "- doors ! ..3",
+            "- doors = 2..3, 5..5",
+            "- price in [1.5, 10)",
+            "- price IN (, 0]",
+            "- price in [-5, 5] & colour = red",
+        ];
+
+        for rule in valid_rules {
+            assert!(
+                parser.validate_rule(rule).is_ok(),
+                "Expected rule to be valid: {}",
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_rule_intervals_invalid() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let invalid_rules = vec![
+            ("- doors = 4..2", "does not contain any values"),
+            ("- price in (5, 5]", "does not contain any values"),
+            ("- doors = 2..9", "'9' is above the maximum of 5"),
+            ("- doors = ..", "has no bounds"),
+            ("- price in [1 5]", "Interval must be written as"),
+            ("- price in 5", "Interval must be written as"),
+            ("- doors > 2..4", "cannot be used with range"),
+            ("- size = small..large", "only valid for numeric tags"),
+            ("- colour in [red, blue]", "Comparison 'in' is only valid"),
+        ];
+
+        for (rule, expected) in invalid_rules {
+            match parser.validate_rule(rule) {
+                Err(RulesError::RuleParseError(e)) => {
+                    assert!(
+                        e.message.contains(expected),
+                        "'{}' should contain '{}'",
+                        e.message,
+                        expected
+                    )
+                }
+                other => panic!("Expected RuleParseError for '{}', got {:?}", rule, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_in_is_only_an_operator_after_a_tag_name() {
+        let mut tags = create_test_tags();
+        tags.insert(
+            "status".to_string(),
+            vec!["in".to_string(), "out".to_string()],
+        );
+        tags.insert("in".to_string(), vec!["yes".to_string(), "no".to_string()]);
+        let parser = RuleParser {
+            m_mapped_tags: tags,
+        };
+
+        for rule in [
+            "- status = in & size = large",
+            "- status = out, in",
+            "- status ! in",
+            "- (status = in) | in = yes",
+            "- in = yes & price in [1, 2)",
+        ] {
+            assert!(
+                parser.validate_rule(rule).is_ok(),
+                "Expected rule to be valid: {} ({:?})",
+                rule,
+                parser.validate_rule(rule)
+            );
+        }
+
+        let ast = parser
+            .string_to_rule("- status = in & size = large")
+            .unwrap();
+        let subrules = parser.rule_to_dnf_subrules(ast).unwrap();
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![
+                ("status".to_string(), "in".to_string()),
+                ("size".to_string(), "large".to_string()),
+            ]
+        );
+        assert_eq!(
+            RuleParser::format_rule("-STATUS=IN,out").unwrap(),
+            "- status = in, out"
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_range() {
+        let subrules = rule_to_dnf("- doors = 2..4 & colour = red");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(subrules[0].expected_count, 2);
+        assert_eq!(
+            subrules[0].comparison_ops,
+            vec![ComparisonOp::ISIN, ComparisonOp::ISEQ]
+        );
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![
+                ("doors".to_string(), "[2,4]".to_string()),
+                ("colour".to_string(), "red".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_interval() {
+        let subrules = rule_to_dnf("- price in (1.5, 10]");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(subrules[0].comparison_ops, vec![ComparisonOp::ISIN]);
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![("price".to_string(), "(1.5,10]".to_string())]
+        );
+
+        let subrules = rule_to_dnf("- price in [, 10)");
+        assert_eq!(subrules[0].comparison_ops, vec![ComparisonOp::ISIN]);
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![("price".to_string(), "(,10)".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_negated_range() {
+        // doors ! 2..4 -> (doors not in [2,4])
+        let subrules = rule_to_dnf("- doors ! 2..4 & colour = red");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(
+            subrules[0].comparison_ops,
+            vec![ComparisonOp::NOIN, ComparisonOp::ISEQ]
+        );
+        assert_eq!(
+            subrules[0].tag_kvs[0],
+            ("doors".to_string(), "[2,4]".to_string())
+        );
+    }
+
+    // Tests for prefix negation
+    fn clauses(subrule: &SubRule) -> Vec<(ComparisonOp, &str, &str)> {
+        subrule
+            .comparison_ops
+            .iter()
+            .zip(&subrule.tag_kvs)
+            .map(|(op, (name, value))| (*op, name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_map_rule_tokens_negation() {
+        let tokens = RuleParser::tokenise_rule("!(colour = red) & size ! large").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                "!", "(", "colour", "=", "red", ")", "&", "size", "!", "large"
+            ]
+        );
+
+        let mapped = RuleParser::map_rule_tokens(&tokens);
+        assert_eq!(mapped[0].1, TokenType::Negation);
+        assert_eq!(mapped[2].1, TokenType::TagName);
+        assert_eq!(mapped[8].1, TokenType::ComparisonOp);
+        assert_eq!(mapped[9].1, TokenType::TagValue);
+    }
+
+    #[test]
+    fn test_validate_rule_negation() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let valid_rules = vec![
+            "- !(colour = red & size = large)",
+            "- !colour = red",
+            "- size = small & !(colour = red | shape ! circle)",
+            "- !!(colour = red)",
+            "- !(!(colour = red) | (size = large))",
+            "- !(doors > 2 & price in [1, 5))",
+        ];
+
+        for rule in valid_rules {
+            assert!(
+                parser.validate_rule(rule).is_ok(),
+                "Expected rule to be valid: {}",
+                rule
+            );
+        }
+
+        let invalid_rules = vec![
+            "- !",
+            "- colour = red !",
+            "- colour = red ! (size = large)",
+            "- (colour = red) !",
+            "- colour ! ! red",
+        ];
+
+        for rule in invalid_rules {
+            assert!(
+                parser.validate_rule(rule).is_err(),
+                "Expected rule to be invalid: {}",
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn test_ast_to_dnf_negated_and() {
+        // !(colour=red & size=large) -> colour!red | size!large
+        let subrules = rule_to_dnf("- !(colour = red & size = large)");
+
+        assert_eq!(subrules.len(), 2);
+        assert_eq!(
+            clauses(&subrules[0]),
+            vec![(ComparisonOp::NOEQ, "colour", "red")]
+        );
+        assert_eq!(
+            clauses(&subrules[1]),
+            vec![(ComparisonOp::NOEQ, "size", "large")]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_negated_or() {
+        // size=small & !(colour=red | shape!circle) -> size=small & colour!red & shape=circle
+        let subrules = rule_to_dnf("- size = small & !(colour = red, blue | shape ! circle)");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(
+            clauses(&subrules[0]),
+            vec![
+                (ComparisonOp::ISEQ, "size", "small"),
+                (ComparisonOp::NOEQ, "colour", "red"),
+                (ComparisonOp::NOEQ, "colour", "blue"),
+                (ComparisonOp::ISEQ, "shape", "circle"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_double_negation() {
+        let subrules = rule_to_dnf("- !(!(colour = red) & size = large)");
+
+        assert_eq!(subrules.len(), 2);
+        assert_eq!(
+            clauses(&subrules[0]),
+            vec![(ComparisonOp::ISEQ, "colour", "red")]
+        );
+        assert_eq!(
+            clauses(&subrules[1]),
+            vec![(ComparisonOp::NOEQ, "size", "large")]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_negated_leaf_binds_tightest() {
+        // !colour=red & size=large -> colour!red & size=large
+        let subrules = rule_to_dnf("- !colour = red & size = large");
+
+        assert_eq!(subrules.len(), 1);
+        assert_eq!(
+            clauses(&subrules[0]),
+            vec![
+                (ComparisonOp::NOEQ, "colour", "red"),
+                (ComparisonOp::ISEQ, "size", "large"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ast_to_dnf_negated_numeric() {
+        // !(doors > 2 & price in [1, 5)) -> !(doors > 2) | !(price in [1, 5)), each the exact
+        // complement, so they also match objects without doors or price
+        let subrules = rule_to_dnf("- !(doors > 2 & price in [1, 5))");
+
+        let all: Vec<_> = subrules.iter().flat_map(clauses).collect();
+        assert_eq!(
+            all,
+            vec![
+                (ComparisonOp::NOIN, "doors", "(2,)"),
+                (ComparisonOp::NOIN, "price", "[1,5)"),
+            ]
+        );
+        assert!(subrules.iter().all(|s| s.expected_count == 1));
+
+        // Negating twice gives back the comparison
+        let subrules = rule_to_dnf("- !(!(doors <= 2))");
+        assert_eq!(
+            clauses(&subrules[0]),
+            vec![(ComparisonOp::LEEQ, "doors", "2")]
+        );
+    }
+
+    // Tests for error spans
+    fn parse_error(result: Result<(), RulesError>) -> RuleParseError {
+        match result {
+            Err(RulesError::RuleParseError(e)) => *e,
+            other => panic!("Expected RuleParseError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tokenise_rule_with_spans() {
+        let (tokens, spans) =
+            RuleParser::tokenise_rule_with_spans("colour>=red, blue & (size ! large)").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                "colour", ">=", "red", "|", "colour", ">=", "blue", "&", "(", "size", "!", "large",
+                ")"
+            ]
+        );
+        assert_eq!(
+            spans,
+            vec![
+                (0, 6),
+                (6, 2),
+                (8, 3),
+                (11, 1),
+                (11, 1),
+                (11, 1),
+                (13, 4),
+                (18, 1),
+                (20, 1),
+                (21, 4),
+                (26, 1),
+                (28, 5),
+                (33, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_rule_error_spans() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let cases = vec![
+            // Invalid TagName, underlining the tag name
+            ("- colur = red", 3, 5),
+            // Invalid TagValue
+            ("-  colour = red & size = huge", 26, 4),
+            // Non-numeric comparison, underlining the operator
+            ("- colour >= red", 10, 2),
+            // Syntax errors
+            ("- colour = red size = large", 16, 4),
+            ("- colour = red &", 16, 1),
+            ("- (colour = red", 3, 1),
+            ("- colour = red)", 15, 1),
+            ("- price in [1, 5", 12, 1),
+            // A word where an operator should be, underlining the word
+            ("- colour red = blue", 10, 3),
+            // Outcome errors, underlining the pair or key
+            ("- colour = red =>", 16, 2),
+            ("- colour = red => tier", 19, 4),
+            ("- colour = red => a: 1, a: 2", 25, 1),
+            ("- colour = red => tier: gold, t!er: x", 31, 4),
+        ];
+
+        for (rule, column, len) in cases {
+            let e = parse_error(parser.validate_rule(rule));
+            assert_eq!(
+                (e.column, e.len),
+                (Some(column), len),
+                "Wrong span for '{}'",
+                rule
+            );
+            assert_eq!(e.source.as_deref(), Some(rule));
+            assert_eq!(e.file, None);
+        }
+    }
+
+    #[test]
+    fn test_parse_rule_files_error_location() {
+        let files = vec![
+            ("a.rules".to_string(), "- colour = red\n".to_string()),
+            (
+                "b.rules".to_string(),
+                "# Comment\n- size = large\n- colour = red & shap = circle\n".to_string(),
+            ),
+        ];
+
+        let e = match RuleParser::parse_rule_files(create_test_tags(), &files) {
+            Err(RulesError::RuleParseError(e)) => e,
+            other => panic!("Expected RuleParseError, got {:?}", other),
+        };
+
+        assert_eq!(e.location(), "b.rules:3:18");
+        assert_eq!(
+            e.render(),
+            "b.rules:3:18: Rule contains invalid TagName: shap, did you mean 'shape'?\n  \
+             |\n3 | - colour = red & shap = circle\n  |                  ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_rule_parse_error_display_without_file() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let e = parse_error(parser.validate_rule("- colour = rde"));
+        assert_eq!(
+            e.to_string(),
+            "column 12: Rule contains invalid TagValue: 'rde' is not a valid value for TagName 'colour', did you mean 'red'?"
+        );
+        assert_eq!(e.render().lines().last(), Some(" |            ^^^"));
+    }
+
+    // Tests for diagnostics
+    #[test]
+    fn test_parse_rule_files_with_diagnostics() {
+        let files = vec![
+            (
+                "a.rules".to_string(),
+                "- colour = red\n- colur = red\n- size = large\n".to_string(),
+            ),
+            (
+                "b.rules".to_string(),
+                "# Comment\n- (colour = blue\n- Colour=Red\n- shape = circle\n".to_string(),
+            ),
+        ];
+
+        let (rules, subrules, diagnostics) =
+            RuleParser::parse_rule_files_with_diagnostics(create_test_tags(), &files);
+
+        // Valid rules are numbered in order, skipping invalid ones
+        let rule_numbers: Vec<_> = subrules.iter().map(|s| s.rule_number).collect();
+        assert_eq!(rule_numbers, vec![0, 1, 2, 3]);
+        let rule_lines: Vec<_> = rules.iter().map(|r| (r.file.as_str(), r.line)).collect();
+        assert_eq!(
+            rule_lines,
+            vec![
+                ("a.rules", 1),
+                ("a.rules", 3),
+                ("b.rules", 3),
+                ("b.rules", 4)
+            ]
+        );
+        assert_eq!(
+            subrules[3].tag_kvs,
+            vec![("shape".to_string(), "circle".to_string())]
+        );
+
+        let found: Vec<(bool, &str, usize, Option<usize>)> = diagnostics
+            .iter()
+            .map(|d| (d.is_error(), d.file.as_str(), d.line, d.column))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (true, "a.rules", 2, Some(3)),
+                (true, "b.rules", 2, Some(3)),
+                (false, "b.rules", 3, None),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: a.rules:2:3: Rule contains invalid TagName: colur, did you mean 'colour'?"
+        );
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "warning: b.rules:3: Rule is the same as the rule at a.rules:1"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_repeats_and_names() {
+        let files = vec![(
+            "a.rules".to_string(),
+            "- colour = red => tier: gold\n- colour = red => tier: silver\n@priority: 5\n\
+             - colour = red => tier: gold\n- COLOUR=red=>tier:gold\n\
+             - [x] colur = red\n- [x] colour = blue\n"
+                .to_string(),
+        )];
+
+        let (rules, _, diagnostics) =
+            RuleParser::parse_rule_files_with_diagnostics(create_test_tags(), &files);
+
+        // Only the rule repeating the outcome and priority of line 1 is a repeat, and the rule
+        // that fails to parse doesn't take its name
+        let found: Vec<(bool, usize)> =
+            diagnostics.iter().map(|d| (d.is_error(), d.line)).collect();
+        assert_eq!(found, vec![(false, 5), (true, 6)], "{:?}", diagnostics);
+        assert_eq!(
+            diagnostics[0].message,
+            "Rule is the same as the rule at a.rules:1"
+        );
+        assert_eq!(rules.last().unwrap().name.as_deref(), Some("x"));
+
+        // The strict parser fails on the same first error
+        match RuleParser::parse_rule_files(create_test_tags(), &files) {
+            Err(RulesError::RuleParseError(e)) => {
+                assert_eq!((e.file.as_deref(), e.line), (Some("a.rules"), Some(6)));
+                assert!(e.message.contains("invalid TagName"), "{}", e.message);
+            }
+            other => panic!("Expected RuleParseError, got {:?}", other),
+        }
+    }
+
+    // Tests for named rules and annotations
+    #[test]
+    fn test_parse_named_rules_with_annotations() {
+        let files = vec![
+            (
+                "a.rules".to_string(),
+                "# Free shipping\n@owner: sales\n@Priority: 10\n- [free_shipping] colour = red & size = large\n- size = small\n"
+                    .to_string(),
+            ),
+            (
+                "b.rules".to_string(),
+                "@description: Blue things, any size\n\n-[blue.v2] colour = blue\n".to_string(),
+            ),
+        ];
+
+        let (rules, subrules) = RuleParser::parse_rule_files(create_test_tags(), &files).unwrap();
+
+        assert_eq!(
+            rules,
+            vec![
+                Rule {
+                    rule_number: 0,
+                    name: Some("free_shipping".to_string()),
+                    owner: Some("sales".to_string()),
+                    description: None,
+                    priority: Some(10),
+                    outcome: Outcome::new(),
+                    file: "a.rules".to_string(),
+                    line: 4,
+                },
+                Rule {
+                    rule_number: 1,
+                    file: "a.rules".to_string(),
+                    line: 5,
+                    ..Rule::default()
+                },
+                Rule {
+                    rule_number: 2,
+                    name: Some("blue.v2".to_string()),
+                    description: Some("Blue things, any size".to_string()),
+                    file: "b.rules".to_string(),
+                    line: 3,
+                    ..Rule::default()
+                },
+            ]
+        );
+        assert_eq!(
+            subrules[0].tag_kvs,
+            vec![
+                ("colour".to_string(), "red".to_string()),
+                ("size".to_string(), "large".to_string())
+            ]
+        );
+        assert_eq!(subrules[2].rule_number, 2);
+    }
+
+    #[test]
+    fn test_parse_named_rules_errors() {
+        let cases = vec![
+            (
+                "@team: sales\n- colour = red\n",
+                "Unknown annotation '@team'",
+            ),
+            ("@owner:\n- colour = red\n", "has no value"),
+            ("@owner sales\n- colour = red\n", "'@key: value'"),
+            ("@owner: a\n@owner: b\n- colour = red\n", "more than once"),
+            ("@priority: high\n- colour = red\n", "must be an integer"),
+            ("- colour = red\n@owner: sales\n", "has no rule after it"),
+            ("- [free shipping] colour = red\n", "Invalid rule name"),
+            ("- [free_shipping colour = red\n", "closing ']'"),
+            (
+                "- [red] colour = red\n- [red] colour = blue\n",
+                "Rule name 'red' is already used by the rule at a.rules:1",
+            ),
+        ];
+
+        for (content, expected) in cases {
+            let files = vec![("a.rules".to_string(), content.to_string())];
+            match RuleParser::parse_rule_files(create_test_tags(), &files) {
+                Err(RulesError::RuleParseError(e)) => assert!(
+                    e.message.contains(expected),
+                    "'{}' should contain '{}'",
+                    e.message,
+                    expected
+                ),
+                other => panic!("Expected RuleParseError for {:?}, got {:?}", content, other),
+            }
+        }
+
+        // Annotations don't carry over to the next file
+        let files = vec![
+            ("a.rules".to_string(), "@owner: sales\n".to_string()),
+            ("b.rules".to_string(), "- colour = red\n".to_string()),
+        ];
+        assert!(RuleParser::parse_rule_files(create_test_tags(), &files).is_err());
+    }
+
+    #[test]
+    fn test_named_rule_formatting_and_errors() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        assert_eq!(
+            RuleParser::format_rule("-[free_shipping]   colour=red").unwrap(),
+            "- [free_shipping] colour = red"
+        );
+        assert_eq!(
+            RuleParser::format_rule_file("a.rules", "@owner:  sales  \n-[a] colour=red\n").unwrap(),
+            "@owner:  sales\n- [a] colour = red\n"
+        );
+        assert_eq!(
+            RuleParser::remove_references("- [a] colour = red | size = large", "size", None)
+                .unwrap(),
+            Some("- [a] colour = red".to_string())
+        );
+
+        assert_eq!(
+            RuleParser::rename_references("- [a] colour = red", "colour", None, "hue").unwrap(),
+            Some("- [a] hue = red".to_string())
+        );
+
+        // Errors point at the rule as written, after its name
+        let e = parse_error(parser.validate_rule("- [a] colour = rde"));
+        assert_eq!((e.column, e.len), (Some(16), 3));
+    }
+
+    // Tests for outcomes
+    #[test]
+    fn test_rule_outcomes() {
+        let files = vec![(
+            "a.rules".to_string(),
+            "- [gold] colour = red & size = large => Discount: 10, tier: Gold-Plus\n- colour = blue\n"
+                .to_string(),
+        )];
+
+        let (rules, subrules) = RuleParser::parse_rule_files(create_test_tags(), &files).unwrap();
+        assert_eq!(
+            rules[0].outcome,
+            Outcome::from([
+                ("discount".to_string(), "10".to_string()),
+                ("tier".to_string(), "Gold-Plus".to_string())
+            ])
+        );
+        assert!(rules[1].outcome.is_empty());
+        assert_eq!(subrules[0].tag_kvs.len(), 2);
+
+        // Keys in the order written, values as written
+        assert_eq!(
+            RuleParser::format_rule("-colour=red=>tier:Gold,discount:10").unwrap(),
+            "- colour = red => tier: Gold, discount: 10"
+        );
+        assert_eq!(
+            RuleParser::remove_references(
+                "- colour = red | size = large => tier: gold",
+                "size",
+                None
+            )
+            .unwrap(),
+            Some("- colour = red => tier: gold".to_string())
+        );
+        assert_eq!(
+            RuleParser::rename_references("- colour = red => colour: red", "colour", None, "hue")
+                .unwrap(),
+            Some("- hue = red => colour: red".to_string())
+        );
+        assert!(!RuleParser::references("- size = large => colour: red", "colour", None).unwrap());
+    }
+
+    #[test]
+    fn test_rule_outcome_errors() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let cases = vec![
+            ("- colour = red =>", "no outcome after it"),
+            ("- colour = red => tier", "'key: value, key: value'"),
+            ("- colour = red => tier:", "has no value"),
+            (
+                "- colour = red => my tier: gold",
+                "Invalid outcome key 'my tier'",
+            ),
+            (
+                "- colour = red => tier: gold, Tier: silver",
+                "more than once",
+            ),
+        ];
+
+        for (rule, expected) in cases {
+            match parser.validate_rule(rule) {
+                Err(RulesError::RuleParseError(e)) => assert!(
+                    e.message.contains(expected),
+                    "'{}' should contain '{}'",
+                    e.message,
+                    expected
+                ),
+                other => panic!("Expected RuleParseError for '{}', got {:?}", rule, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_annotation_lines() {
+        let lines = [
+            "@owner: a",
+            "- colour = red",
+            "@owner: b",
+            "# Comment",
+            "",
+            "@priority: 1",
+            "- colour = blue",
+        ];
+
+        assert_eq!(RuleParser::annotation_lines(&lines, 1), vec![0]);
+        assert_eq!(RuleParser::annotation_lines(&lines, 6), vec![2, 5]);
+        assert!(RuleParser::annotation_lines(&lines, 0).is_empty());
+    }
+
+    // Tests for suggestions
+    #[test]
+    fn test_validate_rule_suggests_closest_match() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let cases = vec![
+            (
+                "- colur = red",
+                "invalid TagName: colur, did you mean 'colour'?",
+            ),
+            ("- COLOR = red", "did you mean 'colour'?"),
+            ("- colour = rde", "TagName 'colour', did you mean 'red'?"),
+            ("- size = medum & shape = circle", "did you mean 'medium'?"),
+            ("- shape = sqaure", "did you mean 'square'?"),
+        ];
+
+        for (rule, expected) in cases {
+            let e = parse_error(parser.validate_rule(rule));
+            assert!(
+                e.message.contains(expected),
+                "'{}' should contain '{}'",
+                e.message,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_rule_no_suggestion_when_nothing_close() {
+        let parser = RuleParser {
+            m_mapped_tags: create_test_tags(),
+        };
+
+        let e = parse_error(parser.validate_rule("- weight = heavy"));
+        assert_eq!(e.message, "Rule contains invalid TagName: weight");
+
+        let e = parse_error(parser.validate_rule("- colour = purple"));
+        assert!(!e.message.contains("did you mean"));
+    }
+
+    // Tests for formatting
+    #[test]
+    fn test_format_rule() {
+        let cases = vec![
+            ("- colour = red", "- colour = red"),
+            (
+                "-(Colour=red,BLUE)&size!large",
+                "- (colour = red, blue) & size ! large",
+            ),
+            (
+                "-  doors>=4|price in [1 ,5)  ",
+                "- doors >= 4 | price in [1, 5)",
+            ),
+            (
+                "- !( colour = red ) & !size=large",
+                "- !(colour = red) & !size = large",
+            ),
+            ("- weight = 10..20, 30..", "- weight = 10..20, 30.."),
+        ];
+
+        for (rule, expected) in cases {
+            assert_eq!(RuleParser::format_rule(rule).unwrap(), expected);
+            // Formatting is idempotent
+            assert_eq!(RuleParser::format_rule(expected).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_format_rule_invalid_syntax() {
+        let e = parse_error(RuleParser::format_rule("- colour = red &").map(|_| ()));
+        assert_eq!(e.column, Some(16));
+
+        // Tags are not checked
+        assert!(RuleParser::format_rule("- unknown = value").is_ok());
+    }
+
+    #[test]
+    fn test_format_rule_file() {
+        let content = "# Colours\n-colour=RED   \n\n-(size=large)|shape!circle";
+        assert_eq!(
+            RuleParser::format_rule_file("test.rules", content).unwrap(),
+            "# Colours\n- colour = red\n\n- (size = large) | shape ! circle\n"
+        );
+
+        let e =
+            parse_error(RuleParser::format_rule_file("test.rules", "- a = b\n- a = ").map(|_| ()));
+        assert_eq!(e.location(), "test.rules:2:5");
+    }
+
+    #[test]
+    fn test_references() {
+        let rule = "- (Colour = red, GREY) & size ! large";
+        assert!(RuleParser::references(rule, "colour", None).unwrap());
+        assert!(RuleParser::references(rule, "colour", Some("grey")).unwrap());
+        assert!(RuleParser::references(rule, "size", Some("large")).unwrap());
+        assert!(!RuleParser::references(rule, "colour", Some("gre")).unwrap());
+        assert!(!RuleParser::references(rule, "col", None).unwrap());
+        assert!(!RuleParser::references(rule, "shape", None).unwrap());
+
+        assert!(RuleParser::references("- !(age in [18,65))", "age", None).unwrap());
+        assert!(RuleParser::references("- colour =", "colour", None).is_err());
+    }
+
+    #[test]
+    fn test_remove_references() {
+        let remove = |rule: &str, tag_name: &str, tag_value: Option<&str>| {
+            RuleParser::remove_references(rule, tag_name, tag_value).unwrap()
+        };
+        let grey = |rule: &str| remove(rule, "colour", Some("grey"));
+
+        // "colour = grey" is false
+        assert_eq!(
+            grey("- (colour = red, grey, blue) & size = large").as_deref(),
+            Some("- (colour = red, blue) & size = large")
+        );
+        // Comma alternatives bind looser than '&'
+        assert_eq!(
+            grey("- colour = red, grey, blue & size = large").as_deref(),
+            Some("- colour = red | colour = blue & size = large")
+        );
+        assert_eq!(
+            grey("- colour = grey | size = large").as_deref(),
+            Some("- size = large")
+        );
+        assert_eq!(grey("- colour = grey & size = large"), None);
+
+        // "colour ! grey" is true
+        assert_eq!(
+            grey("- colour ! grey & size = large").as_deref(),
+            Some("- size = large")
+        );
+        assert_eq!(
+            grey("- !(colour = grey | shape = circle)").as_deref(),
+            Some("- shape ! circle")
+        );
+
+        // Without the tag, ranges and orderings never match, so their negations always do
+        assert_eq!(
+            remove(
+                "- (age = 18..65 | size = large) & shape = circle",
+                "age",
+                None
+            )
+            .as_deref(),
+            Some("- size = large & shape = circle")
+        );
+        assert_eq!(
+            remove(
+                "- (age ! 18..65 | size = large) & shape = circle",
+                "age",
+                None
+            )
+            .as_deref(),
+            Some("- shape = circle")
+        );
+        assert_eq!(
+            remove("- !(doors > 2) & size = large", "doors", None).as_deref(),
+            Some("- size = large")
+        );
+        assert_eq!(remove("- doors > 2 & size = large", "doors", None), None);
+        assert_eq!(
+            remove("- !(doors > 2) & colour ! red", "colour", None).as_deref(),
+            Some("- !(doors > 2)")
+        );
+        assert_eq!(
+            remove("- age in [18,65) & colour ! red", "colour", None).as_deref(),
+            Some("- age in [18, 65)")
+        );
+
+        // A rule that would match everything can't be written
+        assert!(RuleParser::remove_references("- colour ! grey", "colour", Some("grey")).is_err());
+    }
+
+    #[test]
+    fn test_rename_references() {
+        let rename = |rule: &str, tag_value: Option<&str>, new_name: &str| {
+            RuleParser::rename_references(rule, "colour", tag_value, new_name).unwrap()
+        };
+
+        // Only whole tokens, keeping the rest of the rule as written
+        assert_eq!(
+            rename("-  (Colour=red,Grey)&colour!greyish", Some("grey"), "gray").as_deref(),
+            Some("-  (Colour=red,gray)&colour!greyish")
+        );
+        assert_eq!(
+            rename("- colour = grey, red | !(colour = grey)", None, "hue").as_deref(),
+            Some("- hue = grey, red | !(hue = grey)")
+        );
+        assert_eq!(rename("- colours = grey", None, "hue"), None);
+        assert_eq!(rename("- size = grey", Some("grey"), "gray"), None);
+
+        assert!(RuleParser::rename_references("- colour = ", "colour", None, "hue").is_err());
+    }
 }
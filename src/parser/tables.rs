@@ -0,0 +1,572 @@
+use std::collections::HashMap;
+
+// Parser for decision tables -- .csv files with a header row of tag names and an outcome
+// column, where each row below it is a rule requiring every one of its cells:
+//
+// colour,size,outcome
+// red,!small,"discount: 10, tier: gold"
+// -,large,tier: silver
+//
+// "-" or an empty cell matches any value, "!x" any value but x and "x, y" either value.
+// A row is converted to the equivalent rule on the same line of a .rules file, so rows parse
+// into SubRules like any other rule and errors keep the row number. A quoted cell can span
+// lines, and the row's rule is on its first line.
+//
+// A row of only "-" would match every object, which no rule can, so it is an error rather
+// than a default row. The default outcome is the one used when no row matches.
+use crate::err::{Diagnostic, RuleParseError, RulesError};
+use crate::parser::rules::RuleParser;
+use crate::types::{TagName, TagValues};
+use crate::utils::{file, string};
+
+// Reserved column holding the outcome of each row, as written after "=>" in a rule
+const OUTCOME_COLUMN: &str = "outcome";
+const ANY_CELL: &str = "-";
+const NOT_PREFIX: char = '!';
+// A cell holds values, not conditions, so it can't contain operators
+const OPERATOR_CHARS: [char; 8] = ['&', '|', '(', ')', '=', '<', '>', '!'];
+
+// Name and contents of a file
+type NamedFile = (String, String);
+
+enum Column {
+    Tag(TagName),
+    Outcome,
+}
+
+// Message of an error, without the rule it came from
+fn message(error: RulesError) -> String {
+    match error {
+        RulesError::RuleParseError(e) => e.message,
+        RulesError::TagParseError(msg) => msg,
+        other => other.to_string(),
+    }
+}
+
+// Trimmed fields of a CSV row. A field in double quotes can contain commas and line breaks,
+// which become spaces as a rule is a single line, and "" is a quote inside it.
+// E.g. `red,"discount: 10, tier: gold"` -> ["red", "discount: 10, tier: gold"]
+fn split_fields(line: &str) -> Result<Vec<String>, RulesError> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            '\n' => field.push(' '),
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(RuleParseError::new("Row has a '\"' without a closing quote").into());
+    }
+    fields.push(field.trim().to_string());
+
+    Ok(fields)
+}
+
+// Columns of the header row, checking each tag is loaded if the tags are known
+fn parse_header(
+    line: &str,
+    tags: Option<&HashMap<TagName, TagValues>>,
+) -> Result<Vec<Column>, RulesError> {
+    let mut columns: Vec<Column> = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+
+    for name in split_fields(line)? {
+        let name = name.to_lowercase();
+        if name.is_empty() {
+            return Err(RuleParseError::new(format!(
+                "Column {} of the header has no tag name",
+                names.len() + 1
+            ))
+            .into());
+        }
+        if names.contains(&name) {
+            return Err(RuleParseError::new(format!(
+                "Column '{}' appears more than once in the header",
+                name
+            ))
+            .into());
+        }
+
+        if name == OUTCOME_COLUMN {
+            columns.push(Column::Outcome);
+        } else {
+            if let Some(tags) = tags
+                && !tags.contains_key(&name)
+            {
+                return Err(RuleParseError::new(format!(
+                    "Column '{}' is not a tag{}",
+                    name,
+                    string::did_you_mean(&name, tags.keys())
+                ))
+                .into());
+            }
+            columns.push(Column::Tag(name.clone()));
+        }
+        names.push(name);
+    }
+
+    if !columns.iter().any(|c| matches!(c, Column::Tag(_))) {
+        return Err(RuleParseError::new("Header has no tag columns").into());
+    }
+
+    Ok(columns)
+}
+
+// Condition of a cell on its column's tag, None if the cell matches any value
+// E.g. "red" -> "colour = red", "!red, blue" -> "colour ! red & colour ! blue",
+// "red, blue" -> "(colour = red, blue)"
+fn cell_condition(tag: &str, cell: &str) -> Result<Option<String>, RulesError> {
+    if cell.is_empty() || cell == ANY_CELL {
+        return Ok(None);
+    }
+
+    let (negated, values) = match cell.strip_prefix(NOT_PREFIX) {
+        Some(values) => (true, values),
+        None => (false, cell),
+    };
+    let values: Vec<&str> = values.split(',').map(str::trim).collect();
+    if values
+        .iter()
+        .any(|v| v.is_empty() || v.contains(OPERATOR_CHARS))
+    {
+        return Err(RuleParseError::new(format!(
+            "Cell '{}' must be a value, '!value', 'value, value' or '-'",
+            cell
+        ))
+        .into());
+    }
+
+    let condition = match (negated, values.as_slice()) {
+        (false, [value]) => format!("{} = {}", tag, value),
+        // Parenthesised, as ',' binds looser than the '&' joining the cells
+        (false, values) => format!("({} = {})", tag, values.join(", ")),
+        (true, values) => values
+            .iter()
+            .map(|v| format!("{} ! {}", tag, v))
+            .collect::<Vec<String>>()
+            .join(" & "),
+    };
+
+    Ok(Some(condition))
+}
+
+// Rule equivalent to a row, with the conditions of its cells in column order, checking them
+// against the tags if the parser is given
+fn row_rule(
+    columns: &[Column],
+    line: &str,
+    parser: Option<&RuleParser>,
+) -> Result<String, RulesError> {
+    let cells = split_fields(line)?;
+    if cells.len() != columns.len() {
+        return Err(RuleParseError::new(format!(
+            "Row has {} cells but the header has {} columns, quote cells containing commas",
+            cells.len(),
+            columns.len()
+        ))
+        .into());
+    }
+
+    let mut conditions: Vec<String> = Vec::new();
+    let mut outcome = "";
+
+    for (column, cell) in columns.iter().zip(&cells) {
+        let tag = match column {
+            Column::Outcome => {
+                outcome = cell;
+                continue;
+            }
+            Column::Tag(tag) => tag,
+        };
+        let column_error =
+            |e: RulesError| RuleParseError::new(format!("Column '{}': {}", tag, message(e)));
+
+        let Some(condition) = cell_condition(tag, cell).map_err(column_error)? else {
+            continue;
+        };
+        if let Some(parser) = parser {
+            parser
+                .validate_rule(&format!("- {}", condition))
+                .map_err(column_error)?;
+        }
+        conditions.push(condition);
+    }
+
+    if conditions.is_empty() {
+        return Err(RuleParseError::new(format!(
+            "Row has no conditions, every cell is '{}'. A rule can't match every object, so \
+             leave the default row out and use its outcome when no row matches, with the \
+             first-match strategy picking the first matching row in table order",
+            ANY_CELL
+        ))
+        .into());
+    }
+
+    let mut rule = format!("- {}", conditions.join(" & "));
+    if !outcome.is_empty() {
+        rule = format!("{} {} {}", rule, string::OUTCOME_SEPARATOR, outcome);
+        RuleParser::rule_outcome(&rule).map_err(|e| {
+            RuleParseError::new(format!("Column '{}': {}", OUTCOME_COLUMN, message(e)))
+        })?;
+    }
+
+    Ok(rule)
+}
+
+// True if the text of a row so far has a quoted cell still open, so the row goes on to the
+// next line
+fn in_quoted_cell(row: &str) -> bool {
+    row.matches('"').count() % 2 == 1
+}
+
+// Equivalent .rules content of a table, with the rule of each row on the row's first line, and
+// the error of each row that can't be converted with its 1-based line. The header, blank lines
+// and rows with errors are left blank, as are the other lines of a row spanning several. An
+// invalid header is the only error, as no row can be read without it. Cells are checked
+// against the tags if they are given.
+pub fn table_rule_lines(
+    content: &str,
+    tags: Option<&HashMap<TagName, TagValues>>,
+) -> (String, Vec<(usize, RulesError)>) {
+    let parser = tags.map(|tags| RuleParser::new(tags.clone()));
+    let mut columns: Option<Vec<Column>> = None;
+    let mut lines: Vec<String> = Vec::new();
+    let mut errors: Vec<(usize, RulesError)> = Vec::new();
+
+    // Index of the first line of the row being read, and its text so far
+    let mut row: Option<(usize, String)> = None;
+    let content_lines = content.lines().map(Some).chain(std::iter::once(None));
+
+    for (i, line) in content_lines.enumerate() {
+        let (start, text) = match (row.take(), line) {
+            (Some((start, mut text)), Some(line)) => {
+                text.push('\n');
+                text.push_str(line);
+                (start, text)
+            }
+            // The end of the table ends a row with an unclosed quote, which is then an error
+            (Some(row), None) => row,
+            (None, None) => break,
+            // Spreadsheets often save a byte order mark before the header
+            (None, Some(line)) => (i, line.trim_start_matches('\u{feff}').to_string()),
+        };
+        if line.is_some() {
+            lines.push(String::new());
+        }
+
+        if line.is_some() && in_quoted_cell(&text) {
+            row = Some((start, text));
+            continue;
+        }
+        // A table has no comments, as a cell may start with '#', so only blank lines are skipped
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        match &columns {
+            None if errors.is_empty() => match parse_header(&text, tags) {
+                Ok(header) => columns = Some(header),
+                Err(e) => errors.push((start + 1, e)),
+            },
+            None => {}
+            Some(columns) => match row_rule(columns, &text, parser.as_ref()) {
+                Ok(rule) => lines[start] = rule,
+                Err(e) => errors.push((start + 1, e)),
+            },
+        }
+    }
+
+    let mut rules = String::new();
+    for line in lines {
+        rules.push_str(&line);
+        rules.push('\n');
+    }
+
+    (rules, errors)
+}
+
+// Equivalent .rules content of a table, failing on its first invalid row
+pub fn table_to_rules(
+    file_name: &str,
+    content: &str,
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<String, RulesError> {
+    let (rules, errors) = table_rule_lines(content, Some(tags));
+
+    match errors.into_iter().next() {
+        Some((line, e)) => {
+            Err(RuleParseError::new(format!("{}:{}: {}", file_name, line, message(e))).into())
+        }
+        None => Ok(rules),
+    }
+}
+
+// Same as table_to_rules, but collects an error diagnostic for every invalid row instead of
+// stopping at the first one
+pub fn table_to_rules_with_diagnostics(
+    file_name: &str,
+    content: &str,
+    tags: &HashMap<TagName, TagValues>,
+) -> (String, Vec<Diagnostic>) {
+    let (rules, errors) = table_rule_lines(content, Some(tags));
+    let diagnostics = errors
+        .into_iter()
+        .map(|(line, e)| Diagnostic::error(e, file_name, line))
+        .collect();
+
+    (rules, diagnostics)
+}
+
+// Contents of the .rules and .csv files of a directory, with each decision table converted to
+// its equivalent rules. Decision tables come after the .rules files, so their rule numbers
+// continue on from them.
+pub fn read_rule_files(
+    config_dir: &str,
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<Vec<NamedFile>, RulesError> {
+    let mut files = file::read_named_files_in_dir(&format!("{}/*.rules", config_dir))?;
+    for (file_name, content) in file::read_named_files_in_dir(&format!("{}/*.csv", config_dir))? {
+        let rules = table_to_rules(&file_name, &content, tags)?;
+        files.push((file_name, rules));
+    }
+
+    Ok(files)
+}
+
+// Same as read_rule_files, but collects the errors of invalid rows as diagnostics, leaving
+// those rows out
+pub fn read_rule_files_with_diagnostics(
+    config_dir: &str,
+    tags: &HashMap<TagName, TagValues>,
+) -> Result<(Vec<NamedFile>, Vec<Diagnostic>), RulesError> {
+    let mut files = file::read_named_files_in_dir(&format!("{}/*.rules", config_dir))?;
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for (file_name, content) in file::read_named_files_in_dir(&format!("{}/*.csv", config_dir))? {
+        let (rules, table_diagnostics) =
+            table_to_rules_with_diagnostics(&file_name, &content, tags);
+        files.push((file_name, rules));
+        diagnostics.extend(table_diagnostics);
+    }
+
+    Ok((files, diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_tags() -> HashMap<TagName, TagValues> {
+        let mut tags = HashMap::new();
+        tags.insert(
+            "colour".to_string(),
+            vec!["red".to_string(), "green".to_string(), "blue".to_string()],
+        );
+        tags.insert(
+            "size".to_string(),
+            vec!["small".to_string(), "large".to_string()],
+        );
+        tags
+    }
+
+    #[test]
+    fn test_split_fields() {
+        assert_eq!(
+            split_fields("red, !small ,-").unwrap(),
+            vec!["red", "!small", "-"]
+        );
+        assert_eq!(
+            split_fields("red,\"discount: 10, tier: \"\"gold\"\"\"").unwrap(),
+            vec!["red", "discount: 10, tier: \"gold\""]
+        );
+        assert_eq!(split_fields("a,,").unwrap(), vec!["a", "", ""]);
+        assert!(split_fields("red,\"tier: gold").is_err());
+    }
+
+    #[test]
+    fn test_table_rule_lines() {
+        let tags = create_test_tags();
+        let content = "\u{feff}Colour,size,Outcome\n\
+                       red,!small,\"discount: 10, tier: gold\"\n\
+                       \n\
+                       -,large,tier: silver\n\
+                       \"red, blue\",,\n";
+        let (rules, errors) = table_rule_lines(content, Some(&tags));
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        let lines: Vec<&str> = rules.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "",
+                "- colour = red & size ! small => discount: 10, tier: gold",
+                "",
+                "- size = large => tier: silver",
+                "- (colour = red, blue)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_hash_rows_are_rules() {
+        let tags = create_test_tags();
+        let content = "colour,size,outcome\n\
+                       #red,small,\n\
+                       blue,large,\"note: #1\"\n";
+        let (rules, errors) = table_rule_lines(content, Some(&tags));
+
+        // A row starting with '#' is read like any other, so its invalid cell is reported
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].0, 2);
+        assert_eq!(
+            rules.lines().nth(2),
+            Some("- colour = blue & size = large => note: #1")
+        );
+    }
+
+    #[test]
+    fn test_table_negated_list() {
+        let tags = create_test_tags();
+        let (rules, errors) = table_rule_lines("colour,size\n\"!red, blue\",-\n", Some(&tags));
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(rules.lines().nth(1), Some("- colour ! red & colour ! blue"));
+    }
+
+    #[test]
+    fn test_table_multiline_cells() {
+        let tags = create_test_tags();
+        let content = "colour,size,outcome\n\
+                       red,small,\"discount: 10,\n\
+                       tier: gold\"\n\
+                       blue,large,tier: silver\n\
+                       green,\"small\n";
+        let (rules, errors) = table_rule_lines(content, Some(&tags));
+
+        // The rule of a row spanning lines is on its first line, so later rows keep theirs
+        let lines: Vec<&str> = rules.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "",
+                "- colour = red & size = small => discount: 10, tier: gold",
+                "",
+                "- colour = blue & size = large => tier: silver",
+                "",
+            ]
+        );
+        // A quote left open to the end of the table is an error on the row's first line
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 5);
+    }
+
+    #[test]
+    fn test_table_row_errors() {
+        let tags = create_test_tags();
+        let content = "colour,size,outcome\n\
+                       rde,small,\n\
+                       red,small\n\
+                       -,-,tier: gold\n\
+                       red,size = large,\n\
+                       red,small,tier\n\
+                       blue,large,tier: gold\n";
+        let (rules, errors) = table_rule_lines(content, Some(&tags));
+
+        // Rows with errors are left blank, and the rest still convert
+        assert_eq!(
+            rules.lines().nth(6),
+            Some("- colour = blue & size = large => tier: gold")
+        );
+        let errors: Vec<(usize, String)> = errors
+            .into_iter()
+            .map(|(line, e)| (line, message(e)))
+            .collect();
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+
+        assert_eq!(errors[0].0, 2);
+        assert!(
+            errors[0].1.starts_with("Column 'colour':"),
+            "{}",
+            errors[0].1
+        );
+        assert!(
+            errors[0].1.contains("did you mean 'red'?"),
+            "{}",
+            errors[0].1
+        );
+        assert_eq!(errors[1].0, 3);
+        assert!(errors[1].1.contains("Row has 2 cells"));
+        assert_eq!(errors[2].0, 4);
+        assert!(errors[2].1.contains("no conditions"));
+        assert_eq!(errors[3].0, 5);
+        assert!(
+            errors[3]
+                .1
+                .starts_with("Column 'size': Cell 'size = large'")
+        );
+        assert_eq!(errors[4].0, 6);
+        assert!(errors[4].1.starts_with("Column 'outcome':"));
+    }
+
+    #[test]
+    fn test_table_header_errors() {
+        let tags = create_test_tags();
+
+        let (rules, errors) = table_rule_lines("color,size\nred,small\n", Some(&tags));
+        assert_eq!(rules, "\n\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 1);
+        assert_eq!(
+            message(errors.into_iter().next().unwrap().1),
+            "Column 'color' is not a tag, did you mean 'colour'?"
+        );
+
+        for header in [
+            "colour,,size",
+            "colour,size,colour",
+            "outcome",
+            "colour,\"size",
+        ] {
+            let (_, errors) = table_rule_lines(header, Some(&tags));
+            assert_eq!(errors.len(), 1, "{}", header);
+        }
+
+        // Without tags the cells aren't checked, only converted
+        let (rules, errors) = table_rule_lines("color,size\nred,small\n", None);
+        assert!(errors.is_empty());
+        assert_eq!(rules.lines().nth(1), Some("- color = red & size = small"));
+    }
+
+    #[test]
+    fn test_table_to_rules_errors_have_row_numbers() {
+        let tags = create_test_tags();
+        let content = "colour,size\nred,small\nred,huge\nrde,small\n";
+
+        let err = table_to_rules("prices.csv", content, &tags).unwrap_err();
+        assert!(
+            err.to_string().contains("prices.csv:3: Column 'size':"),
+            "{}",
+            err
+        );
+
+        let (rules, diagnostics) = table_to_rules_with_diagnostics("prices.csv", content, &tags);
+        assert_eq!(rules.lines().nth(1), Some("- colour = red & size = small"));
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            (diagnostics[0].file.as_str(), diagnostics[0].line),
+            ("prices.csv", 3)
+        );
+        assert_eq!(diagnostics[1].line, 4);
+    }
+}
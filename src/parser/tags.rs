@@ -1,11 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 // Parser for .tags files
-use crate::err::RulesError;
-use crate::types::Tag;
+use crate::err::{Diagnostic, RulesError};
+use crate::types::{NumericKind, NumericTag, Tag, TagName, TagValues};
 use crate::utils::file;
 use crate::utils::string::{StringUtils, normalise};
 
+// Keywords declaring a numeric tag, followed by bounds -- e.g. "- Doors: integer(2..5)"
+const INTEGER_KEYWORD: &str = "integer";
+const DECIMAL_KEYWORD: &str = "decimal";
+
 pub fn validate_tag(line: &str) -> Result<(), RulesError> {
     if file::line_blank_or_comment(line) {
         return Ok(());
@@ -58,14 +62,123 @@ pub fn validate_tag(line: &str) -> Result<(), RulesError> {
         )));
     }
 
+    let values: Vec<String> = values.split(',').map(|v| v.trim().to_string()).collect();
+    parse_numeric_tag(&values).map_err(|e| match e {
+        RulesError::TagParseError(msg) => {
+            RulesError::TagParseError(format!("Errors parsing line: '{}': {}", line, msg))
+        }
+        other => other,
+    })?;
+
     Ok(())
 }
 
-fn get_name_from_tag(parts: &Vec<&str>) -> Result<String, RulesError> {
+// Parses a numeric bound with the tag's number kind -- e.g. "2" for an integer tag
+fn parse_bound(kind: NumericKind, bound: &str) -> Result<Option<f64>, String> {
+    if bound.is_empty() {
+        return Ok(None);
+    }
+
+    let unbounded = NumericTag {
+        kind,
+        min: None,
+        max: None,
+    };
+    unbounded.parse_value(bound).map(Some)
+}
+
+// Parses a numeric declaration -- e.g. "integer(..)", "decimal(0..10.5)", "integer(..100)"
+// Returns Ok(None) if the value isn't a numeric declaration. The keywords are only reserved
+// with bounds, so "integer" and "decimal" on their own are ordinary values.
+fn parse_numeric_declaration(value: &str) -> Result<Option<NumericTag>, String> {
+    let value = value.trim().to_lowercase();
+    let Some((keyword, bounds)) = value.find('(').map(|i| value.split_at(i)) else {
+        return Ok(None);
+    };
+
+    let kind = match keyword.trim_end() {
+        INTEGER_KEYWORD => NumericKind::Integer,
+        DECIMAL_KEYWORD => NumericKind::Decimal,
+        _ => return Ok(None),
+    };
+
+    let (min, max) = bounds
+        .strip_prefix('(')
+        .and_then(|b| b.strip_suffix(')'))
+        .and_then(|b| b.split_once(".."))
+        .ok_or_else(|| {
+            format!(
+                "Numeric bounds must be written as '{}(min..max)', found '{}'",
+                keyword, value
+            )
+        })?;
+
+    let min = parse_bound(kind, min.trim())?;
+    let max = parse_bound(kind, max.trim())?;
+    if let (Some(min), Some(max)) = (min, max)
+        && min > max
+    {
+        return Err(format!("Numeric minimum {} is above maximum {}", min, max));
+    }
+
+    Ok(Some(NumericTag { kind, min, max }))
+}
+
+// Numeric definition of a tag's values, if it is numeric
+// A numeric declaration must be the tag's only value
+pub fn parse_numeric_tag(values: &[String]) -> Result<Option<NumericTag>, RulesError> {
+    let mut numeric: Option<NumericTag> = None;
+
+    for value in values {
+        if let Some(tag) = parse_numeric_declaration(value).map_err(RulesError::TagParseError)? {
+            numeric = Some(tag);
+        }
+    }
+
+    if numeric.is_some() && values.len() > 1 {
+        return Err(RulesError::TagParseError(
+            "Numeric tag declaration must be the tag's only value".to_string(),
+        ));
+    }
+
+    Ok(numeric)
+}
+
+// Numeric definition of already validated tag values -- e.g. ["integer(2..5)"]
+pub fn numeric_tag(values: &[String]) -> Option<NumericTag> {
+    parse_numeric_tag(values).ok().flatten()
+}
+
+// Adds a tag to the tag map, merging values if the tag already exists.
+// Numeric tags cannot be merged with any other definition.
+pub fn merge_tag(
+    tags: &mut HashMap<TagName, TagValues>,
+    name: TagName,
+    values: TagValues,
+) -> Result<(), RulesError> {
+    match tags.get_mut(&name) {
+        Some(existing) => {
+            if numeric_tag(existing).is_some() || numeric_tag(&values).is_some() {
+                return Err(RulesError::TagParseError(format!(
+                    "Numeric tag '{}' cannot be defined more than once",
+                    name
+                )));
+            }
+            existing.extend(values);
+        }
+        None => {
+            tags.insert(name, values);
+        }
+    }
+
+    Ok(())
+}
+
+fn get_name_from_tag(parts: &[&str]) -> Result<String, RulesError> {
     normalise(parts[0])
 }
 
-fn get_values_from_tag(parts: &Vec<&str>) -> Vec<String> {
+fn get_values_from_tag(parts: &[&str]) -> Vec<String> {
     parts[1].split(',').map(|v| v.trim().to_string()).collect()
 }
 
@@ -78,9 +191,9 @@ pub fn get_name_and_values_from_tag(line: &str) -> Result<(String, Vec<String>),
     Ok((name, values))
 }
 
-pub fn parse_tags() -> Result<Vec<Tag>, RulesError> {
+pub fn parse_tags(config_dir: &str) -> Result<Vec<Tag>, RulesError> {
     let mut tags: Vec<Tag> = Vec::new();
-    let all_files: Vec<String> = file::read_files_in_dir("config/*.tags")?;
+    let all_files: Vec<String> = file::read_files_in_dir(&format!("{}/*.tags", config_dir))?;
 
     for file in all_files.iter() {
         for line in file.lines() {
@@ -100,6 +213,68 @@ pub fn parse_tags() -> Result<Vec<Tag>, RulesError> {
     Ok(tags)
 }
 
+// Adds a line of a .tags file to the tag map, with its name and values lowercased
+pub fn add_tag_line(tags: &mut HashMap<TagName, TagValues>, line: &str) -> Result<(), RulesError> {
+    let (name, values) = get_name_and_values_from_tag(line)?;
+
+    // Normalize to lowercase for consistent lookup
+    let name = name.to_lowercase();
+    let values: Vec<String> = values.iter().map(|v| v.to_lowercase()).collect();
+
+    merge_tag(tags, name, values)
+}
+
+// Values of a line of a .tags file that are already defined for its tag -- e.g. "red" for
+// "- colour: red" when colour already has red, or for "- colour: red, red"
+fn duplicate_values(tags: &HashMap<TagName, TagValues>, line: &str) -> Vec<String> {
+    let Ok((name, values)) = get_name_and_values_from_tag(line) else {
+        return Vec::new();
+    };
+
+    let mut seen: HashSet<String> = tags
+        .get(&name.to_lowercase())
+        .map(|existing| existing.iter().cloned().collect())
+        .unwrap_or_default();
+
+    values
+        .iter()
+        .map(|v| v.to_lowercase())
+        .filter(|v| !seen.insert(v.clone()))
+        .collect()
+}
+
+// Same as adding every line of the files with add_tag_line, but keeps going past invalid
+// lines. Returns the valid tags, with an error for each invalid line and a warning for
+// each value defined more than once.
+pub fn parse_tag_files_with_diagnostics(
+    all_files: &[(String, String)],
+) -> (HashMap<TagName, TagValues>, Vec<Diagnostic>) {
+    let mut tags: HashMap<TagName, TagValues> = HashMap::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for (file_name, content) in all_files.iter() {
+        for (i, line) in content.lines().enumerate() {
+            if file::line_blank_or_comment(line) {
+                continue;
+            }
+
+            for value in duplicate_values(&tags, line) {
+                diagnostics.push(Diagnostic::warning(
+                    format!("Tag value '{}' is already defined", value),
+                    file_name,
+                    i + 1,
+                ));
+            }
+
+            if let Err(e) = add_tag_line(&mut tags, line) {
+                diagnostics.push(Diagnostic::error(e, file_name, i + 1));
+            }
+        }
+    }
+
+    (tags, diagnostics)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -219,4 +394,126 @@ mod tests {
             assert_eq!(values, vec!["Red".to_string(), "Blue".to_string()]);
         }
     }
+
+    #[test]
+    fn test_validate_tag_numeric() {
+        assert!(validate_tag("- Doors: integer(..)").is_ok());
+        assert!(validate_tag("- Doors: Integer(2..5)").is_ok());
+        assert!(validate_tag("- Price: decimal(0..)").is_ok());
+        assert!(validate_tag("- Temp: decimal(-10.5..40)").is_ok());
+    }
+
+    #[test]
+    fn test_validate_tag_numeric_invalid() {
+        let invalid_tags = vec![
+            "- Doors: integer(..), 3",
+            "- Doors: integer(2.5..5)",
+            "- Doors: integer(5..2)",
+            "- Doors: integer(2-5)",
+            "- Price: decimal(a..b)",
+        ];
+
+        for tag in invalid_tags {
+            assert!(
+                matches!(validate_tag(tag), Err(RulesError::TagParseError(_))),
+                "Expected tag to be invalid: {}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_numeric_tag() {
+        let values = vec!["integer(2..5)".to_string()];
+        assert_eq!(
+            parse_numeric_tag(&values).unwrap(),
+            Some(NumericTag {
+                kind: NumericKind::Integer,
+                min: Some(2.0),
+                max: Some(5.0),
+            })
+        );
+
+        let values = vec!["decimal(..10.5)".to_string()];
+        assert_eq!(
+            numeric_tag(&values),
+            Some(NumericTag {
+                kind: NumericKind::Decimal,
+                min: None,
+                max: Some(10.5),
+            })
+        );
+
+        let values = vec!["red".to_string(), "blue".to_string()];
+        assert_eq!(numeric_tag(&values), None);
+
+        // The keywords are ordinary values without bounds
+        let values = vec!["integer".to_string(), "decimal".to_string()];
+        assert_eq!(parse_numeric_tag(&values).unwrap(), None);
+        assert!(validate_tag("- Kind: integer, decimal").is_ok());
+    }
+
+    #[test]
+    fn test_merge_tag() {
+        let mut tags = HashMap::new();
+        merge_tag(&mut tags, "colour".to_string(), vec!["red".to_string()]).unwrap();
+        merge_tag(&mut tags, "colour".to_string(), vec!["blue".to_string()]).unwrap();
+        assert_eq!(tags["colour"], vec!["red".to_string(), "blue".to_string()]);
+
+        merge_tag(
+            &mut tags,
+            "doors".to_string(),
+            vec!["integer(..)".to_string()],
+        )
+        .unwrap();
+        assert!(merge_tag(&mut tags, "doors".to_string(), vec!["3".to_string()]).is_err());
+        assert!(
+            merge_tag(
+                &mut tags,
+                "colour".to_string(),
+                vec!["integer(..)".to_string()]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_tag_files_with_diagnostics() {
+        let files = vec![
+            (
+                "a.tags".to_string(),
+                "- colour: red, blue\n- size small\n".to_string(),
+            ),
+            (
+                "b.tags".to_string(),
+                "# More colours\n- Colour: Red, green\n- doors: integer(..)\n- doors: 3\n"
+                    .to_string(),
+            ),
+        ];
+
+        let (tags, diagnostics) = parse_tag_files_with_diagnostics(&files);
+
+        assert_eq!(tags["colour"], vec!["red", "blue", "red", "green"]);
+        assert_eq!(tags["doors"], vec!["integer(..)"]);
+        assert!(!tags.contains_key("size"));
+
+        let found: Vec<(bool, &str, usize)> = diagnostics
+            .iter()
+            .map(|d| (d.is_error(), d.file.as_str(), d.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (true, "a.tags", 2),
+                (false, "b.tags", 2),
+                (true, "b.tags", 4)
+            ]
+        );
+        assert!(diagnostics[1].message.contains("'red' is already defined"));
+        assert!(
+            diagnostics[2]
+                .message
+                .contains("cannot be defined more than once")
+        );
+    }
 }
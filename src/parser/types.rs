@@ -1,4 +1,5 @@
 // Parser-specific types
+use crate::types::ComparisonOp;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
@@ -6,10 +7,14 @@ pub enum TokenType {
     ComparisonOp, // =
     TagValue,     // "red"
     LogicalOp,    // &
+    Negation,     // ! before a group or comparison -- e.g. "!(colour = red)"
 }
 
 pub type TokenDepth = i32;
 
+// Start and length of a token, in characters of the rule it was read from
+pub type TokenSpan = (usize, usize);
+
 // Tokens, their type and their parenthesis depth -- e.g. [("colour", TagName, 2), ("=", ComparisonOp, 0)]
 pub type MappedRuleTokens = Vec<(String, TokenType, TokenDepth)>;
 
@@ -22,13 +27,45 @@ pub enum Token {
     And,        // &
     Or,         // |
     Comma,      // ,
+    Greater,    // >
+    GreaterEq,  // >=
+    Less,       // <
+    LessEq,     // <=
+    In,         // in
     Invalid,    // Initialiser
 }
 
+// One end of a numeric interval -- e.g. "18" and inclusive for "[18"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bound {
+    pub value: String,
+    pub inclusive: bool,
+}
+
+// Numeric interval from a range or interval TagValue -- e.g. "10..20" or "[18,65)"
+// A missing bound is unbounded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interval {
+    pub lower: Option<Bound>,
+    pub upper: Option<Bound>,
+}
+
 pub struct Node {
     pub token: Token,
     pub left: Option<Box<Node>>,
     pub right: Option<Box<Node>>,
+    // Only set on leaf (comparison) nodes -- e.g. ("colour", "red")
+    pub tag_kv: Option<(String, String)>,
+    // Only set on leaf nodes whose comparison has no opposite operator, so the node matches
+    // exactly the objects the comparison doesn't -- e.g. "!(doors > 2)"
+    pub negated: bool,
+}
+
+// An expression once some of its comparisons are replaced by their value -- either still an
+// expression, or always true or always false
+pub enum Pruned {
+    Node(Node),
+    Always(bool),
 }
 
 pub struct AstRule {
@@ -37,27 +74,65 @@ pub struct AstRule {
 
 // Impls
 
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Invalid => write!(f, "<invalid>"),
+            token => write!(f, "{}", token.as_str()),
+        }
+    }
+}
+
+// Bracket notation, which every interval and range can be written in -- e.g. "[10,20]" for
+// "10..20", "(2,)" for "> 2"
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (open, lower) = match &self.lower {
+            Some(bound) => (
+                if bound.inclusive { '[' } else { '(' },
+                bound.value.as_str(),
+            ),
+            None => ('(', ""),
+        };
+        let (close, upper) = match &self.upper {
+            Some(bound) => (
+                if bound.inclusive { ']' } else { ')' },
+                bound.value.as_str(),
+            ),
+            None => (')', ""),
+        };
+        write!(f, "{}{},{}{}", open, lower, upper, close)
+    }
+}
+
 impl Default for Node {
     fn default() -> Self {
         Node {
             token: Token::Invalid,
             left: None,
             right: None,
+            tag_kv: None,
+            negated: false,
         }
     }
 }
 
 impl Token {
-    pub fn as_char(&self) -> char {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Token::LeftParen => '(',
-            Token::RightParen => ')',
-            Token::Equals => '=',
-            Token::NotEquals => '!',
-            Token::And => '&',
-            Token::Or => '|',
-            Token::Comma => ',',
-            Token::Invalid => panic!("Invalid token has no character representation"),
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::Equals => "=",
+            Token::NotEquals => "!",
+            Token::And => "&",
+            Token::Or => "|",
+            Token::Comma => ",",
+            Token::Greater => ">",
+            Token::GreaterEq => ">=",
+            Token::Less => "<",
+            Token::LessEq => "<=",
+            Token::In => "in",
+            Token::Invalid => panic!("Invalid token has no string representation"),
         }
     }
 
@@ -70,7 +145,130 @@ impl Token {
             '&' => Some(Token::And),
             '|' => Some(Token::Or),
             ',' => Some(Token::Comma),
+            '>' => Some(Token::Greater),
+            '<' => Some(Token::Less),
             _ => None,
         }
     }
+
+    // Operator token for a rule token in operator position -- e.g. "&" -> Token::And,
+    // ">=" -> Token::GreaterEq. "in" is only the operator straight after a TagName.
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            ">=" => Some(Token::GreaterEq),
+            "<=" => Some(Token::LessEq),
+            _ if token.eq_ignore_ascii_case("in") => Some(Token::In),
+            _ => {
+                let mut chars = token.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Token::from_char(c),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl Interval {
+    // Interval written after a comparison operator, or None if the value isn't an interval.
+    // "in" takes bracket notation -- e.g. "[18,65)", where '[' and ']' are inclusive.
+    // Other operators take an inclusive range -- e.g. "10..20", "10.." or "..20".
+    pub fn parse(operator: &str, value: &str) -> Result<Option<Interval>, String> {
+        if operator.eq_ignore_ascii_case("in") {
+            return Self::parse_brackets(value).map(Some);
+        }
+
+        let Some((lower, upper)) = value.split_once("..") else {
+            return Ok(None);
+        };
+
+        let bound = |value: &str| {
+            (!value.is_empty()).then(|| Bound {
+                value: value.to_string(),
+                inclusive: true,
+            })
+        };
+
+        Self::new(value, bound(lower.trim()), bound(upper.trim())).map(Some)
+    }
+
+    // Interval in bracket notation -- e.g. "[18,65)" or "(2,)"
+    pub fn parse_brackets(value: &str) -> Result<Interval, String> {
+        let malformed = || {
+            format!(
+                "Interval must be written as '[min, max]' with '(' or ')' for exclusive bounds, found '{}'",
+                value
+            )
+        };
+
+        let lower_inclusive = match value.chars().next() {
+            Some('[') => true,
+            Some('(') => false,
+            _ => return Err(malformed()),
+        };
+        let upper_inclusive = match value.chars().last() {
+            Some(']') if value.len() > 1 => true,
+            Some(')') if value.len() > 1 => false,
+            _ => return Err(malformed()),
+        };
+
+        let (lower, upper) = value[1..value.len() - 1]
+            .split_once(',')
+            .ok_or_else(malformed)?;
+
+        let bound = |value: &str, inclusive: bool| {
+            (!value.is_empty()).then(|| Bound {
+                value: value.to_string(),
+                inclusive,
+            })
+        };
+
+        Self::new(
+            value,
+            bound(lower.trim(), lower_inclusive),
+            bound(upper.trim(), upper_inclusive),
+        )
+    }
+
+    fn new(value: &str, lower: Option<Bound>, upper: Option<Bound>) -> Result<Interval, String> {
+        if lower.is_none() && upper.is_none() {
+            return Err(format!("Interval '{}' has no bounds", value));
+        }
+
+        Ok(Interval { lower, upper })
+    }
+
+    // Interval of the values an ordering comparison is true for
+    // E.g. "> 2" -> "(2,)", "<= 5" -> "(,5]"
+    pub fn from_ordering(op: ComparisonOp, value: &str) -> Option<Interval> {
+        let bound = |inclusive: bool| {
+            Some(Bound {
+                value: value.to_string(),
+                inclusive,
+            })
+        };
+
+        let (lower, upper) = match op {
+            ComparisonOp::GRTH => (bound(false), None),
+            ComparisonOp::GREQ => (bound(true), None),
+            ComparisonOp::LETH => (None, bound(false)),
+            ComparisonOp::LEEQ => (None, bound(true)),
+            _ => return None,
+        };
+        Some(Interval { lower, upper })
+    }
+
+    // True if the number is within both bounds. A bound that isn't a number contains nothing.
+    pub fn contains(&self, number: f64) -> bool {
+        let limit = |bound: &Bound| bound.value.parse::<f64>().ok();
+
+        let above_lower = self.lower.as_ref().is_none_or(|bound| {
+            limit(bound).is_some_and(|min| number > min || (bound.inclusive && number == min))
+        });
+        let below_upper = self.upper.as_ref().is_none_or(|bound| {
+            limit(bound).is_some_and(|max| number < max || (bound.inclusive && number == max))
+        });
+
+        above_lower && below_upper
+    }
 }
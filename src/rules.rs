@@ -1,9 +1,18 @@
-use crate::err::RulesError;
+use crate::builder::RulesBuilder;
+use crate::engine::Engine;
+use crate::err::{Diagnostic, RulesError};
+use crate::parser::objects;
 use crate::parser::rules::RuleParser;
+use crate::parser::tables;
 use crate::parser::tags;
-use crate::types::{TagName, TagValues};
-use crate::utils::file;
+use crate::types::{
+    Decision, EvaluationResult, LineChange, NearMiss, Object, Rule, RuleExplanation, RuleMatch,
+    RuleNumber, RuleReference, Strategy, SubRule, SubRuleNumber, TagName, TagValues, TypedObject,
+};
+use crate::utils::{cache, file};
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
 /// Main API for the rules engine.
 ///
@@ -31,6 +40,14 @@ pub struct Rules {
     config_dir: String,
     /// Cached tags loaded from config files
     tags: HashMap<TagName, TagValues>,
+    /// Matching engine holding the DNF subrules loaded from config files
+    engine: Engine,
+    /// Cached objects loaded from config files
+    objects: Vec<TypedObject>,
+    /// Hash of the .tags, .rules and .csv files in the config directory when the tags were
+    /// loaded from them, for `save_cache`. None if the tags weren't loaded from the config
+    /// directory.
+    source_hash: Option<u64>,
 }
 
 impl Rules {
@@ -47,9 +64,26 @@ impl Rules {
         Self {
             config_dir: config_dir.into(),
             tags: HashMap::new(),
+            engine: Engine::default(),
+            objects: Vec::new(),
+            source_hash: None,
         }
     }
 
+    /// Creates a builder for loading tags, rules and objects from strings,
+    /// readers or individual files instead of a config directory.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let rules = Rules::builder()
+    ///     .tags_str("- colour: red, blue")
+    ///     .rules_str("- colour = red")
+    ///     .build()?;
+    /// ```
+    pub fn builder() -> RulesBuilder {
+        RulesBuilder::new()
+    }
+
     /// Loads all tags from .tags files in the config directory.
     ///
     /// This should be called after creating a new Rules instance to populate
@@ -65,6 +99,10 @@ impl Rules {
     /// rules.load_tags()?;
     /// ```
     pub fn load_tags(&mut self) -> Result<(), RulesError> {
+        // Hashed before anything is read, so a file changed while loading makes the hash stale
+        // rather than the cache
+        self.source_hash = Some(self.read_source_hash()?);
+
         let pattern = format!("{}/*.tags", self.config_dir);
         let all_files = file::read_files_in_dir(&pattern)?;
 
@@ -72,28 +110,308 @@ impl Rules {
         self.tags.clear();
 
         for file_content in all_files.iter() {
-            for line in file_content.lines() {
-                if file::line_blank_or_comment(line) {
-                    continue;
-                }
-
-                let (name, values) = tags::get_name_and_values_from_tag(line)?;
+            self.add_tags(file_content)?;
+        }
 
-                // Normalize to lowercase for consistent lookup
-                let name = name.to_lowercase();
-                let values: Vec<String> = values.iter().map(|v| v.to_lowercase()).collect();
+        Ok(())
+    }
 
-                // Merge values if tag already exists
-                self.tags
-                    .entry(name)
-                    .and_modify(|existing_values| existing_values.extend(values.clone()))
-                    .or_insert(values);
+    // Adds the tags in the contents of a .tags file to the cached tags
+    pub(crate) fn add_tags(&mut self, content: &str) -> Result<(), RulesError> {
+        for line in content.lines() {
+            if file::line_blank_or_comment(line) {
+                continue;
             }
+
+            // Merge values if tag already exists
+            tags::add_tag_line(&mut self.tags, line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads all rules from .rules files and decision tables (.csv files) in the config
+    /// directory.
+    ///
+    /// Rules are validated against the loaded tags and converted to DNF subrules,
+    /// so `load_tags` must be called first. Rules are numbered in file order, with the rows
+    /// of decision tables after every .rules file. A row's rule keeps the table as its file
+    /// and the row number as its line.
+    ///
+    /// # Returns
+    /// * `Ok(())` if rules were loaded successfully
+    /// * `Err(RulesError)` if loading or validation fails
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut rules = Rules::new("config");
+    /// rules.load_tags()?;
+    /// rules.load_rules()?;
+    /// ```
+    pub fn load_rules(&mut self) -> Result<(), RulesError> {
+        let (rules, dnf_subrules) = RuleParser::parse_rules(&self.config_dir, self.tags.clone())?;
+        self.set_rules(rules, dnf_subrules);
+
+        Ok(())
+    }
+
+    pub(crate) fn tags(&self) -> &HashMap<TagName, TagValues> {
+        &self.tags
+    }
+
+    // Replaces the loaded rules and their subrules, numbering subrules in order
+    pub(crate) fn set_rules(&mut self, rules: Vec<Rule>, dnf_subrules: Vec<SubRule>) {
+        let subrules: HashMap<SubRuleNumber, SubRule> = dnf_subrules
+            .into_iter()
+            .enumerate()
+            .map(|(i, subrule)| (i as SubRuleNumber, subrule))
+            .collect();
+        self.engine = Engine::new(subrules, &self.tags).with_rules(rules);
+    }
+
+    /// Loads all objects from .yaml files in the config directory.
+    ///
+    /// Objects are validated against the loaded tags, so `load_tags` must be called first.
+    ///
+    /// # Returns
+    /// * `Ok(())` if objects were loaded successfully
+    /// * `Err(RulesError::ObjectParseError)` with the file and line if parsing fails
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut rules = Rules::new("config");
+    /// rules.load_tags()?;
+    /// rules.load_objects()?;
+    /// ```
+    pub fn load_objects(&mut self) -> Result<(), RulesError> {
+        self.objects = objects::parse_objects(&self.config_dir, &self.tags)?;
+
+        Ok(())
+    }
+
+    /// Number of loaded rules. Rules are numbered from 0 in file order.
+    pub fn rule_count(&self) -> usize {
+        self.engine.rule_count()
+    }
+
+    /// The loaded rules, in rule number order, with their names and metadata.
+    pub fn rules(&self) -> &[Rule] {
+        self.engine.rules()
+    }
+
+    /// Finds a loaded rule by the name it was given in its .rules file.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the rule -- e.g. "free_shipping" for
+    ///   `- [free_shipping] colour = red`
+    ///
+    /// # Returns
+    /// * `Some(&Rule)` if a loaded rule has the name
+    /// * `None` otherwise
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let rule = rules.rule_by_name("free_shipping").unwrap();
+    /// println!("{} is owned by {:?}", rule.rule_number, rule.owner);
+    /// ```
+    pub fn rule_by_name(&self, name: &str) -> Option<&Rule> {
+        self.engine
+            .rules()
+            .iter()
+            .find(|rule| rule.name.as_deref() == Some(name))
+    }
+
+    /// The loaded objects, in file order.
+    pub fn objects(&self) -> &[TypedObject] {
+        &self.objects
+    }
+
+    // Replaces the loaded objects
+    pub(crate) fn set_objects(&mut self, objects: Vec<TypedObject>) {
+        self.objects = objects;
+    }
+
+    /// Loads all tags, then all rules and objects, from the config directory.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut rules = Rules::new("config");
+    /// rules.load()?;
+    /// ```
+    pub fn load(&mut self) -> Result<(), RulesError> {
+        self.load_tags()?;
+        self.load_rules()?;
+        self.load_objects()
+    }
+
+    /// Loads all tags, then all rules and objects, from the config directory without
+    /// stopping at the first invalid line.
+    ///
+    /// Every file is checked, and the valid tags, rules and objects are loaded. Rules and
+    /// objects are validated against the valid tags only, and rules are numbered in file
+    /// order skipping invalid rules. An objects file is skipped from its first error.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<Diagnostic>)` with an error for each invalid tag or rule line and objects
+    ///   file, and a warning for each duplicated tag value or rule, in file and line order.
+    ///   Empty if the config is clean.
+    /// * `Err(RulesError)` if the config files cannot be read
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut rules = Rules::new("config");
+    /// for diagnostic in rules.load_with_diagnostics()? {
+    ///     eprintln!("{}", diagnostic);
+    /// }
+    /// ```
+    pub fn load_with_diagnostics(&mut self) -> Result<Vec<Diagnostic>, RulesError> {
+        self.source_hash = Some(self.read_source_hash()?);
+
+        let tag_files = file::read_named_files_in_dir(&format!("{}/*.tags", self.config_dir))?;
+        let (tags, mut diagnostics) = tags::parse_tag_files_with_diagnostics(&tag_files);
+        self.tags = tags;
+
+        let (rule_files, table_diagnostics) =
+            tables::read_rule_files_with_diagnostics(&self.config_dir, &self.tags)?;
+        let (rules, dnf_subrules, rule_diagnostics) =
+            RuleParser::parse_rule_files_with_diagnostics(self.tags.clone(), &rule_files);
+        self.set_rules(rules, dnf_subrules);
+        diagnostics.extend(table_diagnostics);
+        diagnostics.extend(rule_diagnostics);
+
+        let object_files = file::read_named_files_in_dir(&format!("{}/*.yaml", self.config_dir))?;
+        let (objects, object_diagnostics) =
+            objects::parse_object_files_with_diagnostics(&object_files, &self.tags);
+        self.objects = objects;
+        diagnostics.extend(object_diagnostics);
+
+        Ok(diagnostics)
+    }
+
+    // Hash of the .tags, .rules and .csv files in the config directory, to check a cache against
+    fn read_source_hash(&self) -> Result<u64, RulesError> {
+        let mut files = file::read_named_files_in_dir(&format!("{}/*.tags", self.config_dir))?;
+        for extension in ["rules", "csv"] {
+            files.extend(file::read_named_files_in_dir(&format!(
+                "{}/*.{}",
+                self.config_dir, extension
+            ))?);
         }
 
+        Ok(cache::source_hash(&files))
+    }
+
+    /// Saves the loaded tags and rules to a binary cache file.
+    ///
+    /// The cache records a hash of the .tags, .rules and .csv files in the config directory
+    /// taken when the tags were loaded, so it is only used while the files are as they were
+    /// then. Rules written since loading make the cache stale rather than being left out of
+    /// it. Objects are not cached.
+    ///
+    /// # Arguments
+    /// * `cache_path` - Path of the cache file to write
+    ///
+    /// # Returns
+    /// * `Ok(())` if the cache was written
+    /// * `Err(RulesError::CacheError)` if the tags weren't loaded from the config directory
+    ///   -- e.g. by a `RulesBuilder`
+    /// * `Err(RulesError)` if the cache file cannot be written
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.load_tags()?;
+    /// rules.load_rules()?;
+    /// rules.save_cache("target/rules.cache")?;
+    /// ```
+    pub fn save_cache(&self, cache_path: impl AsRef<Path>) -> Result<(), RulesError> {
+        let source_hash = self.source_hash.ok_or_else(|| {
+            RulesError::CacheError(format!(
+                "Tags and rules weren't loaded from the config directory '{}'",
+                self.config_dir
+            ))
+        })?;
+        let bytes = cache::encode(
+            source_hash,
+            &self.tags,
+            self.engine.rules(),
+            &self.engine.subrules(),
+        );
+        fs::write(cache_path, bytes)?;
+
         Ok(())
     }
 
+    /// Loads tags and rules from a binary cache file written by `save_cache`, without
+    /// parsing them.
+    ///
+    /// The .tags, .rules and .csv files are still read to check the cache against them, but
+    /// nothing is tokenised or validated. Nothing is loaded if the cache is missing, was
+    /// written by another version, or the files have changed since it was written.
+    ///
+    /// # Arguments
+    /// * `cache_path` - Path of the cache file to read
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the tags and rules were loaded from the cache
+    /// * `Ok(false)` if the cache is missing or stale
+    /// * `Err(RulesError)` if the cache or config files cannot be read
+    ///
+    /// # Examples
+    /// ```ignore
+    /// if !rules.load_cache("target/rules.cache")? {
+    ///     rules.load_tags()?;
+    ///     rules.load_rules()?;
+    /// }
+    /// ```
+    pub fn load_cache(&mut self, cache_path: impl AsRef<Path>) -> Result<bool, RulesError> {
+        let bytes = match fs::read(cache_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        let source_hash = self.read_source_hash()?;
+        match cache::decode(&bytes, source_hash) {
+            Some((tags, rules, subrules)) => {
+                self.tags = tags;
+                self.source_hash = Some(source_hash);
+                self.set_rules(rules, subrules);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Loads tags and rules from a binary cache file, or parses them and writes the cache
+    /// if it is missing or stale.
+    ///
+    /// # Arguments
+    /// * `cache_path` - Path of the cache file to read and write
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the tags and rules were loaded from the cache
+    /// * `Ok(false)` if they were parsed from the config files and the cache was rewritten
+    /// * `Err(RulesError)` if loading fails, or the cache cannot be written
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut rules = Rules::new("config");
+    /// rules.load_cached("target/rules.cache")?;
+    /// rules.load_objects()?;
+    /// ```
+    pub fn load_cached(&mut self, cache_path: impl AsRef<Path>) -> Result<bool, RulesError> {
+        let cache_path = cache_path.as_ref();
+        if self.load_cache(cache_path)? {
+            return Ok(true);
+        }
+
+        self.load_tags()?;
+        self.load_rules()?;
+        self.save_cache(cache_path)?;
+
+        Ok(false)
+    }
+
     /// Writes a tag to a .tags file.
     ///
     /// # Arguments
@@ -114,30 +432,32 @@ impl Rules {
         let tag_name = tag_name.into();
         let tag_values: Vec<String> = tag_values.into_iter().map(|v| v.into()).collect();
 
+        // Normalize to lowercase for consistent lookup in cache
+        let tag_name_lower = tag_name.to_lowercase();
+        let tag_values_lower: Vec<String> = tag_values.iter().map(|v| v.to_lowercase()).collect();
+
+        // Check the merge before writing, so a rejected tag leaves the file untouched
+        let mut updated_tags = self.tags.clone();
+        tags::merge_tag(&mut updated_tags, tag_name_lower, tag_values_lower)?;
+
         // Write to file
         crate::api::write::tag::write_with_base_dir(
             file_name,
-            tag_name.clone(),
-            tag_values.clone(),
+            tag_name,
+            tag_values,
             &self.config_dir,
         )?;
 
-        // Normalize to lowercase for consistent lookup in cache
-        let tag_name_lower = tag_name.to_lowercase();
-        let tag_values_lower: Vec<String> = tag_values.iter().map(|v| v.to_lowercase()).collect();
-
         // Update cached tags (append if exists)
-        self.tags
-            .entry(tag_name_lower)
-            .and_modify(|existing| existing.extend(tag_values_lower.clone()))
-            .or_insert(tag_values_lower);
+        self.tags = updated_tags;
 
         Ok(())
     }
 
     /// Writes a rule to a .rules file.
     ///
-    /// The rule is validated against the current tag definitions before writing.
+    /// The rule is validated against the current tag definitions before writing, and a named
+    /// rule must not share its name with a loaded rule.
     ///
     /// # Arguments
     /// * `file_name` - Name of the file (with or without .rules extension)
@@ -148,6 +468,7 @@ impl Rules {
     /// rules.write_rule("my_rules", "- colour = red & size = large")?;
     /// ```
     pub fn write_rule(&self, file_name: &str, rule: &str) -> Result<(), RulesError> {
+        self.check_name_free(rule, None)?;
         crate::api::write::rule::write_with_base_dir(
             file_name,
             rule,
@@ -158,11 +479,21 @@ impl Rules {
 
     /// Writes an object definition to a .yaml file.
     ///
+    /// The object is validated against the current tag definitions, then appended to the
+    /// group for its type, which is added to the end of the file if it doesn't exist yet.
+    /// If the group already has the object -- an object whose shared attributes all have the
+    /// same values, where one has every attribute of the other -- the attributes it doesn't
+    /// have yet are added to it instead. Comments and the rest of the file are kept.
+    ///
     /// # Arguments
-    /// * `file_name` - Name of the file
+    /// * `file_name` - Name of the file (with or without .yaml extension)
     /// * `obj_type` - Type/category of the object
     /// * `obj` - HashMap representing the object's properties
     ///
+    /// # Returns
+    /// * `Ok(())` if the object was written, or the file already has it
+    /// * `Err(RulesError::ObjectParseError)` if the object is invalid, or the file can't be parsed
+    ///
     /// # Examples
     /// ```ignore
     /// let mut obj = HashMap::new();
@@ -179,41 +510,1012 @@ impl Rules {
             file_name,
             obj_type.into(),
             obj,
+            self.tags.clone(),
             &self.config_dir,
         )
     }
 
-    /// Validates a rule string against the current tag definitions.
+    /// Removes a rule from a .rules file.
     ///
-    /// This checks syntax and ensures all referenced tags and values exist.
+    /// Rules are compared in canonical form, so spacing and case don't matter. Every line of
+    /// the file with the rule is removed; comments and the other rules keep their order.
     ///
     /// # Arguments
-    /// * `rule` - The rule string to validate (should start with '-')
+    /// * `file_name` - Name of the file (with or without .rules extension)
+    /// * `rule` - The rule to remove
     ///
     /// # Returns
-    /// * `Ok(())` if the rule is valid
-    /// * `Err(RulesError)` with details if validation fails
+    /// * `Ok(())` if the rule was removed
+    /// * `Err(RulesError::RuleParseError)` if the file doesn't have the rule
     ///
     /// # Examples
     /// ```ignore
-    /// rules.validate_rule("- colour = red & size = large")?;
+    /// rules.remove_rule("my_rules", "- colour = red & size = large")?;
     /// ```
-    pub fn validate_rule(&self, rule: &str) -> Result<(), RulesError> {
-        let parser = RuleParser::new(self.tags.clone());
-        parser.validate_rule(rule)
+    pub fn remove_rule(&self, file_name: &str, rule: &str) -> Result<(), RulesError> {
+        crate::api::write::rule::remove_with_base_dir(file_name, rule, &self.config_dir)
+    }
+
+    /// Replaces a rule in a .rules file with another, on the same line.
+    ///
+    /// The new rule is validated against the current tag definitions before writing, and a
+    /// named rule must not share its name with a loaded rule other than the one it replaces.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file (with or without .rules extension)
+    /// * `old_rule` - The rule to replace, compared in canonical form
+    /// * `new_rule` - The rule to write in its place
+    ///
+    /// # Returns
+    /// * `Ok(())` if the rule was replaced
+    /// * `Err(RulesError)` if the new rule is invalid, the file doesn't have the old rule,
+    ///   or already has the new one
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.replace_rule("my_rules", "- colour = red", "- colour = red, blue")?;
+    /// ```
+    pub fn replace_rule(
+        &self,
+        file_name: &str,
+        old_rule: &str,
+        new_rule: &str,
+    ) -> Result<(), RulesError> {
+        self.check_name_free(new_rule, Some(old_rule))?;
+        crate::api::write::rule::replace_with_base_dir(
+            file_name,
+            old_rule,
+            new_rule,
+            self.tags.clone(),
+            &self.config_dir,
+        )
+    }
+
+    // Refuses a rule named like a loaded rule, other than the rule it replaces, as the rules
+    // would no longer load
+    fn check_name_free(&self, rule: &str, replaced: Option<&str>) -> Result<(), RulesError> {
+        let Some(name) = RuleParser::rule_name(rule) else {
+            return Ok(());
+        };
+        if replaced.and_then(RuleParser::rule_name) == Some(name) {
+            return Ok(());
+        }
+
+        match self
+            .rules()
+            .iter()
+            .find(|r| r.name.as_deref() == Some(name))
+        {
+            Some(used) => Err(RuleParser::name_used_error(name, &used.file, used.line)),
+            None => Ok(()),
+        }
+    }
+
+    /// Finds the rules in the .rules files of the config directory that reference a tag.
+    ///
+    /// Rows of decision tables that reference it are included, with the row as the rule.
+    /// Rules that can't be parsed are skipped, as they fail to load anyway.
+    ///
+    /// # Arguments
+    /// * `tag_name` - Name of the tag
+    ///
+    /// # Returns
+    /// * `Ok(Vec<RuleReference>)` with the file, line and text of each rule, in file order
+    /// * `Err(RulesError)` if the rules files can't be read
+    ///
+    /// # Examples
+    /// ```ignore
+    /// for rule in rules.tag_dependents("colour")? {
+    ///     println!("{}: {}", rule, rule.rule);
+    /// }
+    /// ```
+    pub fn tag_dependents(&self, tag_name: &str) -> Result<Vec<RuleReference>, RulesError> {
+        self.rule_references(tag_name, None)
+    }
+
+    /// Finds the rules in the .rules files of the config directory that compare a tag with
+    /// a value -- e.g. `- colour = red, grey` for colour and grey. Rows of decision tables
+    /// are included, as in `tag_dependents`.
+    ///
+    /// # Arguments
+    /// * `tag_name` - Name of the tag
+    /// * `value` - The tag value
+    ///
+    /// # Returns
+    /// * `Ok(Vec<RuleReference>)` with the file, line and text of each rule, in file order
+    /// * `Err(RulesError)` if the rules files can't be read
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let dependents = rules.tag_value_dependents("colour", "grey")?;
+    /// ```
+    pub fn tag_value_dependents(
+        &self,
+        tag_name: &str,
+        value: &str,
+    ) -> Result<Vec<RuleReference>, RulesError> {
+        self.rule_references(tag_name, Some(value))
+    }
+
+    // Every parsable rule of the config directory referencing the tag, or the tag with the value
+    fn rule_references(
+        &self,
+        tag_name: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<RuleReference>, RulesError> {
+        let pattern = format!("{}/*.rules", self.config_dir);
+        let mut references: Vec<RuleReference> = Vec::new();
+
+        for (file, content) in file::read_named_files_in_dir(&pattern)? {
+            for (i, line) in content.lines().enumerate() {
+                if file::line_blank_or_comment(line)
+                    || !RuleParser::references(line, tag_name, value).unwrap_or(false)
+                {
+                    continue;
+                }
+
+                references.push(RuleReference {
+                    file: file.clone(),
+                    line: i + 1,
+                    rule: line.to_string(),
+                });
+            }
+        }
+
+        references.extend(self.table_references(tag_name, value)?);
+        Ok(references)
+    }
+
+    // Every row of the decision tables of the config directory referencing the tag, or the tag
+    // with the value. Rows are checked through their equivalent rules.
+    fn table_references(
+        &self,
+        tag_name: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<RuleReference>, RulesError> {
+        let pattern = format!("{}/*.csv", self.config_dir);
+        let mut references: Vec<RuleReference> = Vec::new();
+
+        for (file, content) in file::read_named_files_in_dir(&pattern)? {
+            let (table_rules, _) = tables::table_rule_lines(&content, None);
+            for (i, (rule, row)) in table_rules.lines().zip(content.lines()).enumerate() {
+                if rule.is_empty()
+                    || !RuleParser::references(rule, tag_name, value).unwrap_or(false)
+                {
+                    continue;
+                }
+
+                references.push(RuleReference {
+                    file: file.clone(),
+                    line: i + 1,
+                    rule: row.to_string(),
+                });
+            }
+        }
+
+        Ok(references)
+    }
+
+    // Refuses to remove a tag or value rules still reference
+    fn check_unreferenced(&self, tag_name: &str, value: Option<&str>) -> Result<(), RulesError> {
+        Self::check_no_references(tag_name, value, self.rule_references(tag_name, value)?)
+    }
+
+    // Refuses to rewrite rules of decision tables, which are edited as spreadsheets instead
+    fn check_no_table_references(
+        &self,
+        tag_name: &str,
+        value: Option<&str>,
+    ) -> Result<(), RulesError> {
+        Self::check_no_references(tag_name, value, self.table_references(tag_name, value)?)
+    }
+
+    fn check_no_references(
+        tag_name: &str,
+        value: Option<&str>,
+        rules: Vec<RuleReference>,
+    ) -> Result<(), RulesError> {
+        if rules.is_empty() {
+            return Ok(());
+        }
+
+        let tag_name = tag_name.trim().to_lowercase();
+        Err(RulesError::TagInUse {
+            tag: match value {
+                Some(value) => format!("{}: {}", tag_name, value.trim().to_lowercase()),
+                None => tag_name,
+            },
+            rules,
+        })
+    }
+
+    // Rewrites of the rules referencing the tag or value, without their comparisons of it
+    fn cascade_changes(
+        &self,
+        tag_name: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        self.check_no_table_references(tag_name, value)?;
+
+        let mut changes: Vec<LineChange> = Vec::new();
+        let mut files: HashMap<String, Vec<String>> = HashMap::new();
+
+        for reference in self.rule_references(tag_name, value)? {
+            let new_line = RuleParser::remove_references(&reference.rule, tag_name, value)?;
+
+            // A removed rule takes its annotations with it
+            if new_line.is_none() {
+                if !files.contains_key(&reference.file) {
+                    let content = fs::read_to_string(&reference.file)?;
+                    let lines = content.lines().map(str::to_string).collect();
+                    files.insert(reference.file.clone(), lines);
+                }
+                let lines = &files[&reference.file];
+                for i in RuleParser::annotation_lines(lines, reference.line - 1) {
+                    changes.push(LineChange {
+                        file: reference.file.clone(),
+                        line: i + 1,
+                        old_line: lines[i].clone(),
+                        new_line: None,
+                    });
+                }
+            }
+
+            changes.push(LineChange {
+                new_line,
+                file: reference.file,
+                line: reference.line,
+                old_line: reference.rule,
+            });
+        }
+
+        Ok(changes)
+    }
+
+    // Checks the last value of a tag isn't being removed, as a tag must keep one
+    fn check_not_last_value(&self, tag_name: &str, value: &str) -> Result<(), RulesError> {
+        let tag_name = tag_name.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+
+        if self
+            .tags
+            .get(&tag_name)
+            .is_some_and(|values| values.iter().all(|v| *v == value))
+        {
+            return Err(RulesError::TagParseError(format!(
+                "Cannot remove the last value of tag '{}', remove the tag instead",
+                tag_name
+            )));
+        }
+
+        Ok(())
     }
 
-    /// Evaluates rules against objects.
+    /// Removes a value of a tag from a .tags file.
     ///
-    /// Note: Currently uses the default orchestrator which reads from the config directory.
-    /// Parameters will be supported in a future update.
+    /// A tag must keep at least one value, so removing its last value is an error -- use
+    /// `remove_tag` instead. Numeric tags have no values to remove. The value isn't removed
+    /// while rules in the config directory still compare the tag with it; use
+    /// `remove_tag_value_cascade` to rewrite those rules as well.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file (with or without .tags extension)
+    /// * `tag_name` - Name of the tag
+    /// * `value` - The value to remove
+    ///
+    /// # Returns
+    /// * `Ok(())` if the value was removed
+    /// * `Err(RulesError::TagInUse)` with the rules still referencing the value
+    /// * `Err(RulesError::TagParseError)` if the file doesn't define the value, or it is the
+    ///   tag's last value
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.remove_tag_value("my_tags", "colour", "blue")?;
+    /// ```
+    pub fn remove_tag_value(
+        &mut self,
+        file_name: &str,
+        tag_name: &str,
+        value: &str,
+    ) -> Result<(), RulesError> {
+        // Check before writing, so a rejected removal leaves the file untouched
+        self.check_not_last_value(tag_name, value)?;
+        self.check_unreferenced(tag_name, Some(value))?;
+
+        let removed = crate::api::write::tag::remove_value_with_base_dir(
+            file_name,
+            tag_name,
+            value,
+            &self.config_dir,
+        )?;
+        self.remove_cached_tag_values(&tag_name.trim().to_lowercase(), &removed);
+
+        Ok(())
+    }
+
+    /// Removes a value of a tag from a .tags file, rewriting the rules that reference it.
+    ///
+    /// Each comparison of the tag with the value is replaced by its result for an object
+    /// that can't have the value -- `colour = grey` is false and `colour ! grey` is true --
+    /// so rules keep matching the objects they matched before. A rule that can no longer
+    /// match anything is removed. Nothing is changed if a rule would match every object, or
+    /// a decision table references the value, as tables are edited by hand.
+    ///
+    /// The rules are loaded again from the rewritten files, so evaluation uses the rewritten
+    /// rules straight away.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file (with or without .tags extension)
+    /// * `tag_name` - Name of the tag
+    /// * `value` - The value to remove
+    ///
+    /// # Returns
+    /// * `Ok(Vec<LineChange>)` with each rule that was rewritten or removed
+    /// * `Err(RulesError)` if the value can't be removed, or a rule can't be rewritten
+    ///
+    /// # Examples
+    /// ```ignore
+    /// // "- colour = red, grey & size = large" becomes "- colour = red & size = large"
+    /// let changes = rules.remove_tag_value_cascade("my_tags", "colour", "grey")?;
+    /// ```
+    pub fn remove_tag_value_cascade(
+        &mut self,
+        file_name: &str,
+        tag_name: &str,
+        value: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        self.check_not_last_value(tag_name, value)?;
+        let changes = self.cascade_changes(tag_name, Some(value))?;
+
+        let removed = crate::api::write::tag::remove_value_with_base_dir(
+            file_name,
+            tag_name,
+            value,
+            &self.config_dir,
+        )?;
+        self.remove_cached_tag_values(&tag_name.trim().to_lowercase(), &removed);
+        crate::api::write::apply_changes(&changes)?;
+
+        // The loaded rules still have the removed comparisons until they are read back
+        self.load_rules()?;
+
+        Ok(changes)
+    }
+
+    /// Removes a tag, and all of its values, from a .tags file.
+    ///
+    /// Values of the tag defined in other .tags files are kept. The tag isn't removed while
+    /// rules in the config directory still reference it; use `remove_tag_cascade` to rewrite
+    /// those rules as well.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file (with or without .tags extension)
+    /// * `tag_name` - Name of the tag
+    ///
+    /// # Returns
+    /// * `Ok(())` if the tag was removed
+    /// * `Err(RulesError::TagInUse)` with the rules still referencing the tag
+    /// * `Err(RulesError::TagParseError)` if the file doesn't define the tag
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.remove_tag("my_tags", "colour")?;
+    /// ```
+    pub fn remove_tag(&mut self, file_name: &str, tag_name: &str) -> Result<(), RulesError> {
+        self.check_unreferenced(tag_name, None)?;
+
+        let removed =
+            crate::api::write::tag::remove_with_base_dir(file_name, tag_name, &self.config_dir)?;
+        self.remove_cached_tag_values(&tag_name.trim().to_lowercase(), &removed);
+
+        Ok(())
+    }
+
+    /// Removes a tag from a .tags file, rewriting the rules that reference it.
+    ///
+    /// Comparisons of the tag are replaced the same way as in `remove_tag_value_cascade`,
+    /// as if no object had the tag. Nothing is changed if a decision table references it.
+    /// The rules are loaded again from the rewritten files.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file (with or without .tags extension)
+    /// * `tag_name` - Name of the tag
+    ///
+    /// # Returns
+    /// * `Ok(Vec<LineChange>)` with each rule that was rewritten or removed
+    /// * `Err(RulesError)` if the tag can't be removed, or a rule can't be rewritten
+    ///
+    /// # Examples
+    /// ```ignore
+    /// // "- colour = red & size = large" is removed, "- colour ! red & size = large"
+    /// // becomes "- size = large"
+    /// let changes = rules.remove_tag_cascade("my_tags", "colour")?;
+    /// ```
+    pub fn remove_tag_cascade(
+        &mut self,
+        file_name: &str,
+        tag_name: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        let changes = self.cascade_changes(tag_name, None)?;
+
+        let removed =
+            crate::api::write::tag::remove_with_base_dir(file_name, tag_name, &self.config_dir)?;
+        self.remove_cached_tag_values(&tag_name.trim().to_lowercase(), &removed);
+        crate::api::write::apply_changes(&changes)?;
+
+        // The loaded rules still have the removed comparisons until they are read back
+        self.load_rules()?;
+
+        Ok(changes)
+    }
+
+    // Checks a new tag name or value is a single word that can be written to a .tags file
+    fn check_new_name(new_name: &str) -> Result<String, RulesError> {
+        let new_name = new_name.trim().to_lowercase();
+        if new_name.is_empty()
+            || new_name.contains(|c: char| c.is_whitespace() || [':', ',', '#'].contains(&c))
+        {
+            return Err(RulesError::TagParseError(format!(
+                "Invalid tag name or value '{}'",
+                new_name
+            )));
+        }
+
+        Ok(new_name)
+    }
+
+    // Changes renaming the tag, or the tag's value when one is given, in every .tags, .rules
+    // and .yaml file of the config directory
+    fn rename_changes(
+        &self,
+        tag_name: &str,
+        tag_value: Option<&str>,
+        new_name: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        self.check_no_table_references(tag_name, tag_value)?;
+
+        let mut changes: Vec<LineChange> = Vec::new();
+        let mut change = |file: &str, i: usize, line: &str, new_line: Option<String>| {
+            if let Some(new_line) = new_line {
+                changes.push(LineChange {
+                    file: file.to_string(),
+                    line: i + 1,
+                    old_line: line.to_string(),
+                    new_line: Some(new_line),
+                });
+            }
+        };
+
+        let tags_pattern = format!("{}/*.tags", self.config_dir);
+        for (file, content) in file::read_named_files_in_dir(&tags_pattern)? {
+            for (i, line) in content.lines().enumerate() {
+                let new_line =
+                    crate::api::write::tag::rename_line(line, tag_name, tag_value, new_name);
+                change(&file, i, line, new_line);
+            }
+        }
+
+        // Rules that can't be parsed are left alone, as they fail to load anyway
+        let rules_pattern = format!("{}/*.rules", self.config_dir);
+        for (file, content) in file::read_named_files_in_dir(&rules_pattern)? {
+            for (i, line) in content.lines().enumerate() {
+                if file::line_blank_or_comment(line) {
+                    continue;
+                }
+                let new_line = RuleParser::rename_references(line, tag_name, tag_value, new_name)
+                    .ok()
+                    .flatten();
+                change(&file, i, line, new_line);
+            }
+        }
+
+        // Objects are renamed too, so they still load with the renamed tags. An object file
+        // that can't be parsed can't be rewritten, so nothing is renamed.
+        let objects_pattern = format!("{}/*.yaml", self.config_dir);
+        for (file, content) in file::read_named_files_in_dir(&objects_pattern)? {
+            let renamed = crate::api::write::object::rename_lines(
+                &file, &content, &self.tags, tag_name, tag_value, new_name,
+            )?;
+            let lines: Vec<&str> = content.lines().collect();
+            for (i, new_line) in renamed {
+                change(&file, i, lines[i], Some(new_line));
+            }
+        }
+
+        Ok(changes)
+    }
+
+    // Changes renaming a tag, checking the tag exists and the new name is free
+    fn rename_tag_changes(
+        &self,
+        tag_name: &str,
+        new_name: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        let tag_name = tag_name.trim().to_lowercase();
+        let new_name = Self::check_new_name(new_name)?;
+
+        if !self.tags.contains_key(&tag_name) {
+            return Err(RulesError::TagParseError(format!(
+                "Tag '{}' not found",
+                tag_name
+            )));
+        }
+        if tag_name != new_name && self.tags.contains_key(&new_name) {
+            return Err(RulesError::TagParseError(format!(
+                "Tag '{}' already exists",
+                new_name
+            )));
+        }
+
+        self.rename_changes(&tag_name, None, &new_name)
+    }
+
+    // Changes renaming a tag value, checking the value exists and the new value is free
+    fn rename_tag_value_changes(
+        &self,
+        tag_name: &str,
+        value: &str,
+        new_value: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        let tag_name = tag_name.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        let new_value = Self::check_new_name(new_value)?;
+
+        let Some(values) = self.tags.get(&tag_name) else {
+            return Err(RulesError::TagParseError(format!(
+                "Tag '{}' not found",
+                tag_name
+            )));
+        };
+        if tags::numeric_tag(values).is_some() {
+            return Err(RulesError::TagParseError(format!(
+                "Numeric tag '{}' has no values to rename",
+                tag_name
+            )));
+        }
+        if !values.contains(&value) {
+            return Err(RulesError::TagParseError(format!(
+                "Tag '{}' has no value '{}'",
+                tag_name, value
+            )));
+        }
+        if value != new_value && values.contains(&new_value) {
+            return Err(RulesError::TagParseError(format!(
+                "Tag '{}' already has a value '{}'",
+                tag_name, new_value
+            )));
+        }
+        if !matches!(
+            tags::parse_numeric_tag(std::slice::from_ref(&new_value)),
+            Ok(None)
+        ) {
+            return Err(RulesError::TagParseError(format!(
+                "Tag value '{}' cannot be a numeric declaration",
+                new_value
+            )));
+        }
+
+        self.rename_changes(&tag_name, Some(&value), &new_value)
+    }
+
+    /// Renames a tag in every .tags file of the config directory, and in every rule of its
+    /// .rules files that references it.
+    ///
+    /// Rules are rewritten token by token, so a tag with a similar name -- e.g. `colours`
+    /// when renaming `colour` -- is left alone, and the rest of each rule keeps its formatting.
+    /// The attribute is renamed in every object of the .yaml files too, and nothing is renamed
+    /// while a decision table references the tag. Use `rename_tag_dry_run` to see the changes
+    /// first.
+    ///
+    /// The rules are loaded again from the rewritten files, so they match on the new name
+    /// straight away.
+    ///
+    /// # Arguments
+    /// * `tag_name` - Name of the tag
+    /// * `new_name` - New name of the tag, which must not already exist
+    ///
+    /// # Returns
+    /// * `Ok(Vec<LineChange>)` with each line that was changed, .tags files first and .yaml
+    ///   files last
+    /// * `Err(RulesError)` if the tag doesn't exist, the new name is invalid or taken, or an
+    ///   objects file can't be parsed
+    ///
+    /// # Examples
+    /// ```ignore
+    /// for change in rules.rename_tag("colour", "hue")? {
+    ///     println!("{}:{}: {:?}", change.file, change.line, change.new_line);
+    /// }
+    /// ```
+    pub fn rename_tag(
+        &mut self,
+        tag_name: &str,
+        new_name: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        let changes = self.rename_tag_changes(tag_name, new_name)?;
+        crate::api::write::apply_changes(&changes)?;
+
+        let tag_name = tag_name.trim().to_lowercase();
+        let new_name = new_name.trim().to_lowercase();
+        if let Some(values) = self.tags.remove(&tag_name) {
+            self.tags.insert(new_name.clone(), values);
+        }
+        for obj in &mut self.objects {
+            if let Some(values) = obj.attributes.remove(&tag_name) {
+                obj.attributes.insert(new_name.clone(), values);
+            }
+        }
+
+        // The loaded rules still compare the old name until they are read back
+        self.load_rules()?;
+
+        Ok(changes)
+    }
+
+    /// Finds the lines `rename_tag` would change, without changing them.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let changes = rules.rename_tag_dry_run("colour", "hue")?;
+    /// print!("{}", LineChange::diff(&changes));
+    /// ```
+    pub fn rename_tag_dry_run(
+        &self,
+        tag_name: &str,
+        new_name: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        self.rename_tag_changes(tag_name, new_name)
+    }
+
+    /// Renames a value of a tag in every .tags file of the config directory, and in every
+    /// rule of its .rules files that compares the tag with it.
+    ///
+    /// Only whole values are renamed -- e.g. renaming `grey` leaves `greyish` alone -- and
+    /// only in comparisons of the tag. Objects with the value in .yaml files are renamed too,
+    /// and nothing is renamed while a decision table uses the value. Use
+    /// `rename_tag_value_dry_run` to see the changes first.
+    ///
+    /// The rules are loaded again from the rewritten files, so they match on the new value
+    /// straight away.
+    ///
+    /// # Arguments
+    /// * `tag_name` - Name of the tag
+    /// * `value` - The value to rename
+    /// * `new_value` - New value, which the tag must not already have
+    ///
+    /// # Returns
+    /// * `Ok(Vec<LineChange>)` with each line that was changed, .tags files first and .yaml
+    ///   files last
+    /// * `Err(RulesError)` if the tag doesn't have the value, the new value is invalid or
+    ///   taken, or an objects file can't be parsed
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.rename_tag_value("colour", "grey", "gray")?;
+    /// ```
+    pub fn rename_tag_value(
+        &mut self,
+        tag_name: &str,
+        value: &str,
+        new_value: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        let changes = self.rename_tag_value_changes(tag_name, value, new_value)?;
+        crate::api::write::apply_changes(&changes)?;
+
+        let tag_name = tag_name.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        let new_value = new_value.trim().to_lowercase();
+        let object_values = self
+            .objects
+            .iter_mut()
+            .filter_map(|obj| obj.attributes.get_mut(&tag_name));
+        for values in self
+            .tags
+            .get_mut(&tag_name)
+            .into_iter()
+            .chain(object_values)
+        {
+            for v in values.iter_mut().filter(|v| **v == value) {
+                *v = new_value.clone();
+            }
+        }
+
+        // The loaded rules still compare the old value until they are read back
+        self.load_rules()?;
+
+        Ok(changes)
+    }
+
+    /// Finds the lines `rename_tag_value` would change, without changing them.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let changes = rules.rename_tag_value_dry_run("colour", "grey", "gray")?;
+    /// print!("{}", LineChange::diff(&changes));
+    /// ```
+    pub fn rename_tag_value_dry_run(
+        &self,
+        tag_name: &str,
+        value: &str,
+        new_value: &str,
+    ) -> Result<Vec<LineChange>, RulesError> {
+        self.rename_tag_value_changes(tag_name, value, new_value)
+    }
+
+    // Removes each value once from the cached tag, and the tag if it has no values left
+    fn remove_cached_tag_values(&mut self, tag_name: &str, removed: &[String]) {
+        let Some(values) = self.tags.get_mut(tag_name) else {
+            return;
+        };
+
+        for value in removed {
+            if let Some(i) = values.iter().position(|v| v == value) {
+                values.remove(i);
+            }
+        }
+
+        if values.is_empty() {
+            self.tags.remove(tag_name);
+        }
+    }
+
+    /// Removes an object from a .yaml file.
+    ///
+    /// The object must have exactly the given attributes and values, compared
+    /// case-insensitively. If it was the only object of its type, the type's group is removed
+    /// too. Comments and the rest of the file are kept.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file (with or without .yaml extension)
+    /// * `obj_type` - Type/category of the object
+    /// * `obj` - HashMap representing the object's properties
+    ///
+    /// # Returns
+    /// * `Ok(())` if the object was removed
+    /// * `Err(RulesError::ObjectParseError)` if the file doesn't have the object, or can't be parsed
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut obj = HashMap::new();
+    /// obj.insert("colour".to_string(), vec!["red".to_string()]);
+    /// rules.remove_object("objects.yaml", "shapes", &obj)?;
+    /// ```
+    pub fn remove_object(
+        &self,
+        file_name: &str,
+        obj_type: &str,
+        obj: &Object,
+    ) -> Result<(), RulesError> {
+        crate::api::write::object::remove_with_base_dir(
+            file_name,
+            obj_type,
+            obj,
+            self.tags.clone(),
+            &self.config_dir,
+        )
+    }
+
+    /// Validates a rule string against the current tag definitions.
+    ///
+    /// This checks syntax and ensures all referenced tags and values exist.
+    ///
+    /// # Arguments
+    /// * `rule` - The rule string to validate (should start with '-')
+    ///
+    /// # Returns
+    /// * `Ok(())` if the rule is valid
+    /// * `Err(RulesError)` with details if validation fails
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.validate_rule("- colour = red & size = large")?;
+    /// ```
+    pub fn validate_rule(&self, rule: &str) -> Result<(), RulesError> {
+        let parser = RuleParser::new(self.tags.clone());
+        parser.validate_rule(rule)
+    }
+
+    /// Formats the contents of a .rules file into canonical form.
+    ///
+    /// Each rule is lowercased and written with single spaces around operators, commas
+    /// followed by a space and no space inside brackets -- e.g. `-(Colour=red,BLUE)&size!large`
+    /// becomes `- (colour = red, blue) & size ! large`. Comments and blank lines are kept,
+    /// and trailing whitespace is removed. Only syntax is checked, not tags.
+    ///
+    /// # Arguments
+    /// * `file_name` - Name of the file, only used in error messages
+    /// * `content` - Contents of a .rules file
+    ///
+    /// # Returns
+    /// * `Ok(String)` with the formatted contents
+    /// * `Err(RulesError)` for the first rule with invalid syntax, with its file and line
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let formatted = Rules::format_rules("my_rules.rules", "-colour=RED")?;
+    /// assert_eq!(formatted, "- colour = red\n");
+    /// ```
+    pub fn format_rules(file_name: &str, content: &str) -> Result<String, RulesError> {
+        RuleParser::format_rule_file(file_name, content)
+    }
+
+    /// Validates an object against the current tag definitions.
+    ///
+    /// Every attribute must be a known tag, and every value must be valid for that tag.
+    ///
+    /// # Arguments
+    /// * `obj` - HashMap representing the object's properties
+    ///
+    /// # Returns
+    /// * `Ok(())` if the object is valid
+    /// * `Err(RulesError::ObjectParseError)` with details if validation fails
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut obj = HashMap::new();
+    /// obj.insert("colour".to_string(), vec!["red".to_string()]);
+    /// rules.validate_object(&obj)?;
+    /// ```
+    pub fn validate_object(&self, obj: &Object) -> Result<(), RulesError> {
+        objects::validate_object(obj, &self.tags)
+    }
+
+    /// Evaluates the loaded rules against the loaded objects.
+    ///
+    /// Rules and objects must be loaded first with `load_rules` and `load_objects`
+    /// (or `load`), or with a `RulesBuilder`.
+    ///
+    /// # Returns
+    /// An entry per object, listing the rules and DNF subrules it matched
+    ///
+    /// # Examples
+    /// ```ignore
+    /// rules.load()?;
+    /// let result = rules.evaluate();
+    /// for obj in &result.objects {
+    ///     println!("{} #{} matched rules {:?}", obj.obj_type, obj.index, obj.rule_numbers());
+    /// }
+    /// ```
+    pub fn evaluate(&self) -> EvaluationResult {
+        self.engine.evaluate(&self.objects)
+    }
+
+    /// Evaluates the loaded rules against a single in-memory object.
+    ///
+    /// Nothing is read from the config directory. Attribute names and values are
+    /// matched case-insensitively, and attributes that aren't tags are ignored;
+    /// use `validate_object` first if the object should be checked against the tags.
+    ///
+    /// # Returns
+    /// The rules the object matched, in rule order, with the DNF subrules that matched
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let mut obj = HashMap::new();
+    /// obj.insert("colour".to_string(), vec!["red".to_string()]);
+    /// let matches = rules.evaluate_object(&obj);
+    /// ```
+    pub fn evaluate_object(&self, obj: &Object) -> Vec<RuleMatch> {
+        self.engine.match_object(obj)
+    }
+
+    /// Evaluates the loaded rules against several in-memory objects.
+    ///
+    /// # Returns
+    /// The matches for each object, in the same order as the objects
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let matches = rules.evaluate_objects(vec![obj1, obj2]);
+    /// assert_eq!(matches.len(), 2);
+    /// ```
+    pub fn evaluate_objects(&self, objs: impl IntoIterator<Item = Object>) -> Vec<Vec<RuleMatch>> {
+        objs.into_iter()
+            .map(|obj| self.engine.match_object(&obj))
+            .collect()
+    }
+
+    /// Decides the outcome for an in-memory object from the loaded rules it matches.
+    ///
+    /// Rules declare an outcome after `=>` -- e.g. `- colour = red => discount: 10` -- and
+    /// a priority with `@priority`, 0 if they have none. The strategy picks which of the
+    /// matching rules make the decision. A matching rule without an outcome is still
+    /// picked, with an empty outcome.
+    ///
+    /// Matching rules are always considered in rule order, so the same object always gets
+    /// the same decision. With `HighestPriority`, several matching rules sharing the
+    /// highest priority are reported as a conflict, and the first of them is picked.
+    ///
+    /// # Arguments
+    /// * `obj` - The object to decide for
+    /// * `strategy` - `FirstMatch`, `AllMatches`, `HighestPriority` or `Unique`
+    ///
+    /// # Returns
+    /// * `Ok(Decision)` with the chosen rules in rule order, their outcomes and any conflict
+    /// * `Err(RulesError::RuleConflict)` with `Unique` if several matching rules share the
+    ///   highest priority
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let decision = rules.decide(&obj, Strategy::HighestPriority)?;
+    /// if let Some(outcome) = decision.outcome() {
+    ///     println!("discount: {:?}", outcome.get("discount"));
+    /// }
+    /// for conflict in &decision.conflicts {
+    ///     println!("warning: {}", conflict);
+    /// }
+    /// ```
+    pub fn decide(&self, obj: &Object, strategy: Strategy) -> Result<Decision, RulesError> {
+        self.engine.decide(obj, strategy)
+    }
+
+    /// Explains why an in-memory object did or didn't match a loaded rule.
+    ///
+    /// Every DNF subrule of the rule is listed with each of its clauses marked as passed
+    /// or failed, along with the object's values for the clause's tag. A subrule matches
+    /// if all of its clauses pass, and the rule matches if any of its subrules match.
+    ///
+    /// # Arguments
+    /// * `obj` - HashMap representing the object's properties
+    /// * `rule_number` - Number of the rule, in file order from 0
+    ///
+    /// # Returns
+    /// * `Some(RuleExplanation)` with a trace of every clause
+    /// * `None` if there is no rule with this number
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let explanation = rules.explain(&obj, 7).unwrap();
+    /// for subrule in &explanation.subrules {
+    ///     for clause in subrule.clauses.iter().filter(|c| !c.passed) {
+    ///         println!("{} {} {} failed, object has {:?}",
+    ///             clause.tag_name, clause.comparison_op.as_str(), clause.tag_value,
+    ///             clause.object_values);
+    ///     }
+    /// }
+    /// ```
+    pub fn explain(&self, obj: &Object, rule_number: RuleNumber) -> Option<RuleExplanation> {
+        self.engine.explain(obj, rule_number)
+    }
+
+    /// Explains why an in-memory object did or didn't match each loaded rule.
+    ///
+    /// # Returns
+    /// An explanation for every rule, in rule order
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let failed: Vec<_> = rules.explain_all(&obj).into_iter().filter(|e| !e.is_match()).collect();
+    /// ```
+    pub fn explain_all(&self, obj: &Object) -> Vec<RuleExplanation> {
+        self.engine.explain_all(obj)
+    }
+
+    /// Finds the rules an in-memory object came closest to matching.
+    ///
+    /// Each unmatched rule is scored by its subrule with the most satisfied clauses, and
+    /// returned with the clauses of that subrule the object failed. Rules are ranked by
+    /// most satisfied clauses, then fewest missing clauses, then rule order. Matched rules
+    /// and rules without a single satisfied clause are left out.
+    ///
+    /// # Arguments
+    /// * `obj` - HashMap representing the object's properties
+    /// * `limit` - Maximum number of rules to return
+    ///
+    /// # Returns
+    /// Up to `limit` near misses, closest first
     ///
     /// # Examples
     /// ```ignore
-    /// rules.evaluate()?;
+    /// for near_miss in rules.near_misses(&obj, 3) {
+    ///     for clause in &near_miss.missing_clauses {
+    ///         println!("You'd qualify for rule {} if {} were {}",
+    ///             near_miss.rule_number, clause.tag_name, clause.tag_value);
+    ///     }
+    /// }
     /// ```
-    pub fn evaluate(&self) -> Result<(), RulesError> {
-        crate::api::entry::evaluate()
+    pub fn near_misses(&self, obj: &Object, limit: usize) -> Vec<NearMiss> {
+        self.engine.near_misses(obj, limit)
     }
 
     /// Debug method to print loaded tags
@@ -1,6 +1,6 @@
 // Shared domain types
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 // Aliases
 
@@ -13,49 +13,389 @@ pub type TagValues = Vec<String>;
 // Number of subrule -- e.g. 42
 pub type SubRuleNumber = i32;
 
+// Number of rule the subrule was expanded from, in file order -- e.g. 7
+pub type RuleNumber = i32;
+
 // Object structure -- e.g. "colour": ["green"]
 pub type Object = HashMap<String, Vec<String>>;
 
-// Clause in subrule -- e.g. "colour": "green"
-pub type TagKvMap = HashMap<String, String>;
+// Object type, taken from its group in the objects file -- e.g. "shapes"
+pub type ObjectType = String;
+
+// Clauses in subrule, in rule order -- e.g. [("colour", "green"), ("shape", "circle")]
+pub type TagKvs = Vec<(TagName, String)>;
+
+// Payload a rule declares after "=>" -- e.g. {"discount": "10", "tier": "gold"}
+pub type Outcome = BTreeMap<String, String>;
 
 // Structs
 
+#[derive(Debug, Clone)]
 pub struct Tag {
     pub name: TagName,
     pub values: TagValues,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedObject {
+    pub obj_type: ObjectType,
+    // Position of the object within its type group -- e.g. 0 for the first "shapes" object
+    pub index: usize,
+    pub attributes: Object,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumericKind {
+    Integer,
+    Decimal,
+}
+
+// Numeric tag, declared in a .tags file as its only value -- e.g. "integer(2..5)"
+// Bounds are inclusive and optional
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericTag {
+    pub kind: NumericKind,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ComparisonOp {
     ISEQ,
     NOEQ,
-    // To be supported in future:
-    // GREQ,
-    // LEEQ,
+    // Numeric tags only
+    GRTH,
+    GREQ,
+    LETH,
+    LEEQ,
+    // Numeric tags only, with an interval in bracket notation as the value -- e.g. "[10,20]"
+    ISIN,
+    NOIN,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LogicalOp {
     AND,
     OR,
 }
 
+#[derive(Debug, Clone)]
 pub struct SubRule {
+    pub rule_number: RuleNumber,
     pub expected_count: i32,
     pub actual_count: i32,
-    // No. elements (tag_kvs) should be == no. elements (comparison_op - 1)
+    // No. elements (tag_kvs) should be == no. elements (comparison_ops)
     pub comparison_ops: Vec<ComparisonOp>,
-    pub tag_kvs: TagKvMap,
+    pub tag_kvs: TagKvs,
+}
+
+// A rule of a .rules file, with its optional name and metadata -- e.g. the rule on the line
+// "- [free_shipping] colour = red", with "@owner: sales" on the line before it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rule {
+    pub rule_number: RuleNumber,
+    pub name: Option<String>,
+    pub owner: Option<String>,
+    pub description: Option<String>,
+    pub priority: Option<i32>,
+    // Empty if the rule declares no outcome
+    pub outcome: Outcome,
+    // File the rule was read from, and its 1-based line
+    pub file: String,
+    pub line: usize,
+}
+
+// Subrules of a single rule that matched an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleMatch {
+    pub rule_number: RuleNumber,
+    pub subrules: Vec<SubRuleNumber>,
+    pub rule: Rule,
+}
+
+// How the rules an object matched are combined into a decision
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Strategy {
+    // The first rule in rule order
+    #[default]
+    FirstMatch,
+    // Every rule, in rule order
+    AllMatches,
+    // The rule with the highest priority, or the first of them if several share it.
+    // Rules without a priority have priority 0.
+    HighestPriority,
+    // The rule with the highest priority, or an error if several share it
+    Unique,
+}
+
+// Matching rules that share a priority, so none of them wins over the others
+// E.g. rules 2 and 5 at priority 10
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub priority: i32,
+    // In rule order
+    pub rule_numbers: Vec<RuleNumber>,
+}
+
+// Rules chosen from the matches of an object by a Strategy, in rule order. Their outcomes
+// are the decision.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Decision {
+    pub matches: Vec<RuleMatch>,
+    // The matching rules that share the highest priority, if several do and the strategy
+    // is HighestPriority
+    pub conflicts: Vec<Conflict>,
+}
+
+// Rules that matched a single object, in rule order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMatch {
+    pub obj_type: ObjectType,
+    pub index: usize,
+    pub rules: Vec<RuleMatch>,
+}
+
+// A single clause of a subrule checked against an object -- e.g. "doors >= 4" against ["3"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClauseTrace {
+    pub tag_name: TagName,
+    pub comparison_op: ComparisonOp,
+    pub tag_value: String,
+    // The object's values for tag_name, empty if it doesn't have the attribute
+    pub object_values: Vec<String>,
+    pub passed: bool,
+}
+
+// Every clause of a subrule checked against an object. The subrule matches if all pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubRuleTrace {
+    pub subrule_number: SubRuleNumber,
+    pub expected_count: i32,
+    pub actual_count: i32,
+    pub clauses: Vec<ClauseTrace>,
+}
+
+// Why a rule did or didn't match an object. The rule matches if any of its subrules match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleExplanation {
+    pub rule_number: RuleNumber,
+    pub subrules: Vec<SubRuleTrace>,
+}
+
+// A rule an object didn't match, with the subrule it came closest to -- e.g. 2 of 3 clauses
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearMiss {
+    pub rule_number: RuleNumber,
+    pub subrule_number: SubRuleNumber,
+    pub satisfied_count: i32,
+    pub expected_count: i32,
+    // Clauses of the subrule the object failed, in rule order
+    pub missing_clauses: Vec<ClauseTrace>,
+}
+
+// A rule in a .rules file -- e.g. one that references a tag being removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleReference {
+    pub file: String,
+    // 1-based
+    pub line: usize,
+    // As written in the file
+    pub rule: String,
+}
+
+// A line of a .tags, .rules or .yaml file rewritten by an edit to a tag -- e.g. a rule
+// referencing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineChange {
+    pub file: String,
+    // 1-based
+    pub line: usize,
+    // As written in the file before the edit
+    pub old_line: String,
+    // None if the line was removed -- e.g. a rule that could no longer match anything
+    pub new_line: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvaluationResult {
+    // One entry per evaluated object, in evaluation order
+    pub objects: Vec<ObjectMatch>,
 }
 
 // Impls
 
+impl NumericTag {
+    // Parses a value of this tag, checking it is the right kind of number and within bounds
+    pub fn parse_value(&self, value: &str) -> Result<f64, String> {
+        let number = match self.kind {
+            NumericKind::Integer => value
+                .parse::<i64>()
+                .map(|n| n as f64)
+                .map_err(|_| format!("'{}' is not an integer", value))?,
+            NumericKind::Decimal => value
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("'{}' is not a number", value))?,
+        };
+
+        if let Some(min) = self.min
+            && number < min
+        {
+            return Err(format!("'{}' is below the minimum of {}", value, min));
+        }
+        if let Some(max) = self.max
+            && number > max
+        {
+            return Err(format!("'{}' is above the maximum of {}", value, max));
+        }
+
+        Ok(number)
+    }
+}
+
+impl ComparisonOp {
+    // Operator as written in a rule -- e.g. GREQ -> ">=". NOIN has no operator of its own,
+    // and is written as a negated "in" -- e.g. "!(weight in [10,20])".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComparisonOp::ISEQ => "=",
+            ComparisonOp::NOEQ => "!",
+            ComparisonOp::GRTH => ">",
+            ComparisonOp::GREQ => ">=",
+            ComparisonOp::LETH => "<",
+            ComparisonOp::LEEQ => "<=",
+            ComparisonOp::ISIN => "in",
+            ComparisonOp::NOIN => "not in",
+        }
+    }
+}
+
+impl SubRuleTrace {
+    pub fn is_match(&self) -> bool {
+        self.actual_count == self.expected_count
+    }
+}
+
+impl RuleExplanation {
+    pub fn is_match(&self) -> bool {
+        self.subrules.iter().any(SubRuleTrace::is_match)
+    }
+}
+
+impl Rule {
+    // Priority used to pick between rules, 0 if the rule has none
+    pub fn priority_or_default(&self) -> i32 {
+        self.priority.unwrap_or(0)
+    }
+}
+
+impl Strategy {
+    // Strategy as written on the command line -- e.g. "first"
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strategy::FirstMatch => "first",
+            Strategy::AllMatches => "all",
+            Strategy::HighestPriority => "priority",
+            Strategy::Unique => "unique",
+        }
+    }
+
+    pub fn parse(strategy: &str) -> Option<Self> {
+        match strategy.trim().to_lowercase().as_str() {
+            "first" => Some(Strategy::FirstMatch),
+            "all" => Some(Strategy::AllMatches),
+            "priority" => Some(Strategy::HighestPriority),
+            "unique" => Some(Strategy::Unique),
+            _ => None,
+        }
+    }
+}
+
+impl Decision {
+    pub fn is_match(&self) -> bool {
+        !self.matches.is_empty()
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+
+    // Outcome of each chosen rule, in rule order
+    pub fn outcomes(&self) -> Vec<&Outcome> {
+        self.matches.iter().map(|m| &m.rule.outcome).collect()
+    }
+
+    // Outcome of the first chosen rule, or None if no rule matched
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.matches.first().map(|m| &m.rule.outcome)
+    }
+}
+
+impl ObjectMatch {
+    pub fn is_match(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub fn rule_numbers(&self) -> Vec<RuleNumber> {
+        self.rules.iter().map(|r| r.rule_number).collect()
+    }
+}
+
+impl EvaluationResult {
+    // Match for the object at `index` within `obj_type` -- e.g. ("shapes", 0)
+    pub fn get(&self, obj_type: &str, index: usize) -> Option<&ObjectMatch> {
+        self.objects
+            .iter()
+            .find(|m| m.obj_type == obj_type && m.index == index)
+    }
+}
+
+impl LineChange {
+    // Diff of the changes, with the old line and new line under the location of each
+    // E.g. "config/a.rules:3\n-- colour = grey\n+- colour = gray\n"
+    pub fn diff(changes: &[LineChange]) -> String {
+        let mut diff = String::new();
+        for change in changes {
+            diff.push_str(&format!(
+                "{}:{}\n-{}\n",
+                change.file, change.line, change.old_line
+            ));
+            if let Some(new_line) = &change.new_line {
+                diff.push_str(&format!("+{}\n", new_line));
+            }
+        }
+        diff
+    }
+}
+
+// "rules 2, 5 at priority 10"
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rule_numbers: Vec<String> = self.rule_numbers.iter().map(ToString::to_string).collect();
+        write!(
+            f,
+            "rules {} at priority {}",
+            rule_numbers.join(", "),
+            self.priority
+        )
+    }
+}
+
+impl std::fmt::Display for RuleReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
 impl Default for SubRule {
     fn default() -> Self {
         SubRule {
+            rule_number: 0,
             expected_count: 2,
             actual_count: 0,
             comparison_ops: Vec::new(),
-            tag_kvs: HashMap::new(),
+            tag_kvs: Vec::new(),
         }
     }
 }
@@ -0,0 +1,387 @@
+// Binary cache of a compiled ruleset -- the tag index, rule metadata and DNF subrules
+//
+// Layout, with integers in little-endian:
+// magic "RULC", version u32, source hash u64
+// tag count u32, then for each tag: name, value count u32, values
+// rule count u32, then for each rule: rule number i32, name, owner, description,
+//   priority, outcome count u32, then for each outcome: key, value, then file, line u32
+// subrule count u32, then for each subrule: rule number i32, expected count i32,
+//   clause count u32, then for each clause: comparison op u8, tag name, tag value
+// Strings are a u32 length in bytes followed by UTF-8. Optional values are a u8 flag,
+// followed by the value if the flag is 1.
+use crate::types::{ComparisonOp, Outcome, Rule, SubRule, TagName, TagValues};
+
+use std::collections::HashMap;
+
+const MAGIC: &[u8; 4] = b"RULC";
+
+// Bump whenever the layout or the meaning of cached data changes
+pub const CACHE_VERSION: u32 = 4;
+
+// Compiled tags, rules and subrules, with rules and subrules in number order
+pub type CachedRuleset = (HashMap<TagName, TagValues>, Vec<Rule>, Vec<SubRule>);
+
+// FNV-1a over the name and contents of every source file, so the hash is stable between
+// runs and builds, and adding, removing, renaming or editing a file changes it
+pub fn source_hash(files: &[(String, String)]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    };
+
+    for (name, content) in files {
+        // Lengths keep "ab" + "c" and "a" + "bc" apart
+        write(&(name.len() as u64).to_le_bytes());
+        write(name.as_bytes());
+        write(&(content.len() as u64).to_le_bytes());
+        write(content.as_bytes());
+    }
+
+    hash
+}
+
+fn op_to_byte(op: ComparisonOp) -> u8 {
+    match op {
+        ComparisonOp::ISEQ => 0,
+        ComparisonOp::NOEQ => 1,
+        ComparisonOp::GRTH => 2,
+        ComparisonOp::GREQ => 3,
+        ComparisonOp::LETH => 4,
+        ComparisonOp::LEEQ => 5,
+        ComparisonOp::ISIN => 6,
+        ComparisonOp::NOIN => 7,
+    }
+}
+
+fn op_from_byte(byte: u8) -> Option<ComparisonOp> {
+    match byte {
+        0 => Some(ComparisonOp::ISEQ),
+        1 => Some(ComparisonOp::NOEQ),
+        2 => Some(ComparisonOp::GRTH),
+        3 => Some(ComparisonOp::GREQ),
+        4 => Some(ComparisonOp::LETH),
+        5 => Some(ComparisonOp::LEEQ),
+        6 => Some(ComparisonOp::ISIN),
+        7 => Some(ComparisonOp::NOIN),
+        _ => None,
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, n: usize) {
+    bytes.extend((n as u32).to_le_bytes());
+}
+
+fn write_string(bytes: &mut Vec<u8>, s: &str) {
+    write_u32(bytes, s.len());
+    bytes.extend(s.as_bytes());
+}
+
+fn write_optional_string(bytes: &mut Vec<u8>, s: &Option<String>) {
+    match s {
+        Some(s) => {
+            bytes.push(1);
+            write_string(bytes, s);
+        }
+        None => bytes.push(0),
+    }
+}
+
+pub fn encode(
+    source_hash: u64,
+    tags: &HashMap<TagName, TagValues>,
+    rules: &[Rule],
+    subrules: &[&SubRule],
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(MAGIC);
+    bytes.extend(CACHE_VERSION.to_le_bytes());
+    bytes.extend(source_hash.to_le_bytes());
+
+    // Sorted, so the same ruleset always produces the same file
+    let mut names: Vec<&TagName> = tags.keys().collect();
+    names.sort();
+
+    write_u32(&mut bytes, names.len());
+    for name in names {
+        write_string(&mut bytes, name);
+        write_u32(&mut bytes, tags[name].len());
+        for value in &tags[name] {
+            write_string(&mut bytes, value);
+        }
+    }
+
+    write_u32(&mut bytes, rules.len());
+    for rule in rules {
+        bytes.extend(rule.rule_number.to_le_bytes());
+        write_optional_string(&mut bytes, &rule.name);
+        write_optional_string(&mut bytes, &rule.owner);
+        write_optional_string(&mut bytes, &rule.description);
+        match rule.priority {
+            Some(priority) => {
+                bytes.push(1);
+                bytes.extend(priority.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        write_u32(&mut bytes, rule.outcome.len());
+        for (key, value) in &rule.outcome {
+            write_string(&mut bytes, key);
+            write_string(&mut bytes, value);
+        }
+        write_string(&mut bytes, &rule.file);
+        write_u32(&mut bytes, rule.line);
+    }
+
+    write_u32(&mut bytes, subrules.len());
+    for subrule in subrules {
+        bytes.extend(subrule.rule_number.to_le_bytes());
+        bytes.extend(subrule.expected_count.to_le_bytes());
+        write_u32(&mut bytes, subrule.tag_kvs.len());
+        for (op, (tag_name, tag_value)) in subrule.comparison_ops.iter().zip(&subrule.tag_kvs) {
+            bytes.push(op_to_byte(*op));
+            write_string(&mut bytes, tag_name);
+            write_string(&mut bytes, tag_value);
+        }
+    }
+
+    bytes
+}
+
+struct Reader<'a> {
+    m_bytes: &'a [u8],
+    m_pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.m_pos.checked_add(len)?;
+        let bytes = self.m_bytes.get(self.m_pos..end)?;
+        self.m_pos = end;
+        Some(bytes)
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn read_i32(&mut self) -> Option<i32> {
+        self.take(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn read_string(&mut self) -> Option<String> {
+        let len = self.read_u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    // Value after a flag of 1, None after a flag of 0, or fails on any other flag
+    fn read_optional<T>(&mut self, read: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.read_u8()? {
+            0 => Some(None),
+            1 => read(self).map(Some),
+            _ => None,
+        }
+    }
+}
+
+// The ruleset in a cache file, or None if it is from another version, was compiled from
+// other sources, or is corrupt
+pub fn decode(bytes: &[u8], source_hash: u64) -> Option<CachedRuleset> {
+    let mut reader = Reader {
+        m_bytes: bytes,
+        m_pos: 0,
+    };
+
+    if reader.take(MAGIC.len())? != MAGIC
+        || reader.read_u32()? != CACHE_VERSION
+        || reader.read_u64()? != source_hash
+    {
+        return None;
+    }
+
+    let mut tags: HashMap<TagName, TagValues> = HashMap::new();
+    for _ in 0..reader.read_u32()? {
+        let name = reader.read_string()?;
+        let values = (0..reader.read_u32()?)
+            .map(|_| reader.read_string())
+            .collect::<Option<TagValues>>()?;
+        tags.insert(name, values);
+    }
+
+    let mut rules: Vec<Rule> = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        rules.push(Rule {
+            rule_number: reader.read_i32()?,
+            name: reader.read_optional(Reader::read_string)?,
+            owner: reader.read_optional(Reader::read_string)?,
+            description: reader.read_optional(Reader::read_string)?,
+            priority: reader.read_optional(Reader::read_i32)?,
+            outcome: (0..reader.read_u32()?)
+                .map(|_| Some((reader.read_string()?, reader.read_string()?)))
+                .collect::<Option<Outcome>>()?,
+            file: reader.read_string()?,
+            line: reader.read_u32()? as usize,
+        });
+    }
+
+    let mut subrules: Vec<SubRule> = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        let mut subrule = SubRule {
+            rule_number: reader.read_i32()?,
+            expected_count: reader.read_i32()?,
+            ..SubRule::default()
+        };
+
+        for _ in 0..reader.read_u32()? {
+            subrule
+                .comparison_ops
+                .push(op_from_byte(reader.read_u8()?)?);
+            subrule
+                .tag_kvs
+                .push((reader.read_string()?, reader.read_string()?));
+        }
+        subrules.push(subrule);
+    }
+
+    // Trailing bytes mean the file isn't what was written
+    (reader.m_pos == bytes.len()).then_some((tags, rules, subrules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ruleset() -> CachedRuleset {
+        let mut tags = HashMap::new();
+        tags.insert(
+            "colour".to_string(),
+            vec!["red".to_string(), "blue".to_string()],
+        );
+        tags.insert("doors".to_string(), vec!["integer(2..5)".to_string()]);
+
+        let rules = vec![
+            Rule {
+                rule_number: 0,
+                name: Some("red_not_blue".to_string()),
+                owner: Some("sales".to_string()),
+                description: None,
+                priority: Some(-5),
+                outcome: Outcome::from([
+                    ("discount".to_string(), "10".to_string()),
+                    ("tier".to_string(), "gold".to_string()),
+                ]),
+                file: "config/a.rules".to_string(),
+                line: 3,
+            },
+            Rule {
+                rule_number: 1,
+                file: "config/b.rules".to_string(),
+                line: 1,
+                ..Rule::default()
+            },
+        ];
+
+        let subrules = vec![
+            SubRule {
+                rule_number: 0,
+                expected_count: 2,
+                actual_count: 0,
+                comparison_ops: vec![ComparisonOp::ISEQ, ComparisonOp::NOEQ],
+                tag_kvs: vec![
+                    ("colour".to_string(), "red".to_string()),
+                    ("colour".to_string(), "blue".to_string()),
+                ],
+            },
+            SubRule {
+                rule_number: 1,
+                expected_count: 1,
+                actual_count: 0,
+                comparison_ops: vec![ComparisonOp::LEEQ],
+                tag_kvs: vec![("doors".to_string(), "4".to_string())],
+            },
+        ];
+
+        (tags, rules, subrules)
+    }
+
+    fn encode_ruleset(hash: u64) -> Vec<u8> {
+        let (tags, rules, subrules) = ruleset();
+        encode(hash, &tags, &rules, &subrules.iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let (tags, rules, subrules) = decode(&encode_ruleset(42), 42).unwrap();
+        let (expected_tags, expected_rules, expected_subrules) = ruleset();
+
+        assert_eq!(tags, expected_tags);
+        assert_eq!(rules, expected_rules);
+        assert_eq!(subrules.len(), expected_subrules.len());
+        for (subrule, expected) in subrules.iter().zip(&expected_subrules) {
+            assert_eq!(subrule.rule_number, expected.rule_number);
+            assert_eq!(subrule.expected_count, expected.expected_count);
+            assert_eq!(subrule.comparison_ops, expected.comparison_ops);
+            assert_eq!(subrule.tag_kvs, expected.tag_kvs);
+        }
+
+        // Deterministic, whatever the HashMap order
+        assert_eq!(encode_ruleset(42), encode_ruleset(42));
+    }
+
+    #[test]
+    fn test_cache_rejects_stale_or_corrupt_data() {
+        let bytes = encode_ruleset(42);
+
+        // Compiled from other sources
+        assert!(decode(&bytes, 43).is_none());
+
+        // Another version
+        let mut other_version = bytes.clone();
+        other_version[4..8].copy_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+        assert!(decode(&other_version, 42).is_none());
+
+        // Not a cache file
+        assert!(decode(b"- colour: red", 42).is_none());
+
+        // Truncated or with trailing bytes
+        assert!(decode(&bytes[..bytes.len() - 1], 42).is_none());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode(&trailing, 42).is_none());
+    }
+
+    #[test]
+    fn test_source_hash() {
+        let files = |files: &[(&str, &str)]| {
+            files
+                .iter()
+                .map(|(name, content)| (name.to_string(), content.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let hash = source_hash(&files(&[("a.rules", "- colour = red")]));
+        assert_eq!(hash, source_hash(&files(&[("a.rules", "- colour = red")])));
+        assert_ne!(hash, source_hash(&files(&[("a.rules", "- colour = blue")])));
+        assert_ne!(hash, source_hash(&files(&[("b.rules", "- colour = red")])));
+        assert_ne!(
+            source_hash(&files(&[("a", "bc")])),
+            source_hash(&files(&[("ab", "c")]))
+        );
+    }
+}
@@ -17,10 +17,20 @@ pub fn read_files_in_dir(pattern: &str) -> Result<Vec<String>, RulesError> {
     Ok(contents)
 }
 
+// Same as read_files_in_dir, but keeps the path of each file for error messages
+pub fn read_named_files_in_dir(pattern: &str) -> Result<Vec<(String, String)>, RulesError> {
+    let mut contents = Vec::new();
+
+    for entry in glob(pattern)? {
+        let path = entry?;
+        let file_content = fs::read_to_string(&path)?;
+        contents.push((path.display().to_string(), file_content));
+    }
+
+    Ok(contents)
+}
+
 pub fn line_blank_or_comment(line: &str) -> bool {
     let trimmed = line.trim();
-    if trimmed.is_empty() || trimmed.starts_with('#') {
-        return true;
-    }
-    return false;
+    trimmed.is_empty() || trimmed.starts_with('#')
 }
@@ -1,4 +1,4 @@
-use crate::err::RulesError;
+use crate::err::{RuleParseError, RulesError};
 
 pub trait StringUtils {
     fn at(&self, index: usize) -> Option<char>;
@@ -13,17 +13,24 @@ impl StringUtils for str {
 // Remove first char ('-') and trim
 pub fn normalise(string: &str) -> Result<String, RulesError> {
     if string.at(0) != Some('-') {
-        return Err(RulesError::RuleParseError(
-            "Rule is missing initial dash.".to_string(),
-        ));
+        return Err(RuleParseError::new("Rule is missing initial dash.").into());
     }
 
-    let dash_count = string.matches('-').count();
+    // Dashes directly before a digit are negative numbers -- e.g. "temp > -5"
+    let chars: Vec<char> = string.chars().collect();
+    let dash_count = chars
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| {
+            **c == '-' && (*i == 0 || !chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()))
+        })
+        .count();
     if dash_count > 1 {
-        return Err(RulesError::RuleParseError(format!(
+        return Err(RuleParseError::new(format!(
             "Rule should only contain 1 dash, found {}",
             dash_count
-        )));
+        ))
+        .into());
     }
 
     Ok(string
@@ -34,3 +41,118 @@ pub fn normalise(string: &str) -> Result<String, RulesError> {
         .trim()
         .to_string())
 }
+
+// Name of a named rule and the rest of the rule, from the text after its dash
+// E.g. " [free_shipping] colour = red" -> (Some("free_shipping"), " colour = red")
+pub fn split_rule_name(rule: &str) -> Result<(Option<&str>, &str), RulesError> {
+    let Some(rest) = rule.trim_start().strip_prefix('[') else {
+        return Ok((None, rule));
+    };
+
+    let Some((name, rest)) = rest.split_once(']') else {
+        return Err(RuleParseError::new("Rule name is missing its closing ']'").into());
+    };
+
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(RuleParseError::new(format!(
+            "Invalid rule name '{}', names can only contain letters, digits, '_', '-' and '.'",
+            name
+        ))
+        .into());
+    }
+
+    Ok((Some(name), rest))
+}
+
+// Separates a rule's conditions from the outcome it declares
+pub const OUTCOME_SEPARATOR: &str = "=>";
+
+// Conditions of a rule, and its outcome if it declares one
+// E.g. "- colour = red => tier: gold" -> ("- colour = red ", Some(" tier: gold"))
+pub fn split_rule_outcome(rule: &str) -> (&str, Option<&str>) {
+    match rule.split_once(OUTCOME_SEPARATOR) {
+        Some((conditions, outcome)) => (conditions, Some(outcome)),
+        None => (rule, None),
+    }
+}
+
+// Same as normalise, but also removes the name of a named rule and its outcome
+// E.g. "- [free_shipping] colour = red => discount: 10" -> "colour = red"
+pub fn normalise_rule(rule: &str) -> Result<String, RulesError> {
+    let (rule, _) = split_rule_outcome(rule);
+    let Some(after_dash) = rule.strip_prefix('-') else {
+        return normalise(rule);
+    };
+
+    let (name, rest) = split_rule_name(after_dash)?;
+    if name.is_none() {
+        return normalise(rule);
+    }
+
+    // Names may contain dashes, so only the rest of the rule is checked for them
+    normalise(&format!("-{}", rest))
+}
+
+// Number of single character insertions, deletions, substitutions or swaps of adjacent
+// characters to turn a into b -- e.g. 1 for "rde" -> "red"
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // distances[i][j] is the distance between the first i chars of a and first j chars of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+// Closest candidate to a misspelt word, if any is close enough to be a likely typo
+// E.g. "colur" -> "colour", "rde" -> "red"
+pub fn closest_match<'a>(
+    word: &str,
+    candidates: impl IntoIterator<Item = &'a String>,
+) -> Option<&'a str> {
+    // Allow roughly one edit for every three characters
+    let max_distance = (word.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(word, c), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        // Lowest distance, then alphabetical, so suggestions are deterministic
+        .min()
+        .map(|(_, c)| c.as_str())
+}
+
+// Suggestion to append to an error about a misspelt word -- e.g. ", did you mean 'colour'?"
+// Empty if no candidate is close enough
+pub fn did_you_mean<'a>(word: &str, candidates: impl IntoIterator<Item = &'a String>) -> String {
+    closest_match(word, candidates)
+        .map(|c| format!(", did you mean '{}'?", c))
+        .unwrap_or_default()
+}